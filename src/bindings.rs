@@ -0,0 +1,295 @@
+// Desktop input bindings, loaded from a TOML file:
+//
+//   [keys]
+//   X = "a"
+//   Z = "b"
+//   F5 = "save"
+//
+//   [gamepad]                       # optional, Linux evdev
+//   device = "/dev/input/event4"
+//   [gamepad.buttons]
+//   east = "a"
+//   start = "start"
+//
+//   [turbo]                         # frames pressed / released while auto-firing
+//   a = 3
+//
+//   [macros]
+//   walk-up = "up*16, a"
+//
+// Every binding maps a key (or gamepad button) to a Game Boy button, a hotkey,
+// "turbo-<button>" (auto-fire while held) or "macro:<name>" (play on press).
+// Keys left out of `[keys]` keep their default binding unless `replace = true`.
+
+use std::collections::BTreeMap;
+
+use minifb::Key;
+use serde::Deserialize;
+
+use crate::joypad::{Buttons, InputMacro};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hotkey {
+    Save,        // write the battery save
+    Load,        // reload the battery save from disk
+    Pause,
+    FastForward, // held
+    Screenshot,
+    Palette,     // cycle the DMG palette presets
+    SpeedUp,     // next fixed speed multiplier
+    SpeedDown,
+    FrameAdvance, // one frame while paused
+    Cheats,       // all cheats off / back on
+    ToggleBg,     // debug layer switches
+    ToggleWindow,
+    ToggleSprites,
+    HighlightSprites,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Button(Buttons),
+    Hotkey(Hotkey),
+    Turbo(Buttons),
+    Macro(usize), // index into `Bindings::macros`
+}
+
+const ACTIONS: [(&str, Action); 22] = [
+    ("up", Action::Button(Buttons::UP)),
+    ("down", Action::Button(Buttons::DOWN)),
+    ("left", Action::Button(Buttons::LEFT)),
+    ("right", Action::Button(Buttons::RIGHT)),
+    ("a", Action::Button(Buttons::A)),
+    ("b", Action::Button(Buttons::B)),
+    ("select", Action::Button(Buttons::SELECT)),
+    ("start", Action::Button(Buttons::START)),
+    ("save", Action::Hotkey(Hotkey::Save)),
+    ("load", Action::Hotkey(Hotkey::Load)),
+    ("pause", Action::Hotkey(Hotkey::Pause)),
+    ("fast-forward", Action::Hotkey(Hotkey::FastForward)),
+    ("screenshot", Action::Hotkey(Hotkey::Screenshot)),
+    ("palette", Action::Hotkey(Hotkey::Palette)),
+    ("speed-up", Action::Hotkey(Hotkey::SpeedUp)),
+    ("speed-down", Action::Hotkey(Hotkey::SpeedDown)),
+    ("frame-advance", Action::Hotkey(Hotkey::FrameAdvance)),
+    ("cheats", Action::Hotkey(Hotkey::Cheats)),
+    ("toggle-bg", Action::Hotkey(Hotkey::ToggleBg)),
+    ("toggle-window", Action::Hotkey(Hotkey::ToggleWindow)),
+    ("toggle-sprites", Action::Hotkey(Hotkey::ToggleSprites)),
+    ("highlight-sprites", Action::Hotkey(Hotkey::HighlightSprites)),
+];
+
+impl Action {
+    /// Buttons and hotkeys plus "turbo-<button>"; macros need `Bindings::action`
+    pub fn by_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase().replace('_', "-");
+        if let Some(button) = name.strip_prefix("turbo-") {
+            return Buttons::by_name(button).map(Action::Turbo);
+        }
+        ACTIONS.iter().find(|(n, _)| *n == name).map(|&(_, a)| a)
+    }
+}
+
+macro_rules! key_names {
+    ($($k:ident),* $(,)?) => { [$((stringify!($k), Key::$k)),*] };
+}
+
+const KEYS: [(&str, Key); 106] = key_names!(
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15,
+    Down, Left, Right, Up,
+    Apostrophe, Backquote, Backslash, Comma, Equal, LeftBracket, Minus, Period, RightBracket, Semicolon, Slash,
+    Backspace, Delete, End, Enter, Escape, Home, Insert, Menu, PageDown, PageUp, Pause, Space, Tab,
+    NumLock, CapsLock, ScrollLock, LeftShift, RightShift, LeftCtrl, RightCtrl,
+    NumPad0, NumPad1, NumPad2, NumPad3, NumPad4, NumPad5, NumPad6, NumPad7, NumPad8, NumPad9,
+    NumPadDot, NumPadSlash, NumPadAsterisk, NumPadMinus, NumPadPlus, NumPadEnter,
+    LeftAlt, RightAlt, LeftSuper, RightSuper,
+);
+
+/// minifb key by name, case-insensitive. Digits may be written as "1" or "Key1".
+pub fn key_by_name(name: &str) -> Option<Key> {
+    let name = if name.len() == 1 && name.as_bytes()[0].is_ascii_digit() { format!("Key{}", name) } else { name.to_string() };
+    KEYS.iter().find(|(n, _)| n.eq_ignore_ascii_case(&name)).map(|&(_, k)| k)
+}
+
+pub fn key_name(key: Key) -> &'static str {
+    KEYS.iter().find(|&&(_, k)| k == key).map_or("?", |&(n, _)| n)
+}
+
+// evdev key codes for the usual gamepad buttons (linux/input-event-codes.h)
+const PAD_BUTTONS: [(&str, u16); 16] = [
+    ("south", 0x130), ("east", 0x131), ("north", 0x133), ("west", 0x134),
+    ("tl", 0x136), ("tr", 0x137), ("tl2", 0x138), ("tr2", 0x139),
+    ("select", 0x13A), ("start", 0x13B), ("mode", 0x13C), ("thumbl", 0x13D),
+    ("dpad_up", 0x220), ("dpad_down", 0x221), ("dpad_left", 0x222), ("dpad_right", 0x223),
+];
+
+/// evdev code by name ("east", "start") or as a plain number ("305")
+pub fn pad_button_by_name(name: &str) -> Option<u16> {
+    name.parse().ok().or_else(|| {
+        let name = name.to_ascii_lowercase().replace('-', "_");
+        PAD_BUTTONS.iter().find(|(n, _)| *n == name).map(|&(_, c)| c)
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct GamepadConfig {
+    pub device: String,
+    pub buttons: Vec<(u16, Action)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    pub keys: Vec<(Key, Action)>,
+    pub gamepad: Option<GamepadConfig>,
+    pub turbo: Vec<(Buttons, u32)>,
+    pub macros: Vec<(String, InputMacro)>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    replace: bool,
+    #[serde(default)]
+    keys: BTreeMap<String, String>,
+    gamepad: Option<GamepadFile>,
+    #[serde(default)]
+    turbo: BTreeMap<String, u32>,
+    #[serde(default)]
+    macros: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GamepadFile {
+    device: String,
+    #[serde(default)]
+    replace: bool,
+    #[serde(default)]
+    buttons: BTreeMap<String, String>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            (Key::Up, "up"), (Key::Down, "down"), (Key::Left, "left"), (Key::Right, "right"),
+            (Key::X, "a"), (Key::Z, "b"), (Key::Backspace, "select"), (Key::Enter, "start"),
+            (Key::F5, "save"), (Key::F9, "load"), (Key::Space, "pause"), (Key::Tab, "fast-forward"),
+            (Key::F12, "screenshot"), (Key::P, "palette"),
+            (Key::Equal, "speed-up"), (Key::Minus, "speed-down"), (Key::N, "frame-advance"),
+            (Key::C, "cheats"),
+            (Key::Key1, "toggle-bg"), (Key::Key2, "toggle-window"), (Key::Key3, "toggle-sprites"), (Key::Key4, "highlight-sprites"),
+        ];
+        Self {
+            keys: keys.iter().map(|&(k, a)| (k, Action::by_name(a).unwrap())).collect(),
+            gamepad: None,
+            turbo: Vec::new(),
+            macros: Vec::new(),
+        }
+    }
+}
+
+fn default_pad_buttons() -> Vec<(u16, Action)> {
+    let map = [
+        ("east", "a"), ("south", "b"), ("select", "select"), ("start", "start"),
+        ("dpad_up", "up"), ("dpad_down", "down"), ("dpad_left", "left"), ("dpad_right", "right"),
+        ("tr", "fast-forward"),
+    ];
+    map.iter().map(|&(b, a)| (pad_button_by_name(b).unwrap(), Action::by_name(a).unwrap())).collect()
+}
+
+impl Bindings {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: File = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut bindings = Self::default();
+        if file.replace { bindings.keys.clear(); }
+
+        for (name, frames) in &file.turbo {
+            let button = Buttons::by_name(name).ok_or_else(|| format!("[turbo] unknown button \"{}\"", name))?;
+            if *frames == 0 { return Err(format!("[turbo] {} = 0: the period must be at least one frame", name)); }
+            bindings.turbo.push((button, *frames));
+        }
+        for (name, steps) in &file.macros {
+            let m = InputMacro::parse(steps).map_err(|e| format!("[macros] {}: {}", name, e))?;
+            bindings.macros.push((name.clone(), m));
+        }
+
+        for (name, act) in &file.keys {
+            let key = key_by_name(name).ok_or_else(|| {
+                format!("[keys] unknown key \"{}\" (key names look like A, Key1, F5, Enter, Left, Space, NumPad0)", name)
+            })?;
+            let act = bindings.action(act, "keys", name)?;
+            bindings.keys.retain(|&(k, _)| k != key);
+            bindings.keys.push((key, act));
+        }
+
+        if let Some(pad) = file.gamepad {
+            let mut buttons = if pad.replace { Vec::new() } else { default_pad_buttons() };
+            for (name, act) in &pad.buttons {
+                let code = pad_button_by_name(name).ok_or_else(|| {
+                    let valid: Vec<&str> = PAD_BUTTONS.iter().map(|(n, _)| *n).collect();
+                    format!("[gamepad.buttons] unknown button \"{}\", expected an evdev code or one of {}", name, valid.join(", "))
+                })?;
+                let act = bindings.action(act, "gamepad.buttons", name)?;
+                buttons.retain(|&(c, _)| c != code);
+                buttons.push((code, act));
+            }
+            bindings.gamepad = Some(GamepadConfig { device: pad.device, buttons });
+        }
+        Ok(bindings)
+    }
+
+    /// Looks up an action by name, including "macro:<name>" for this file's macros
+    fn action(&self, name: &str, section: &str, what: &str) -> Result<Action, String> {
+        if let Some(m) = name.strip_prefix("macro:") {
+            return self.macros.iter().position(|(n, _)| n == m).map(Action::Macro).ok_or_else(|| {
+                format!("[{}] {} = \"{}\": no macro named \"{}\" in [macros]", section, what, name, m)
+            });
+        }
+        Action::by_name(name).ok_or_else(|| {
+            let valid: Vec<&str> = ACTIONS.iter().map(|(n, _)| *n).collect();
+            format!(
+                "[{}] {} = \"{}\": unknown action, expected one of {}, turbo-<button> or macro:<name>",
+                section, what, name, valid.join(", ")
+            )
+        })
+    }
+
+    /// Keys bound to a hotkey
+    pub fn hotkey_keys(&self, hotkey: Hotkey) -> impl Iterator<Item = Key> + '_ {
+        self.keys.iter().filter(move |&&(_, a)| a == Action::Hotkey(hotkey)).map(|&(k, _)| k)
+    }
+
+    /// Game Boy buttons held according to `is_down`
+    pub fn buttons(&self, is_down: impl Fn(Key) -> bool) -> Buttons {
+        let mut pressed = Buttons::empty();
+        for &(key, act) in &self.keys {
+            if let Action::Button(b) = act && is_down(key) { pressed |= b; }
+        }
+        pressed
+    }
+
+    /// Buttons whose turbo key is held according to `is_down`
+    pub fn turbo(&self, is_down: impl Fn(Key) -> bool) -> Buttons {
+        let mut turbo = Buttons::empty();
+        for &(key, act) in &self.keys {
+            if let Action::Turbo(b) = act && is_down(key) { turbo |= b; }
+        }
+        turbo
+    }
+
+    /// Macros whose key went down according to `is_pressed`
+    pub fn triggered_macros(&self, is_pressed: impl Fn(Key) -> bool) -> Vec<usize> {
+        self.keys.iter().filter_map(|&(key, act)| match act {
+            Action::Macro(i) if is_pressed(key) => Some(i),
+            _ => None,
+        }).collect()
+    }
+}
@@ -0,0 +1,104 @@
+// Power-on state: either a real boot ROM mapped over 0x0000-0x00FF, or the
+// register values the boot ROM leaves behind (Pan Docs, "Power Up Sequence").
+
+use crate::model::Model;
+
+pub const DMG_BOOT_LEN: usize = 0x100;
+pub const CGB_BOOT_LEN: usize = 0x900; // 0x0000-0x00FF and 0x0200-0x08FF; the header shows through in between
+
+/// Checks a boot ROM image against the model it is meant to run on.
+pub fn validate(model: Model, boot: &[u8]) -> Result<(), String> {
+    let expected = if model.is_cgb() { CGB_BOOT_LEN } else { DMG_BOOT_LEN };
+    if boot.len() != expected {
+        return Err(format!(
+            "{:?} boot ROM must be {} bytes, got {}", model, expected, boot.len()
+        ));
+    }
+    Ok(())
+}
+
+/// True if `addr` is served by the boot ROM while it is mapped
+pub fn maps(boot: &[u8], addr: u16) -> bool {
+    addr < 0x100 || (boot.len() == CGB_BOOT_LEN && (0x200..0x900).contains(&addr))
+}
+
+// I/O registers common to every model after boot. Registers the boot ROM
+// never touches read back as 0xFF.
+const COMMON_IO: [(u16, u8); 28] = [
+    (0xFF01, 0x00), // SB
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF: VBlank already pending
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF40, 0x91), // LCDC
+    (0xFF47, 0xFC), // BGP
+    (0xFF4A, 0x00), // WY
+];
+
+/// Full post-boot I/O state for `model`, as (address, value) pairs.
+/// Values Pan Docs lists as timing-dependent ("??") use what SameBoy settles on.
+pub fn post_boot_io(model: Model) -> Vec<(u16, u8)> {
+    let mut io: Vec<(u16, u8)> = (0..0x80).map(|i| (0xFF00 + i, 0xFF)).collect();
+    let mut set = |addr: u16, val: u8| io[(addr - 0xFF00) as usize].1 = val;
+
+    COMMON_IO.iter().for_each(|&(a, v)| set(a, v));
+    set(0xFF42, 0x00); // SCY
+    set(0xFF43, 0x00); // SCX
+    set(0xFF44, 0x00); // LY
+    set(0xFF45, 0x00); // LYC
+    set(0xFF4B, 0x00); // WX
+
+    match model {
+        Model::Dmg | Model::Mgb => {
+            set(0xFF00, 0xCF); // P1
+            set(0xFF02, 0x7E); // SC
+            set(0xFF04, 0xAB); // DIV
+            set(0xFF26, 0xF1); // NR52
+            set(0xFF41, 0x85); // STAT
+            set(0xFF46, 0xFF); // DMA
+        }
+        Model::Sgb => {
+            set(0xFF00, 0xCF);
+            set(0xFF02, 0x7E);
+            set(0xFF04, 0x00);
+            set(0xFF26, 0xF0); // SGB boot ROM leaves channel 1 off
+            set(0xFF41, 0x85);
+            set(0xFF46, 0xFF);
+        }
+        Model::Cgb => {
+            set(0xFF00, 0xCF);
+            set(0xFF02, 0x7F); // SC: the clock speed bit reads back on CGB
+            set(0xFF04, 0x00);
+            set(0xFF26, 0xF1);
+            set(0xFF41, 0x85);
+            set(0xFF46, 0x00);
+            set(0xFF4D, 0x7E); // KEY1
+            set(0xFF4F, 0xFE); // VBK
+            set(0xFF56, 0x3E); // RP
+            set(0xFF68, 0xC0); // BCPS
+            set(0xFF6A, 0xC1); // OCPS
+            set(0xFF70, 0xF8); // SVBK
+        }
+    }
+    io
+}
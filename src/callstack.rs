@@ -0,0 +1,79 @@
+// Shadow call stack, rebuilt by watching every instruction the debugger runs.
+//
+// CALL, RST and interrupt dispatch push a frame once the CPU has pushed its
+// return address. A frame is popped as soon as SP moves above the slot its
+// return address lives in, however that happens: RET, RETI, a POP that
+// throws the address away, or a new stack pointer. Games that "return" into
+// a jump table with PUSH + RET therefore never pop a frame by mistake.
+
+use crate::gameboy::GameBoy;
+
+const VECTORS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub entry: u16,  // first instruction of the callee
+    pub bank: usize, // bank mapped at `entry` when it was called
+    pub ret: u16,    // where execution resumes in the caller
+    pub ret_sp: u16, // stack slot holding `ret`
+}
+
+/// How the stack changed over one instruction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Change {
+    pub popped: usize,
+    pub pushed: Option<Frame>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Innermost call last
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Updates the stack after one `GameBoy::step`, given PC, SP and the
+    /// opcode at PC from just before it
+    pub fn observe(&mut self, gb: &GameBoy, pc0: u16, sp0: u16, op: u8) -> Change {
+        let (pc, sp) = (gb.cpu.regs.pc, gb.cpu.regs.sp);
+        let mut change = Change::default();
+        while self.frames.last().is_some_and(|top| sp > top.ret_sp) {
+            self.frames.pop();
+            change.popped += 1;
+        }
+        if sp != sp0.wrapping_sub(2) { return change; }
+
+        let ret = u16::from_le_bytes([gb.mmu.peek(sp), gb.mmu.peek(sp.wrapping_add(1))]);
+        let target = u16::from_le_bytes([gb.mmu.peek(pc0.wrapping_add(1)), gb.mmu.peek(pc0.wrapping_add(2))]);
+        let kind = match op {
+            0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC if ret == pc0.wrapping_add(3) && pc == target => Some(FrameKind::Call),
+            op if op & 0xC7 == 0xC7 && ret == pc0.wrapping_add(1) && pc == (op & 0x38) as u16 => Some(FrameKind::Rst),
+            // Dispatch pushes the PC of the instruction it pre-empted
+            _ if ret == pc0 && VECTORS.contains(&pc) => Some(FrameKind::Interrupt),
+            _ => None,
+        };
+        change.pushed = kind.map(|kind| Frame { kind, entry: pc, bank: gb.mmu.bank_at(pc), ret, ret_sp: sp });
+        if let Some(frame) = change.pushed { self.frames.push(frame); }
+        change
+    }
+}
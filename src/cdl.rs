@@ -0,0 +1,290 @@
+// Code/data logger: one flag byte per ROM byte saying how the game used it.
+//
+// The bus feeds it every ROM access. CPU fetches are code: the first byte of
+// an instruction is an opcode, the rest are operands, and an opcode that
+// doesn't follow the previous instruction is a jump target. Everything else
+// that reads ROM (LD, ALU (HL), POP, OAM DMA, HDMA) is data, indirect unless
+// the address came from an LD A,(nn) operand.
+//
+// Files are FCEUX-style by default: the flag bytes alone, as long as the ROM,
+// with FCEUX's meaning for bits 0, 1, 4 and 5. BizHawk's container
+// ("BIZHAWK-CDL-2", subtype GB) is read and written too. Its ROM block uses
+// Gambatte's ExecFirst / ExecOperand / Data bits, so only those survive. It
+// also has HRAM, WRAM and CartRAM blocks, which BizHawk expects to find. RAM
+// use isn't logged here: those blocks are saved as they were loaded, or
+// zero-filled.
+
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+
+use crate::disasm;
+
+pub const CODE: u8 = 0x01;          // executed, opcode or operand
+pub const DATA: u8 = 0x02;          // read as data
+pub const BANKED: u8 = 0x04;        // accessed through the 4000-7FFF window
+pub const INDIRECT_CODE: u8 = 0x10; // reached by JP HL
+pub const INDIRECT_DATA: u8 = 0x20; // read through a register pointer
+pub const JUMP_TARGET: u8 = 0x40;   // an instruction reached by a jump, call, return or interrupt
+pub const OPCODE: u8 = 0x80;        // first byte of an instruction
+
+const BIZHAWK_MAGIC: &str = "BIZHAWK-CDL-2";
+// Gambatte's flags in BizHawk logs
+const EXEC_FIRST: u8 = 0x01;
+const EXEC_OPERAND: u8 = 0x02;
+const BIZ_DATA: u8 = 0x04;
+// Sizes of BizHawk's Gambatte memory domains
+const HRAM_LEN: usize = 0x80; // FF80-FFFF
+const WRAM_LEN: usize = 0x8000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Fceux,
+    BizHawk,
+}
+
+/// Bytes of one 16KB bank by how they were used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BankCoverage {
+    pub bank: usize,
+    pub code: usize,
+    pub data: usize, // read as data and never executed
+    pub unused: usize,
+}
+
+impl BankCoverage {
+    pub fn percent(&self) -> f64 {
+        100.0 * (0x4000 - self.unused) as f64 / 0x4000 as f64
+    }
+}
+
+/// The log, installed in `Mmu::cdl`
+#[derive(Debug)]
+pub struct Cdl {
+    flags: RefCell<Vec<u8>>,
+    next_pc: Cell<u16>,  // where the instruction after the last one fetched starts
+    operands: Cell<u16>, // operand bytes of that instruction still to fetch
+    last_op: Cell<u8>,
+    ram: Vec<(String, Vec<u8>)>, // BizHawk's RAM blocks by name, in file order
+    pub format: Format, // what `save` writes
+}
+
+impl Cdl {
+    /// An empty log for a ROM of `rom_len` bytes with `cart_ram_len` bytes of cartridge RAM
+    pub fn new(rom_len: usize, cart_ram_len: usize) -> Self {
+        let mut cdl = Self::from_flags(vec![0; rom_len]);
+        if cart_ram_len > 0 { cdl.ram.push(("CartRAM".into(), vec![0; cart_ram_len])); }
+        cdl
+    }
+
+    fn from_flags(flags: Vec<u8>) -> Self {
+        Self {
+            flags: RefCell::new(flags), next_pc: Cell::new(0), operands: Cell::new(0), last_op: Cell::new(0),
+            ram: vec![("HRAM".into(), vec![0; HRAM_LEN]), ("WRAM".into(), vec![0; WRAM_LEN])],
+            format: Format::Fceux,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.flags.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Flags of the byte at `offset` in the ROM file
+    pub fn flags(&self, offset: usize) -> u8 {
+        self.flags.borrow().get(offset).copied().unwrap_or(0)
+    }
+
+    // --- Bus hooks ---
+
+    /// A CPU fetch from `addr`, which maps the ROM byte at `offset` if any.
+    /// Fetches outside ROM only keep track of where instructions start.
+    pub fn note_fetch(&self, offset: Option<usize>, addr: u16, val: u8) {
+        let mut f = CODE | window(addr);
+        if self.operands.get() > 0 && addr == self.next_pc.get().wrapping_sub(self.operands.get()) {
+            self.operands.set(self.operands.get() - 1);
+        } else {
+            f |= OPCODE;
+            if addr != self.next_pc.get() {
+                f |= JUMP_TARGET;
+                if self.last_op.get() == 0xE9 { f |= INDIRECT_CODE; }
+            }
+            let len = disasm::length(val);
+            self.next_pc.set(addr.wrapping_add(len));
+            self.operands.set(len - 1);
+            self.last_op.set(val);
+        }
+        if let Some(offset) = offset { self.mark(offset, f); }
+    }
+
+    /// Any other read of the ROM byte at `offset`, mapped at `addr`
+    pub fn note_read(&self, offset: usize, addr: u16) {
+        let direct = self.last_op.get() == 0xFA && self.operands.get() == 0;
+        self.mark(offset, DATA | window(addr) | if direct { 0 } else { INDIRECT_DATA });
+    }
+
+    fn mark(&self, offset: usize, f: u8) {
+        if let Some(b) = self.flags.borrow_mut().get_mut(offset) { *b |= f; }
+    }
+
+    // --- Sessions ---
+
+    /// Adds every flag from `other`, a log of the same ROM
+    pub fn merge(&mut self, other: &Cdl) -> Result<(), String> {
+        if other.len() != self.len() {
+            return Err(format!("log covers {} bytes, ROM has {}", other.len(), self.len()));
+        }
+        for (name, theirs) in &other.ram {
+            let Some((_, ours)) = self.ram.iter_mut().find(|(n, _)| n == name) else { continue };
+            if ours.len() != theirs.len() {
+                return Err(format!("{name} block covers {} bytes, expected {}", theirs.len(), ours.len()));
+            }
+            for (a, b) in ours.iter_mut().zip(theirs) { *a |= b; }
+        }
+        for (a, b) in self.flags.get_mut().iter_mut().zip(other.flags.borrow().iter()) { *a |= b; }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.get_mut().fill(0);
+    }
+
+    pub fn coverage(&self) -> Vec<BankCoverage> {
+        self.flags.borrow().chunks(0x4000).enumerate().map(|(bank, bytes)| {
+            let mut c = BankCoverage { bank, unused: 0x4000 - bytes.len(), ..Default::default() };
+            for &f in bytes {
+                if f & CODE != 0 { c.code += 1 } else if f & DATA != 0 { c.data += 1 } else { c.unused += 1 }
+            }
+            c
+        }).collect()
+    }
+
+    /// One line per bank that was touched, then the totals
+    pub fn summary(&self) -> String {
+        let banks = self.coverage();
+        let mut out = format!("{:>4} {:>6} {:>6} {:>6} {:>7}\n", "bank", "code", "data", "unused", "covered");
+        for c in banks.iter().filter(|c| c.unused < 0x4000) {
+            writeln!(out, "{:>4X} {:>6} {:>6} {:>6} {:>6.1}%", c.bank, c.code, c.data, c.unused, c.percent()).unwrap();
+        }
+        let (code, data): (usize, usize) = banks.iter().fold((0, 0), |(c, d), b| (c + b.code, d + b.data));
+        let total = self.len().max(1);
+        writeln!(out, "{} banks: {} code, {} data, {:.1}% of the ROM seen", banks.len(), code, data, 100.0 * (code + data) as f64 / total as f64).unwrap();
+        out
+    }
+
+    // --- Files ---
+
+    pub fn encode(&self, format: Format) -> Vec<u8> {
+        let flags = self.flags.borrow();
+        match format {
+            Format::Fceux => flags.clone(),
+            Format::BizHawk => {
+                let rom: Vec<u8> = flags.iter().map(|&f| to_bizhawk(f)).collect();
+                let mut out = Vec::new();
+                dotnet_string(&mut out, BIZHAWK_MAGIC);
+                dotnet_string(&mut out, &format!("{:<15}", "GB"));
+                out.extend_from_slice(&(1 + self.ram.len() as i32).to_le_bytes());
+                for (name, block) in std::iter::once(("ROM", &rom)).chain(self.ram.iter().map(|(n, b)| (n.as_str(), b))) {
+                    dotnet_string(&mut out, name);
+                    out.extend_from_slice(&(block.len() as i32).to_le_bytes());
+                    out.extend_from_slice(block);
+                }
+                out
+            }
+        }
+    }
+
+    /// Reads either format, telling them apart by BizHawk's header
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.string().as_deref() != Some(BIZHAWK_MAGIC) {
+            return Ok(Self::from_flags(bytes.to_vec()));
+        }
+        r.string().ok_or("truncated BizHawk log")?;
+        let blocks = r.i32().ok_or("truncated BizHawk log")?;
+        let (mut rom, mut ram) = (None, Vec::new());
+        for _ in 0..blocks {
+            let name = r.string().ok_or("truncated BizHawk log")?;
+            let len = r.i32().ok_or("truncated BizHawk log")? as usize;
+            let block = r.take(len).ok_or("truncated BizHawk log")?;
+            match name.as_str() {
+                "ROM" => rom = Some(block.iter().map(|&f| from_bizhawk(f)).collect()),
+                _ => ram.push((name, block.to_vec())),
+            }
+        }
+        let mut cdl = Self::from_flags(rom.ok_or("BizHawk log has no ROM block")?);
+        cdl.ram = ram;
+        cdl.format = Format::BizHawk;
+        Ok(cdl)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::decode(&bytes).map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.encode(self.format)).map_err(|e| format!("{path}: {e}"))
+    }
+}
+
+fn window(addr: u16) -> u8 {
+    if addr >= 0x4000 { BANKED } else { 0 }
+}
+
+fn to_bizhawk(f: u8) -> u8 {
+    let mut b = 0;
+    if f & OPCODE != 0 { b |= EXEC_FIRST } else if f & CODE != 0 { b |= EXEC_OPERAND }
+    if f & DATA != 0 { b |= BIZ_DATA }
+    b
+}
+
+fn from_bizhawk(b: u8) -> u8 {
+    let mut f = 0;
+    if b & EXEC_FIRST != 0 { f |= CODE | OPCODE }
+    if b & EXEC_OPERAND != 0 { f |= CODE }
+    if b & BIZ_DATA != 0 { f |= DATA }
+    f
+}
+
+// .NET BinaryWriter strings: 7-bit varint length, then UTF-8
+fn dotnet_string(out: &mut Vec<u8>, s: &str) {
+    let mut n = s.len();
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let s = self.bytes.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(s)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let (mut n, mut shift) = (0usize, 0);
+        loop {
+            let b = *self.take(1)?.first()?;
+            n |= ((b & 0x7F) as usize) << shift;
+            if b & 0x80 == 0 { break; }
+            shift += 7;
+            if shift > 28 { return None; }
+        }
+        String::from_utf8(self.take(n)?.to_vec()).ok()
+    }
+}
@@ -0,0 +1,78 @@
+// CGB-only hardware: colour palette RAM and VRAM DMA (HDMA).
+
+// BCPS/BCPD (FF68/FF69) and OCPS/OCPD (FF6A/FF6B): 8 palettes x 4 colours x BGR555
+#[derive(Clone)]
+pub struct ColorPalettes {
+    data: [u8; 64],
+    index: u8, // bits 0-5: byte index, bit 7: auto-increment
+}
+
+impl Default for ColorPalettes {
+    fn default() -> Self { Self::new() }
+}
+
+impl ColorPalettes {
+    pub fn new() -> Self {
+        // Palette RAM powers up white
+        Self { data: [0xFF; 64], index: 0 }
+    }
+
+    pub fn read_index(&self) -> u8 { self.index | 0x40 }
+    pub fn write_index(&mut self, val: u8) { self.index = val & 0xBF; }
+
+    pub fn read_data(&self) -> u8 { self.data[(self.index & 0x3F) as usize] }
+
+    pub fn write_data(&mut self, val: u8) {
+        self.data[(self.index & 0x3F) as usize] = val;
+        if self.index & 0x80 != 0 {
+            self.index = 0x80 | (self.index.wrapping_add(1) & 0x3F);
+        }
+    }
+
+    /// Raw BGR555 value of `color` (0-3) in `palette` (0-7)
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let i = ((palette & 0x07) * 8 + (color & 0x03) * 2) as usize;
+        u16::from_le_bytes([self.data[i], self.data[i + 1]]) & 0x7FFF
+    }
+}
+
+/// Expands BGR555 to 8-bit RGB
+pub fn bgr555_to_rgb(c: u16) -> [u8; 3] {
+    let expand = |v: u16| ((v << 3) | (v >> 2)) as u8;
+    [expand(c & 0x1F), expand((c >> 5) & 0x1F), expand((c >> 10) & 0x1F)]
+}
+
+// HDMA1-5 (FF51-FF55): general-purpose or per-HBlank copies into VRAM
+#[derive(Clone, Debug, Default)]
+pub struct Hdma {
+    pub src: u16,
+    pub dst: u16,      // offset into the VRAM bank, 0x0000-0x1FF0
+    pub blocks: u8,    // 16-byte blocks left
+    pub hblank: bool,  // HBlank transfer in progress
+}
+
+impl Hdma {
+    pub fn write_src_hi(&mut self, v: u8) { self.src = (self.src & 0x00F0) | (v as u16) << 8; }
+    pub fn write_src_lo(&mut self, v: u8) { self.src = (self.src & 0xFF00) | (v & 0xF0) as u16; }
+    pub fn write_dst_hi(&mut self, v: u8) { self.dst = (self.dst & 0x00F0) | ((v & 0x1F) as u16) << 8; }
+    pub fn write_dst_lo(&mut self, v: u8) { self.dst = (self.dst & 0x1F00) | (v & 0xF0) as u16; }
+
+    /// FF55 read: blocks left minus one, bit 7 set once nothing is running
+    pub fn status(&self) -> u8 {
+        if self.hblank { self.blocks.wrapping_sub(1) & 0x7F } else { 0xFF }
+    }
+
+    /// Returns the next (source, VRAM offset) block and advances the pointers.
+    pub fn next_block(&mut self) -> Option<(u16, u16)> {
+        if self.blocks == 0 {
+            self.hblank = false;
+            return None;
+        }
+        let block = (self.src, self.dst);
+        self.src = self.src.wrapping_add(0x10);
+        self.dst = (self.dst + 0x10) & 0x1FFF;
+        self.blocks -= 1;
+        if self.blocks == 0 { self.hblank = false; }
+        Some(block)
+    }
+}
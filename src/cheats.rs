@@ -0,0 +1,206 @@
+// Cheat codes.
+//
+// GameShark codes (`01VVLLHH`: value VV written to HHLL) are RAM writes
+// repeated on every VBlank; type 9X writes into WRAM bank X on the CGB.
+// Game Genie codes (`ABC-DEF` or `ABC-DEF-GHI`) patch what the CPU reads from
+// ROM: AB is the new byte, FCDE ^ F000 the address and, with the third group,
+// ror(GI, 2) ^ BA the byte that must be there for the patch to apply, so a
+// code only hits the bank it was made for.
+//
+// The database is a text file with a section per game, keyed by the header
+// checksum (0x14D) and global checksum (0x14E-0x14F):
+//
+//     [91E6A0] POKEMON RED
+//     + 010138CD Walk through walls
+//     - 0A4-BDF-E6E Disabled code
+//
+// Lines start with + for an enabled code and - for a disabled one, and # starts a comment.
+
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    GameShark { bank: Option<usize>, addr: u16, value: u8 }, // bank: WRAM bank for D000-DFFF
+    GameGenie { addr: u16, value: u8, compare: Option<u8> },
+}
+
+impl Code {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let digits: Vec<u8> = text.chars().filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8).ok_or(format!("'{text}' is not a cheat code")))
+            .collect::<Result<_, _>>()?;
+        let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+        match digits.len() {
+            8 if !text.contains('-') => {
+                let addr = u16::from_le_bytes([byte(4), byte(6)]);
+                let bank = match byte(0) {
+                    0x00 | 0x01 => None,
+                    t @ 0x90..=0x97 => Some((t as usize & 7).max(1)),
+                    t => return Err(format!("unsupported GameShark code type {t:02X} in '{text}'")),
+                };
+                Ok(Code::GameShark { bank, addr, value: byte(2) })
+            }
+            6 | 9 => {
+                let d = |i: usize| digits[i] as u16;
+                let addr = (d(5) << 12 | d(2) << 8 | d(3) << 4 | d(4)) ^ 0xF000;
+                if addr >= 0x8000 { return Err(format!("Game Genie code '{text}' points outside ROM")); }
+                let compare = (digits.len() == 9).then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Code::GameGenie { addr, value: byte(0), compare })
+            }
+            _ => Err(format!("'{text}' is neither a GameShark (01VVLLHH) nor a Game Genie (ABC-DEF-GHI) code")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub code: Code,
+    pub text: String, // as entered
+    pub name: String,
+    pub enabled: bool,
+}
+
+/// The cheats for the running game, installed in `Mmu::cheats`
+#[derive(Clone, Debug)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    active: bool, // master switch
+    patches: Vec<(u16, u8, Option<u8>)>, // enabled Game Genie codes, rebuilt on every change
+}
+
+impl Default for Cheats {
+    fn default() -> Self { Self::new() }
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self { cheats: Vec::new(), active: true, patches: Vec::new() }
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    /// Adds an enabled code and returns its index
+    pub fn add(&mut self, text: &str, name: &str) -> Result<usize, String> {
+        let code = Code::parse(text.trim())?;
+        self.cheats.push(Cheat { code, text: text.trim().to_ascii_uppercase(), name: name.trim().to_string(), enabled: true });
+        self.rebuild();
+        Ok(self.cheats.len() - 1)
+    }
+
+    pub fn remove(&mut self, i: usize) -> Result<Cheat, String> {
+        if i >= self.cheats.len() { return Err(format!("no cheat {i}")); }
+        let cheat = self.cheats.remove(i);
+        self.rebuild();
+        Ok(cheat)
+    }
+
+    pub fn set_enabled(&mut self, i: usize, enabled: bool) -> Result<(), String> {
+        self.cheats.get_mut(i).ok_or(format!("no cheat {i}"))?.enabled = enabled;
+        self.rebuild();
+        Ok(())
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Turns every cheat off or back on without touching their own switches
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        self.rebuild();
+    }
+
+    fn rebuild(&mut self) {
+        self.patches = self.enabled().filter_map(|c| match c.code {
+            Code::GameGenie { addr, value, compare } => Some((addr, value, compare)),
+            Code::GameShark { .. } => None,
+        }).collect();
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter().filter(move |c| self.active && c.enabled)
+    }
+
+    pub fn patches_rom(&self) -> bool {
+        !self.patches.is_empty()
+    }
+
+    /// The byte the CPU sees at ROM address `addr` where the cartridge has `val`
+    pub fn patch(&self, addr: u16, val: u8) -> u8 {
+        self.patches.iter()
+            .find(|&&(at, _, compare)| at == addr && compare.is_none_or(|c| c == val))
+            .map_or(val, |&(_, value, _)| value)
+    }
+
+    /// GameShark writes due this VBlank: (WRAM bank, address, value)
+    pub fn ram_writes(&self) -> impl Iterator<Item = (Option<usize>, u16, u8)> + '_ {
+        self.enabled().filter_map(|c| match c.code {
+            Code::GameShark { bank, addr, value } => Some((bank, addr, value)),
+            Code::GameGenie { .. } => None,
+        })
+    }
+
+    // --- Database ---
+
+    /// This game's codes from a database file's text
+    pub fn from_database(text: &str, key: &str) -> Result<Self, String> {
+        let mut cheats = Self::new();
+        let mut ours = false;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if let Some(section) = line.strip_prefix('[') {
+                ours = section.split(']').next() == Some(key);
+                continue;
+            }
+            if !ours || line.is_empty() { continue; }
+            let enabled = match line.chars().next() {
+                Some('+') => true,
+                Some('-') => false,
+                _ => return Err(format!("line {}: expected '+ CODE name' or '- CODE name'", n + 1)),
+            };
+            let (code, name) = line[1..].trim().split_once(char::is_whitespace).unwrap_or((line[1..].trim(), ""));
+            let i = cheats.add(code, name).map_err(|e| format!("line {}: {e}", n + 1))?;
+            cheats.cheats[i].enabled = enabled;
+        }
+        cheats.rebuild();
+        Ok(cheats)
+    }
+
+    /// `text` with this game's section replaced by the current codes (or dropped if there are none)
+    pub fn to_database(&self, text: &str, key: &str, title: &str) -> String {
+        let mut out = String::new();
+        let mut ours = false;
+        for line in text.lines() {
+            if let Some(section) = line.trim().strip_prefix('[') {
+                ours = section.split(']').next() == Some(key);
+            }
+            if !ours { writeln!(out, "{line}").unwrap(); }
+        }
+        if !self.cheats.is_empty() {
+            if !out.is_empty() && !out.ends_with("\n\n") { out.push('\n'); }
+            writeln!(out, "[{key}] {title}").unwrap();
+            for c in &self.cheats {
+                let line = format!("{} {} {}", if c.enabled { '+' } else { '-' }, c.text, c.name);
+                writeln!(out, "{}", line.trim_end()).unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Database key for a ROM: header checksum then global checksum, in hex
+pub fn rom_key(rom: &[u8]) -> String {
+    rom.get(0x14D..0x150).map_or(String::new(), |b| format!("{:02X}{:02X}{:02X}", b[0], b[1], b[2]))
+}
+
+/// The header title, for database sections
+pub fn rom_title(rom: &[u8]) -> String {
+    let title = rom.get(0x134..0x144).unwrap_or(&[]);
+    title.iter().take_while(|&&b| b != 0).map(|&b| if b.is_ascii_graphic() { b as char } else { ' ' }).collect::<String>().trim().to_string()
+}
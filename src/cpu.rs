@@ -1,4 +1,5 @@
 use crate::mmu::Mmu;
+use crate::model::Model;
 use crate::registers::Registers;
 
 pub struct Cpu {
@@ -6,9 +7,18 @@ pub struct Cpu {
     pub halted: bool,
 }
 
+impl Default for Cpu {
+    fn default() -> Self { Self::new(Model::Dmg) }
+}
+
 impl Cpu {
-    pub fn new() -> Self {
-        Self { regs: Registers::new(), halted: false }
+    pub fn new(model: Model) -> Self {
+        Self { regs: Registers::new(model), halted: false }
+    }
+
+    /// CPU state at power-on, for running a boot ROM
+    pub fn power_on() -> Self {
+        Self { regs: Registers::power_on(), halted: false }
     }
 
     pub fn debug_print(&self, mmu: &Mmu) {
@@ -29,36 +39,39 @@ impl Cpu {
     }
 
     // --- Fetch ---
-    fn fetch8(&mut self, mmu: &Mmu) -> u8 {
-        let v = mmu.read(self.regs.pc);
+    fn fetch8(&mut self, mmu: &mut Mmu) -> u8 {
+        mmu.tick();
+        let v = mmu.fetch(self.regs.pc);
         self.regs.pc = self.regs.pc.wrapping_add(1);
         v
     }
 
-    fn fetch16(&mut self, mmu: &Mmu) -> u16 {
+    fn fetch16(&mut self, mmu: &mut Mmu) -> u16 {
         let lo = self.fetch8(mmu) as u16;
         let hi = self.fetch8(mmu) as u16;
         hi << 8 | lo
     }
 
     // --- Stack ---
+    // Every push (PUSH, CALL, RST, interrupts) spends an internal M-cycle first
     pub fn push16(&mut self, mmu: &mut Mmu, val: u16) {
+        mmu.tick();
         self.regs.sp = self.regs.sp.wrapping_sub(1);
-        mmu.write(self.regs.sp, (val >> 8) as u8);
+        write(mmu, self.regs.sp, (val >> 8) as u8);
         self.regs.sp = self.regs.sp.wrapping_sub(1);
-        mmu.write(self.regs.sp, (val & 0xFF) as u8);
+        write(mmu, self.regs.sp, (val & 0xFF) as u8);
     }
 
     pub fn pop16(&mut self, mmu: &mut Mmu) -> u16 {
-        let low = mmu.read(self.regs.sp) as u16;
+        let low = read(mmu, self.regs.sp) as u16;
         self.regs.sp = self.regs.sp.wrapping_add(1);
-        let high = mmu.read(self.regs.sp) as u16;
+        let high = read(mmu, self.regs.sp) as u16;
         self.regs.sp = self.regs.sp.wrapping_add(1);
         (high << 8) | low
     }
 
     // --- r8 helpers (B C D E H L (HL) A) ---
-    fn read_r8(&self, idx: u8, mmu: &Mmu) -> u8 {
+    fn read_r8(&self, idx: u8, mmu: &mut Mmu) -> u8 {
         match idx {
             0 => self.regs.b,
             1 => self.regs.c,
@@ -66,7 +79,7 @@ impl Cpu {
             3 => self.regs.e,
             4 => self.regs.h,
             5 => self.regs.l,
-            6 => mmu.read(self.regs.get_hl()),
+            6 => read(mmu, self.regs.get_hl()),
             7 => self.regs.a,
             _ => unreachable!(),
         }
@@ -80,7 +93,7 @@ impl Cpu {
             3 => self.regs.e = val,
             4 => self.regs.h = val,
             5 => self.regs.l = val,
-            6 => mmu.write(self.regs.get_hl(), val),
+            6 => write(mmu, self.regs.get_hl(), val),
             7 => self.regs.a = val,
             _ => unreachable!(),
         }
@@ -99,6 +112,7 @@ impl Cpu {
                 if_reg &= !(1 << bit);
                 mmu.write(0xFF0F, if_reg);
 
+                mmu.tick();
                 self.push16(mmu, self.regs.pc);
                 self.regs.pc = 0x0040 + (bit as u16 * 8);
                 return 20;
@@ -118,6 +132,8 @@ impl Cpu {
             // --- Misc ---
             0x00 => 4,
             0x76 => { self.halted = true; 4 }
+            // STOP is a two-byte opcode; on CGB it performs an armed speed switch
+            0x10 => { self.fetch8(mmu); mmu.stop(); 4 }
 
             // --- LD r16, u16 ---
             0x01 => { let v = self.fetch16(mmu); self.regs.set_bc(v); 12 }
@@ -126,16 +142,16 @@ impl Cpu {
             0x31 => { self.regs.sp = self.fetch16(mmu); 12 }
 
             // --- LD (r16), A ---
-            0x02 => { mmu.write(self.regs.get_bc(), self.regs.a); 8 }
-            0x12 => { mmu.write(self.regs.get_de(), self.regs.a); 8 }
-            0x22 => { let hl = self.regs.get_hl(); mmu.write(hl, self.regs.a); self.regs.set_hl(hl.wrapping_add(1)); 8 }
-            0x32 => { let hl = self.regs.get_hl(); mmu.write(hl, self.regs.a); self.regs.set_hl(hl.wrapping_sub(1)); 8 }
+            0x02 => { write(mmu, self.regs.get_bc(), self.regs.a); 8 }
+            0x12 => { write(mmu, self.regs.get_de(), self.regs.a); 8 }
+            0x22 => { let hl = self.regs.get_hl(); write(mmu, hl, self.regs.a); self.regs.set_hl(hl.wrapping_add(1)); 8 }
+            0x32 => { let hl = self.regs.get_hl(); write(mmu, hl, self.regs.a); self.regs.set_hl(hl.wrapping_sub(1)); 8 }
 
             // --- LD A, (r16) ---
-            0x0A => { self.regs.a = mmu.read(self.regs.get_bc()); 8 }
-            0x1A => { self.regs.a = mmu.read(self.regs.get_de()); 8 }
-            0x2A => { let hl = self.regs.get_hl(); self.regs.a = mmu.read(hl); self.regs.set_hl(hl.wrapping_add(1)); 8 }
-            0x3A => { let hl = self.regs.get_hl(); self.regs.a = mmu.read(hl); self.regs.set_hl(hl.wrapping_sub(1)); 8 }
+            0x0A => { self.regs.a = read(mmu, self.regs.get_bc()); 8 }
+            0x1A => { self.regs.a = read(mmu, self.regs.get_de()); 8 }
+            0x2A => { let hl = self.regs.get_hl(); self.regs.a = read(mmu, hl); self.regs.set_hl(hl.wrapping_add(1)); 8 }
+            0x3A => { let hl = self.regs.get_hl(); self.regs.a = read(mmu, hl); self.regs.set_hl(hl.wrapping_sub(1)); 8 }
 
             // --- INC r16 ---
             0x03 => { self.regs.set_bc(self.regs.get_bc().wrapping_add(1)); 8 }
@@ -306,10 +322,10 @@ impl Cpu {
             // --- RET ---
             0xC9 => { self.regs.pc = self.pop16(mmu); 16 }
             0xD9 => { self.regs.pc = self.pop16(mmu); self.regs.ime = true; 16 }
-            0xC0 => { if !self.regs.get_flag_z() { self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
-            0xC8 => { if  self.regs.get_flag_z() { self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
-            0xD0 => { if !self.regs.get_flag_c() { self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
-            0xD8 => { if  self.regs.get_flag_c() { self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
+            0xC0 => { if !self.regs.get_flag_z() { mmu.tick(); self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
+            0xC8 => { if  self.regs.get_flag_z() { mmu.tick(); self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
+            0xD0 => { if !self.regs.get_flag_c() { mmu.tick(); self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
+            0xD8 => { if  self.regs.get_flag_c() { mmu.tick(); self.regs.pc = self.pop16(mmu); 20 } else { 8 } }
 
             // --- PUSH / POP ---
             0xC5 => { let v = self.regs.get_bc(); self.push16(mmu, v); 16 }
@@ -333,12 +349,12 @@ impl Cpu {
             0xFF => { self.push16(mmu, self.regs.pc); self.regs.pc = 0x38; 16 }
 
             // --- I/O ---
-            0xE0 => { let a = 0xFF00 | self.fetch8(mmu) as u16; mmu.write(a, self.regs.a); 12 }
-            0xF0 => { let a = 0xFF00 | self.fetch8(mmu) as u16; self.regs.a = mmu.read(a); 12 }
-            0xE2 => { mmu.write(0xFF00 | self.regs.c as u16, self.regs.a); 8 }
-            0xF2 => { self.regs.a = mmu.read(0xFF00 | self.regs.c as u16); 8 }
-            0xEA => { let a = self.fetch16(mmu); mmu.write(a, self.regs.a); 16 }
-            0xFA => { let a = self.fetch16(mmu); self.regs.a = mmu.read(a); 16 }
+            0xE0 => { let a = 0xFF00 | self.fetch8(mmu) as u16; write(mmu, a, self.regs.a); 12 }
+            0xF0 => { let a = 0xFF00 | self.fetch8(mmu) as u16; self.regs.a = read(mmu, a); 12 }
+            0xE2 => { write(mmu, 0xFF00 | self.regs.c as u16, self.regs.a); 8 }
+            0xF2 => { self.regs.a = read(mmu, 0xFF00 | self.regs.c as u16); 8 }
+            0xEA => { let a = self.fetch16(mmu); write(mmu, a, self.regs.a); 16 }
+            0xFA => { let a = self.fetch16(mmu); self.regs.a = read(mmu, a); 16 }
 
             // --- SP ops ---
             0xE8 => {
@@ -464,4 +480,15 @@ impl Cpu {
         self.write_r8(r, result, mmu);
         cycles
     }
-}
\ No newline at end of file
+}
+// --- Bus: each access is an M-cycle, put on the clock before the access happens ---
+
+fn read(mmu: &mut Mmu, addr: u16) -> u8 {
+    mmu.tick();
+    mmu.read(addr)
+}
+
+fn write(mmu: &mut Mmu, addr: u16, val: u8) {
+    mmu.tick();
+    mmu.write(addr, val);
+}
@@ -0,0 +1,598 @@
+// Debugger core shared by every frontend: breakpoints, watchpoints,
+// conditions and stepping.
+//
+// The debugger does not own the console. Frontends keep their `GameBoy` and
+// call `run_frame` instead of `GameBoy::run_frame`, once per displayed frame.
+// It runs one instruction at a time and returns early with a `Stop` when
+// something triggers. The next call picks up mid-frame where the last one
+// left off. Stepping commands only arm a target; `run_frame` carries them out.
+//
+// Every instruction also feeds the shadow call stack and, when one is
+// attached, the profiler.
+//
+// Breakpoints are checked before an instruction executes and are skipped
+// while the CPU is halted. Read and write watchpoints see every CPU bus
+// access through `Mmu::watch`, instruction fetches included. Value-change
+// watchpoints compare the byte after each instruction, so they also catch
+// changes made by the PPU, timer or DMA.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::callstack::CallStack;
+use crate::gameboy::GameBoy;
+use crate::profiler::Profiler;
+use crate::registers::Reg;
+use crate::search::RamSearch;
+use crate::symbols::Symbols;
+
+// --- Locations ---
+
+/// An execution address, optionally tied to the bank mapped there
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub bank: Option<usize>,
+    pub addr: u16,
+}
+
+impl Location {
+    pub fn new(addr: u16) -> Self {
+        Self { bank: None, addr }
+    }
+
+    /// `4000`, `0x4000`, `$4000` or with a bank, `03:4000`. Numbers are hex.
+    pub fn parse(s: &str) -> Result<Location, String> {
+        Self::parse_with(s, &Symbols::new())
+    }
+
+    /// Like `parse`, but a label from `symbols` also works and keeps its bank
+    pub fn parse_with(s: &str, symbols: &Symbols) -> Result<Location, String> {
+        let s = s.trim();
+        if let Some(at) = symbols.lookup(s) { return Ok(at); }
+        match s.split_once(':') {
+            Some((bank, addr)) => Ok(Location {
+                bank: Some(parse_hex(bank)? as usize),
+                addr: parse_hex(addr)?,
+            }),
+            None => Ok(Location::new(parse_hex(s)?)),
+        }
+    }
+
+    pub fn matches(&self, gb: &GameBoy, pc: u16) -> bool {
+        self.addr == pc && self.bank.is_none_or(|b| b == gb.mmu.bank_at(pc))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.addr),
+            None => write!(f, "{:04X}", self.addr),
+        }
+    }
+}
+
+/// Hex with an optional `0x` or `$` prefix
+pub fn parse_hex(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).or_else(|| s.strip_prefix('$')).unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("bad address '{s}'"))
+}
+
+// --- Expressions ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinOp { Or, And, BitOr, BitXor, BitAnd, Eq, Ne, Lt, Le, Gt, Ge, Shl, Shr, Add, Sub, Mul, Div, Rem }
+
+// Binary operators by token, loosest binding first
+const LEVELS: &[&[(&str, BinOp)]] = &[
+    &[("||", BinOp::Or)],
+    &[("&&", BinOp::And)],
+    &[("|", BinOp::BitOr)],
+    &[("^", BinOp::BitXor)],
+    &[("&", BinOp::BitAnd)],
+    &[("==", BinOp::Eq), ("!=", BinOp::Ne)],
+    &[("<=", BinOp::Le), (">=", BinOp::Ge), ("<", BinOp::Lt), (">", BinOp::Gt)],
+    &[("<<", BinOp::Shl), (">>", BinOp::Shr)],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)],
+    &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+];
+
+#[derive(Clone, Debug)]
+enum Node {
+    Num(i64),
+    Reg(Reg),
+    Mem(Box<Node>),
+    Not(Box<Node>),
+    Neg(Box<Node>),
+    Inv(Box<Node>),
+    Bin(BinOp, Box<Node>, Box<Node>),
+}
+
+/// A C-like expression over registers and memory: `A == 0x3F && [HL] > 5`.
+/// `[addr]` reads a byte without side effects. Numbers are decimal unless
+/// prefixed with `0x` or `$`. Comparisons and logic give 1 or 0. Labels
+/// stand for their address: `[wPartyCount] == 6`.
+#[derive(Clone, Debug)]
+pub struct Expr {
+    text: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        Self::parse_with(text, &Symbols::new())
+    }
+
+    /// Register names win over labels that spell the same
+    pub fn parse_with(text: &str, symbols: &Symbols) -> Result<Expr, String> {
+        let mut p = Parser { s: text, pos: 0, symbols };
+        let root = p.expr(0)?;
+        p.skip_ws();
+        if p.pos < p.s.len() {
+            return Err(format!("unexpected '{}' in '{text}'", &p.s[p.pos..]));
+        }
+        Ok(Expr { text: text.trim().to_string(), root })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn eval(&self, gb: &GameBoy) -> i64 {
+        eval(&self.root, gb)
+    }
+
+    pub fn holds(&self, gb: &GameBoy) -> bool {
+        self.eval(gb) != 0
+    }
+}
+
+fn eval(node: &Node, gb: &GameBoy) -> i64 {
+    match node {
+        Node::Num(n) => *n,
+        Node::Reg(r) => gb.cpu.regs.get(*r) as i64,
+        Node::Mem(a) => gb.mmu.peek(eval(a, gb) as u16) as i64,
+        Node::Not(a) => (eval(a, gb) == 0) as i64,
+        Node::Neg(a) => eval(a, gb).wrapping_neg(),
+        Node::Inv(a) => !eval(a, gb),
+        Node::Bin(op, a, b) => {
+            let x = eval(a, gb);
+            // Short-circuit like C
+            match op {
+                BinOp::Or => return (x != 0 || eval(b, gb) != 0) as i64,
+                BinOp::And => return (x != 0 && eval(b, gb) != 0) as i64,
+                _ => {}
+            }
+            let y = eval(b, gb);
+            match op {
+                BinOp::BitOr => x | y,
+                BinOp::BitXor => x ^ y,
+                BinOp::BitAnd => x & y,
+                BinOp::Eq => (x == y) as i64,
+                BinOp::Ne => (x != y) as i64,
+                BinOp::Lt => (x < y) as i64,
+                BinOp::Le => (x <= y) as i64,
+                BinOp::Gt => (x > y) as i64,
+                BinOp::Ge => (x >= y) as i64,
+                BinOp::Shl => x.wrapping_shl(y as u32),
+                BinOp::Shr => x.wrapping_shr(y as u32),
+                BinOp::Add => x.wrapping_add(y),
+                BinOp::Sub => x.wrapping_sub(y),
+                BinOp::Mul => x.wrapping_mul(y),
+                BinOp::Div => x.checked_div(y).unwrap_or(0),
+                BinOp::Rem => x.checked_rem(y).unwrap_or(0),
+                BinOp::Or | BinOp::And => unreachable!(),
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+    symbols: &'a Symbols,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while self.rest().starts_with(char::is_whitespace) { self.pos += 1; }
+    }
+
+    fn rest(&self) -> &str {
+        &self.s[self.pos..]
+    }
+
+    fn eat(&mut self, tok: &str) -> bool {
+        self.skip_ws();
+        if !self.rest().starts_with(tok) { return false; }
+        // `|` and `&` must not swallow the first half of `||` and `&&`
+        let after = &self.rest()[tok.len()..];
+        if matches!(tok, "|" | "&") && after.starts_with(tok) { return false; }
+        if matches!(tok, "<" | ">") && (after.starts_with('=') || after.starts_with(tok)) { return false; }
+        self.pos += tok.len();
+        true
+    }
+
+    fn expect(&mut self, tok: &str) -> Result<(), String> {
+        if self.eat(tok) { Ok(()) } else { Err(format!("expected '{tok}' in '{}'", self.s)) }
+    }
+
+    fn expr(&mut self, level: usize) -> Result<Node, String> {
+        if level == LEVELS.len() { return self.unary(); }
+        let mut lhs = self.expr(level + 1)?;
+        'outer: loop {
+            for &(tok, op) in LEVELS[level] {
+                if self.eat(tok) {
+                    let rhs = self.expr(level + 1)?;
+                    lhs = Node::Bin(op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat("!") { return Ok(Node::Not(Box::new(self.unary()?))); }
+        if self.eat("-") { return Ok(Node::Neg(Box::new(self.unary()?))); }
+        if self.eat("~") { return Ok(Node::Inv(Box::new(self.unary()?))); }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        if self.eat("(") {
+            let e = self.expr(0)?;
+            self.expect(")")?;
+            return Ok(e);
+        }
+        if self.eat("[") {
+            let e = self.expr(0)?;
+            self.expect("]")?;
+            return Ok(Node::Mem(Box::new(e)));
+        }
+        self.skip_ws();
+        let word: String = self.rest().chars().take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '$' | '_' | '.')).collect();
+        if word.is_empty() {
+            return Err(format!("expected a value in '{}'", self.s));
+        }
+        self.pos += word.len();
+        if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).or_else(|| word.strip_prefix('$')) {
+            return i64::from_str_radix(hex, 16).map(Node::Num).map_err(|_| format!("bad number '{word}'"));
+        }
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            return word.parse().map(Node::Num).map_err(|_| format!("bad number '{word}'"));
+        }
+        if let Some(reg) = Reg::by_name(&word) { return Ok(Node::Reg(reg)); }
+        self.symbols.lookup(&word).map(|at| Node::Num(at.addr as i64)).ok_or_else(|| format!("unknown name '{word}'"))
+    }
+}
+
+// --- Watch hook ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access, // read or write
+    Change, // the byte differs after an instruction, whoever changed it
+}
+
+/// One CPU bus access to a watched address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub addr: u16,
+    pub write: bool,
+    pub old: u8,
+    pub new: u8, // the byte read, or written
+}
+
+/// Addresses the bus reports accesses to, installed in `Mmu::watch`
+#[derive(Debug)]
+pub struct Watch {
+    reads: [u64; 1024],
+    writes: [u64; 1024],
+    hits: RefCell<Vec<Hit>>,
+}
+
+impl Watch {
+    fn new() -> Self {
+        Self { reads: [0; 1024], writes: [0; 1024], hits: RefCell::new(Vec::new()) }
+    }
+
+    fn set(bits: &mut [u64; 1024], addr: u16) {
+        bits[addr as usize / 64] |= 1 << (addr % 64);
+    }
+
+    fn has(bits: &[u64; 1024], addr: u16) -> bool {
+        bits[addr as usize / 64] & 1 << (addr % 64) != 0
+    }
+
+    pub fn note_read(&self, addr: u16, val: u8) {
+        if Self::has(&self.reads, addr) {
+            self.hits.borrow_mut().push(Hit { addr, write: false, old: val, new: val });
+        }
+    }
+
+    pub fn note_write(&self, addr: u16, old: u8, new: u8) {
+        if Self::has(&self.writes, addr) {
+            self.hits.borrow_mut().push(Hit { addr, write: true, old, new });
+        }
+    }
+}
+
+// --- Debugger ---
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub at: Location,
+    pub cond: Option<Expr>,
+    pub enabled: bool,
+    pub hits: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub addr: u16,
+    pub kind: WatchKind,
+    pub cond: Option<Expr>,
+    pub enabled: bool,
+    pub hits: u32,
+    last: Option<u8>, // for Change: the byte after the previous instruction
+}
+
+/// Why `run_frame` returned early
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// A step, step-over, step-out or run-to finished
+    Step,
+    Breakpoint(usize),
+    Watchpoint { id: usize, addr: u16, old: u8, new: u8 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    None,
+    Step,
+    Over { pc: u16, sp: u16 }, // back at the return address with the frame popped
+    Out { sp: u16 },           // a RET popped the frame
+    To(Location),
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+    target: Target,
+    resuming: bool,   // execute the next instruction even if a breakpoint is on it
+    in_frame: bool,   // stopped mid-frame
+    elapsed: u32,     // dots run in the current frame
+    watch_dirty: bool,
+    pub stack: CallStack,
+    pub profiler: Option<Profiler>,
+    pub search: Option<RamSearch>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self { Self::new() }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            target: Target::None,
+            resuming: false,
+            in_frame: false,
+            elapsed: 0,
+            watch_dirty: false,
+            stack: CallStack::new(),
+            profiler: None,
+            search: None,
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns the new breakpoint's id
+    pub fn break_at(&mut self, at: Location, cond: Option<Expr>) -> usize {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint { id, at, cond, enabled: true, hits: 0 });
+        id
+    }
+
+    /// Returns the new watchpoint's id
+    pub fn watch(&mut self, addr: u16, kind: WatchKind, cond: Option<Expr>) -> usize {
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint { id, addr, kind, cond, enabled: true, hits: 0, last: None });
+        self.watch_dirty = true;
+        id
+    }
+
+    fn take_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Removes a breakpoint or watchpoint; false if there is no such id
+    pub fn delete(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|b| b.id != id);
+        self.watchpoints.retain(|w| w.id != id);
+        self.watch_dirty = true;
+        before != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        self.watch_dirty = true;
+        if let Some(b) = self.breakpoints.iter_mut().find(|b| b.id == id) {
+            b.enabled = enabled;
+        } else if let Some(w) = self.watchpoints.iter_mut().find(|w| w.id == id) {
+            w.enabled = enabled;
+            w.last = None;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Writes a byte through the bus (so MBC and I/O side effects happen)
+    /// without it counting as a watchpoint hit
+    pub fn poke(&self, gb: &mut GameBoy, addr: u16, val: u8) {
+        gb.mmu.write(addr, val);
+        if let Some(watch) = &gb.mmu.watch { watch.hits.borrow_mut().clear(); }
+    }
+
+    // --- Stepping ---
+
+    /// Stop after the next instruction (or interrupt dispatch)
+    pub fn step_into(&mut self) {
+        self.target = Target::Step;
+    }
+
+    /// Like `step_into`, but runs a CALL or RST through to its return
+    pub fn step_over(&mut self, gb: &GameBoy) {
+        let (pc, sp) = (gb.cpu.regs.pc, gb.cpu.regs.sp);
+        self.target = match gb.mmu.peek(pc) {
+            0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC => Target::Over { pc: pc.wrapping_add(3), sp },
+            op if op & 0xC7 == 0xC7 => Target::Over { pc: pc.wrapping_add(1), sp },
+            _ => Target::Step,
+        };
+    }
+
+    /// Runs until the current routine returns to its caller
+    pub fn step_out(&mut self, gb: &GameBoy) {
+        self.target = Target::Out { sp: gb.cpu.regs.sp };
+    }
+
+    /// Runs until execution reaches `at`
+    pub fn run_to(&mut self, at: Location) {
+        self.target = Target::To(at);
+    }
+
+    /// Drops a pending step, step-over, step-out or run-to
+    pub fn cancel(&mut self) {
+        self.target = Target::None;
+    }
+
+    // --- Running ---
+
+    /// Runs the rest of the current frame, or returns early with the reason
+    /// execution stopped. None means the frame completed.
+    pub fn run_frame(&mut self, gb: &mut GameBoy) -> Option<Stop> {
+        self.sync_watch(gb);
+        // Accesses made while stopped (UI, scripts, the GDB stub) aren't hits
+        if let Some(watch) = &gb.mmu.watch { watch.hits.borrow_mut().clear(); }
+        if !self.in_frame {
+            gb.begin_frame();
+            self.in_frame = true;
+            self.elapsed = 0;
+        }
+        while !gb.frame_over(self.elapsed) {
+            let resuming = std::mem::take(&mut self.resuming);
+            if !resuming && !gb.cpu.halted && let Some(stop) = self.check_before(gb) {
+                return self.stop(stop);
+            }
+            let (pc, sp, op) = (gb.cpu.regs.pc, gb.cpu.regs.sp, gb.mmu.peek(gb.cpu.regs.pc));
+            let (bank, start) = (gb.mmu.bank_at(pc), gb.mmu.scheduler.now());
+            self.elapsed += gb.step();
+            let change = self.stack.observe(gb, pc, sp, op);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(pc, bank, gb.mmu.scheduler.now() - start, change);
+            }
+            if let Some(stop) = self.check_after(gb, op) {
+                return self.stop(stop);
+            }
+        }
+        self.in_frame = false;
+        None
+    }
+
+    /// Runs up to `frames` frames; None if nothing stopped execution
+    pub fn run(&mut self, gb: &mut GameBoy, frames: u32) -> Option<Stop> {
+        (0..frames).find_map(|_| self.run_frame(gb))
+    }
+
+    fn stop(&mut self, stop: Stop) -> Option<Stop> {
+        self.target = Target::None;
+        self.resuming = true;
+        Some(stop)
+    }
+
+    fn check_before(&mut self, gb: &GameBoy) -> Option<Stop> {
+        let pc = gb.cpu.regs.pc;
+        for b in self.breakpoints.iter_mut().filter(|b| b.enabled && b.at.matches(gb, pc)) {
+            if b.cond.as_ref().is_none_or(|c| c.holds(gb)) {
+                b.hits += 1;
+                return Some(Stop::Breakpoint(b.id));
+            }
+        }
+        match self.target {
+            Target::Over { pc: ret, sp } if pc == ret && gb.cpu.regs.sp >= sp => Some(Stop::Step),
+            Target::To(at) if at.matches(gb, pc) => Some(Stop::Step),
+            _ => None,
+        }
+    }
+
+    fn check_after(&mut self, gb: &GameBoy, op: u8) -> Option<Stop> {
+        let mut stop = None;
+        let hits = gb.mmu.watch.as_ref().map(|w| w.hits.take()).unwrap_or_default();
+        for hit in hits {
+            for w in self.watchpoints.iter_mut().filter(|w| w.enabled && w.addr == hit.addr) {
+                let kind_hit = match w.kind {
+                    WatchKind::Read => !hit.write,
+                    WatchKind::Write => hit.write,
+                    WatchKind::Access => true,
+                    WatchKind::Change => false,
+                };
+                if stop.is_none() && kind_hit && w.cond.as_ref().is_none_or(|c| c.holds(gb)) {
+                    w.hits += 1;
+                    stop = Some(Stop::Watchpoint { id: w.id, addr: hit.addr, old: hit.old, new: hit.new });
+                }
+            }
+        }
+        // Every change watchpoint keeps its byte current, even once something has stopped
+        for w in self.watchpoints.iter_mut().filter(|w| w.enabled && w.kind == WatchKind::Change) {
+            let new = gb.mmu.peek(w.addr);
+            let old = w.last.replace(new).unwrap_or(new);
+            if stop.is_none() && old != new && w.cond.as_ref().is_none_or(|c| c.holds(gb)) {
+                w.hits += 1;
+                stop = Some(Stop::Watchpoint { id: w.id, addr: w.addr, old, new });
+            }
+        }
+        if stop.is_some() { return stop; }
+
+        // RET, RETI and taken RET cc are the only ways back up past `sp`
+        let ret = matches!(op, 0xC9 | 0xD9 | 0xC0 | 0xC8 | 0xD0 | 0xD8);
+        match self.target {
+            Target::Step => Some(Stop::Step),
+            Target::Out { sp: frame } if ret && gb.cpu.regs.sp > frame => Some(Stop::Step),
+            _ => None,
+        }
+    }
+
+    // Installs the bus hook for the enabled read/write watchpoints
+    fn sync_watch(&mut self, gb: &mut GameBoy) {
+        if !self.watch_dirty { return; }
+        self.watch_dirty = false;
+        let mut watch = Watch::new();
+        let mut any = false;
+        for w in self.watchpoints.iter_mut().filter(|w| w.enabled) {
+            if matches!(w.kind, WatchKind::Read | WatchKind::Access) { Watch::set(&mut watch.reads, w.addr); any = true; }
+            if matches!(w.kind, WatchKind::Write | WatchKind::Access) { Watch::set(&mut watch.writes, w.addr); any = true; }
+            if w.kind == WatchKind::Change && w.last.is_none() { w.last = Some(gb.mmu.peek(w.addr)); }
+        }
+        gb.mmu.watch = any.then(|| Box::new(watch));
+    }
+}
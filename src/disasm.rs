@@ -0,0 +1,117 @@
+// LR35902 disassembler. Operands print as `$`-prefixed hex and relative
+// jumps as their absolute target.
+
+use crate::mmu::Mmu;
+use crate::symbols::Symbols;
+
+const R8: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const R16: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const R16_STACK: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const R16_MEM: [&str; 4] = ["(BC)", "(DE)", "(HL+)", "(HL-)"];
+const CC: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU: [&str; 8] = ["ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP "];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+/// Instruction length in bytes for an opcode
+pub fn length(op: u8) -> u16 {
+    match op {
+        0xCB | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xE0 | 0xF0 | 0xE8 | 0xF8 => 2,
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 | 0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA | 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC | 0xEA | 0xFA => 3,
+        op if op & 0xC7 == 0x06 || op & 0xC7 == 0xC6 => 2, // LD r,n and ALU A,n
+        _ => 1,
+    }
+}
+
+/// Decodes the instruction in `bytes` (at least `length(bytes[0])` long) located at `pc`.
+/// Returns its text and length.
+pub fn decode(bytes: &[u8], pc: u16) -> (String, u16) {
+    let op = bytes[0];
+    let len = length(op);
+    let n = bytes.get(1).copied().unwrap_or(0);
+    let nn = u16::from_le_bytes([n, bytes.get(2).copied().unwrap_or(0)]);
+    let rel = pc.wrapping_add(2).wrapping_add(n as i8 as u16);
+    let (x, y, z) = (op >> 6, (op >> 3) & 7, op & 7);
+    let (p, q) = (y as usize >> 1, y & 1);
+    let y = y as usize;
+
+    let text = match (x, z) {
+        (0, 0) => match y {
+            0 => "NOP".into(),
+            1 => format!("LD (${:04X}),SP", nn),
+            2 => "STOP".into(),
+            3 => format!("JR ${:04X}", rel),
+            _ => format!("JR {},${:04X}", CC[y - 4], rel),
+        },
+        (0, 1) if q == 0 => format!("LD {},${:04X}", R16[p], nn),
+        (0, 1) => format!("ADD HL,{}", R16[p]),
+        (0, 2) if q == 0 => format!("LD {},A", R16_MEM[p]),
+        (0, 2) => format!("LD A,{}", R16_MEM[p]),
+        (0, 3) => format!("{} {}", if q == 0 { "INC" } else { "DEC" }, R16[p]),
+        (0, 4) => format!("INC {}", R8[y]),
+        (0, 5) => format!("DEC {}", R8[y]),
+        (0, 6) => format!("LD {},${:02X}", R8[y], n),
+        (0, _) => ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"][y].into(),
+        (1, _) if op == 0x76 => "HALT".into(),
+        (1, _) => format!("LD {},{}", R8[y], R8[z as usize]),
+        (2, _) => format!("{}{}", ALU[y], R8[z as usize]),
+        (3, 0) => match y {
+            0..=3 => format!("RET {}", CC[y]),
+            4 => format!("LDH ($FF{:02X}),A", n),
+            5 => format!("ADD SP,{}", n as i8),
+            6 => format!("LDH A,($FF{:02X})", n),
+            _ => format!("LD HL,SP{:+}", n as i8),
+        },
+        (3, 1) if q == 0 => format!("POP {}", R16_STACK[p]),
+        (3, 1) => ["RET", "RETI", "JP HL", "LD SP,HL"][p].into(),
+        (3, 2) => match y {
+            0..=3 => format!("JP {},${:04X}", CC[y], nn),
+            4 => "LD ($FF00+C),A".into(),
+            5 => format!("LD (${:04X}),A", nn),
+            6 => "LD A,($FF00+C)".into(),
+            _ => format!("LD A,(${:04X})", nn),
+        },
+        (3, 3) => match y {
+            0 => format!("JP ${:04X}", nn),
+            1 => return (cb(n), 2),
+            6 => "DI".into(),
+            7 => "EI".into(),
+            _ => format!("DB ${:02X}", op),
+        },
+        (3, 4) if y < 4 => format!("CALL {},${:04X}", CC[y], nn),
+        (3, 5) if q == 0 => format!("PUSH {}", R16_STACK[p]),
+        (3, 5) if p == 0 => format!("CALL ${:04X}", nn),
+        (3, 6) => format!("{}${:02X}", ALU[y], n),
+        (3, 7) => format!("RST ${:02X}", y * 8),
+        _ => format!("DB ${:02X}", op),
+    };
+    (text, len)
+}
+
+fn cb(op: u8) -> String {
+    let (x, y, z) = (op >> 6, (op >> 3) & 7, (op & 7) as usize);
+    match x {
+        0 => format!("{} {}", ROT[y as usize], R8[z]),
+        1 => format!("BIT {},{}", y, R8[z]),
+        2 => format!("RES {},{}", y, R8[z]),
+        _ => format!("SET {},{}", y, R8[z]),
+    }
+}
+
+/// Disassembles the instruction at `addr` as the CPU currently sees it
+pub fn disassemble(mmu: &Mmu, addr: u16) -> (String, u16) {
+    let bytes = [mmu.peek(addr), mmu.peek(addr.wrapping_add(1)), mmu.peek(addr.wrapping_add(2))];
+    decode(&bytes, addr)
+}
+
+/// Replaces 16-bit operands (`$XXXX`) that have a label in the bank currently mapped there
+pub fn symbolize(text: &str, mmu: &Mmu, symbols: &Symbols) -> String {
+    let Some(i) = text.find('$') else { return text.to_string() };
+    let digits = &text[i + 1..];
+    let hex_len = digits.chars().take_while(char::is_ascii_hexdigit).count();
+    if hex_len != 4 { return text.to_string(); }
+    let addr = u16::from_str_radix(&digits[..4], 16).unwrap();
+    match symbols.name_at(mmu.bank_at(addr), addr) {
+        Some(name) => format!("{}{}{}", &text[..i], name, &digits[4..]),
+        None => text.to_string(),
+    }
+}
@@ -0,0 +1,95 @@
+// OAM DMA: copies 160 bytes from XX00-XX9F into OAM, one byte per M-cycle.
+//
+// Writing FF46 does not start the copy straight away: the first byte moves two
+// M-cycles after the write. A restart during that window leaves the previous
+// transfer running (OAM stays locked) until the new one takes over.
+
+pub const DMA_LEN: u16 = 0xA0;
+const STARTUP_DELAY: u8 = 2; // M-cycles between the FF46 write and the first byte
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bus {
+    External, // ROM, cartridge RAM and WRAM
+    Video,    // VRAM
+    Internal, // OAM, I/O and HRAM never conflict with a DMA source
+}
+
+impl Bus {
+    pub fn of(addr: u16) -> Self {
+        match addr {
+            0x8000..=0x9FFF => Bus::Video,
+            0xFE00..=0xFFFF => Bus::Internal,
+            _ => Bus::External,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Dma {
+    source: u16,
+    pos: u16,
+    active: bool,
+    pending: Option<(u16, u8)>, // (source, remaining startup M-cycles)
+    pub bus_value: u8,          // last byte driven on the source bus
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called on every FF46 write. The transfer is queued behind the startup delay.
+    pub fn start(&mut self, page: u8) {
+        // Pages above DF read through to the WRAM echo on DMG
+        let page = if page >= 0xE0 { page - 0x20 } else { page };
+        self.pending = Some(((page as u16) << 8, STARTUP_DELAY));
+    }
+
+    /// True while the CPU is locked out of OAM and the source bus.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Running or about to start
+    pub fn busy(&self) -> bool {
+        self.active || self.pending.is_some()
+    }
+
+    /// M-cycles until the last byte has been copied
+    pub fn remaining(&self) -> u32 {
+        match self.pending {
+            Some((_, delay)) => delay as u32 + DMA_LEN as u32 - 1,
+            None if self.active => (DMA_LEN - self.pos) as u32,
+            None => 0,
+        }
+    }
+
+    pub fn source_bus(&self) -> Bus {
+        Bus::of(self.source)
+    }
+
+    /// Advances one M-cycle. Returns the (source address, OAM index) pair to copy, if any.
+    pub fn step(&mut self) -> Option<(u16, u8)> {
+        if let Some((src, delay)) = self.pending {
+            if delay <= 1 {
+                self.pending = None;
+                self.source = src;
+                self.pos = 0;
+                self.active = true;
+            } else {
+                self.pending = Some((src, delay - 1));
+            }
+        }
+
+        if !self.active {
+            return None;
+        }
+
+        let idx = self.pos;
+        self.pos += 1;
+        if self.pos == DMA_LEN {
+            self.active = false;
+        }
+        Some((self.source + idx, idx as u8))
+    }
+}
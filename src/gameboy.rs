@@ -0,0 +1,114 @@
+// One complete console: CPU, PPU and bus. Both frontends and the link cable
+// drive the emulation through this.
+
+use crate::cpu::Cpu;
+use crate::joypad::InputStage;
+use crate::mmu::Mmu;
+use crate::model::Model;
+use crate::ppu::Ppu;
+use crate::scheduler::Event;
+use crate::MAX_FRAME_CYCLES;
+
+pub struct GameBoy {
+    pub cpu: Cpu,
+    pub ppu: Ppu,
+    pub mmu: Mmu,
+    pub input: InputStage,
+}
+
+impl GameBoy {
+    /// Model picked from the cartridge header
+    pub fn new(rom: Vec<u8>) -> Self {
+        let model = Model::from_header(&rom);
+        Self::with_model(rom, model)
+    }
+
+    pub fn with_model(rom: Vec<u8>, model: Model) -> Self {
+        let mmu = Mmu::with_model(rom, vec![0u8; 0x8000], model);
+        Self { cpu: Cpu::new(mmu.model), ppu: Ppu::new(), mmu, input: InputStage::new() }
+    }
+
+    /// Maps a boot ROM and resets the CPU so it runs from 0x0000
+    pub fn map_boot_rom(&mut self, boot: Vec<u8>) -> Result<(), String> {
+        self.mmu.map_boot_rom(boot)?;
+        self.cpu = Cpu::power_on();
+        Ok(())
+    }
+
+    /// Executes one instruction (or interrupt dispatch) and handles every event
+    /// that fell due meanwhile. A halted CPU skips straight to the next event.
+    /// Returns the PPU dots that elapsed.
+    pub fn step(&mut self) -> u32 {
+        self.step_within(u32::MAX)
+    }
+
+    // As `step`, but a halted CPU skips at most `limit` dots
+    fn step_within(&mut self, limit: u32) -> u32 {
+        let s = if self.cpu.halted && !self.mmu.interrupt_pending() {
+            let limit = if self.mmu.double_speed { limit.saturating_mul(2) } else { limit };
+            let next = self.mmu.scheduler.next_at().unwrap_or(0);
+            let skip = next.saturating_sub(self.mmu.scheduler.now()).min(limit as u64);
+            let skip = (skip.max(4) as u32).next_multiple_of(4);
+            self.mmu.scheduler.advance(skip);
+            skip
+        } else {
+            // The CPU clocks its own bus accesses; this adds the internal cycles
+            let cycles = self.cpu.step(&mut self.mmu);
+            self.mmu.finish_step(cycles)
+        };
+        self.dispatch();
+        // In CGB double-speed mode the PPU only sees half the CPU cycles
+        if self.mmu.double_speed { s / 2 } else { s }
+    }
+
+    fn dispatch(&mut self) {
+        while let Some((at, event)) = self.mmu.scheduler.pop_due() {
+            match event {
+                Event::Ppu => {
+                    self.mmu.sync_dma();
+                    let dots = self.ppu.event(&mut self.mmu) as u64;
+                    let cycles = if self.mmu.double_speed { dots * 2 } else { dots };
+                    self.mmu.scheduler.schedule(at + cycles, Event::Ppu);
+                }
+                _ => self.mmu.event(event),
+            }
+        }
+    }
+
+    /// Runs for at least `dots` PPU dots and returns how many actually elapsed
+    pub fn run_for(&mut self, dots: u32) -> u32 {
+        let mut elapsed = 0;
+        while elapsed < dots {
+            elapsed += self.step_within(dots - elapsed);
+        }
+        elapsed
+    }
+
+    /// Executes one full frame of Game Boy logic (~16.7ms) and returns the dots that elapsed.
+    /// Input (held buttons, turbo, macros) is latched once at the start of the frame.
+    /// The frame ends on VBlank entry; with the LCD off it is 70224 dots long.
+    pub fn run_frame(&mut self) -> u32 {
+        self.begin_frame();
+        let mut elapsed = 0;
+        while !self.frame_over(elapsed) {
+            elapsed += self.step_within(2 * MAX_FRAME_CYCLES - elapsed);
+        }
+        elapsed
+    }
+
+    /// Latches this frame's input and rewrites frozen RAM. Callers stepping a frame by hand (the
+    /// debugger) pair this with `step` and `frame_over`.
+    pub fn begin_frame(&mut self) {
+        let pressed = self.input.next_frame();
+        self.mmu.set_buttons(pressed);
+        self.mmu.apply_frozen();
+        self.ppu.frame_done = false;
+    }
+
+    /// Whether a frame that has run for `elapsed` dots is complete
+    pub fn frame_over(&self, elapsed: u32) -> bool {
+        // A display switched off (or toggled) mid-frame must not stall the frontend
+        let lcd_off = self.mmu.io[0x40] & 0x80 == 0;
+        self.ppu.frame_done || elapsed >= MAX_FRAME_CYCLES && lcd_off || elapsed >= 2 * MAX_FRAME_CYCLES
+    }
+}
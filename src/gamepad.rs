@@ -0,0 +1,128 @@
+// Gamepad input straight from a Linux evdev node (/dev/input/eventN).
+//
+// Each read returns `input_event`s: a timeval (two C longs), then type, code
+// and value. EV_KEY events go through the configured button map, and the
+// HAT0 axes most pads use for their d-pad always drive the d-pad.
+
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::os::raw::c_long;
+
+use crate::bindings::{Action, GamepadConfig, Hotkey};
+use crate::joypad::Buttons;
+
+#[cfg(target_os = "linux")]
+const O_NONBLOCK: i32 = 0o4000;
+const TIME_SIZE: usize = 2 * size_of::<c_long>();
+const EVENT_SIZE: usize = TIME_SIZE + 8; // 24 bytes on 64-bit, 16 on 32-bit
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+pub struct Gamepad {
+    file: Option<File>, // None once the device went away
+    map: Vec<(u16, Action)>,
+    keys: Buttons,      // from mapped buttons
+    hat: Buttons,       // from the hat axes
+    turbo: Buttons,
+    actions: Vec<Action>, // hotkeys and macros currently held
+}
+
+impl Gamepad {
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(config: &GamepadConfig) -> Result<Self, String> {
+        Err(format!("{}: evdev gamepads are only supported on Linux", config.device))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn open(config: &GamepadConfig) -> Result<Self, String> {
+        use std::os::unix::fs::OpenOptionsExt;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(O_NONBLOCK)
+            .open(&config.device)
+            .map_err(|e| match e.kind() {
+                ErrorKind::PermissionDenied => format!("{}: permission denied (is your user in the `input` group?)", config.device),
+                _ => format!("{}: {}", config.device, e),
+            })?;
+        Ok(Self { file: Some(file), map: config.buttons.clone(), keys: Buttons::empty(), hat: Buttons::empty(), turbo: Buttons::empty(), actions: Vec::new() })
+    }
+
+    /// Drains pending events. Returns the hotkeys and macros pressed since the last poll.
+    /// A read error means the device went away: everything is released and
+    /// `is_connected` turns false.
+    pub fn poll(&mut self) -> Vec<Action> {
+        let mut pressed = Vec::new();
+        let Some(mut file) = self.file.take() else { return pressed };
+        let mut buf = [0u8; EVENT_SIZE * 32];
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(n) if n > 0 => n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                _ => { self.disconnect(); return pressed; }
+            };
+            for ev in buf[..n].chunks_exact(EVENT_SIZE) {
+                let ev = &ev[TIME_SIZE..];
+                let kind = u16::from_ne_bytes([ev[0], ev[1]]);
+                let code = u16::from_ne_bytes([ev[2], ev[3]]);
+                let value = i32::from_ne_bytes([ev[4], ev[5], ev[6], ev[7]]);
+                self.event(kind, code, value, &mut pressed);
+            }
+        }
+        self.file = Some(file);
+        pressed
+    }
+
+    fn event(&mut self, kind: u16, code: u16, value: i32, pressed: &mut Vec<Action>) {
+        match (kind, code) {
+            (EV_KEY, _) => {
+                // value 2 is autorepeat
+                let down = value != 0;
+                for &(c, act) in &self.map {
+                    if c != code { continue; }
+                    match act {
+                        Action::Button(b) => self.keys.set(b, down),
+                        Action::Turbo(b) => self.turbo.set(b, down),
+                        _ if down => {
+                            if !self.actions.contains(&act) { self.actions.push(act); pressed.push(act); }
+                        }
+                        _ => self.actions.retain(|&x| x != act),
+                    }
+                }
+            }
+            (EV_ABS, ABS_HAT0X) => {
+                self.hat.set(Buttons::LEFT, value < 0);
+                self.hat.set(Buttons::RIGHT, value > 0);
+            }
+            (EV_ABS, ABS_HAT0Y) => {
+                self.hat.set(Buttons::UP, value < 0);
+                self.hat.set(Buttons::DOWN, value > 0);
+            }
+            _ => {}
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.keys = Buttons::empty();
+        self.hat = Buttons::empty();
+        self.turbo = Buttons::empty();
+        self.actions.clear();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.keys | self.hat
+    }
+
+    pub fn turbo(&self) -> Buttons {
+        self.turbo
+    }
+
+    pub fn is_held(&self, hotkey: Hotkey) -> bool {
+        self.actions.contains(&Action::Hotkey(hotkey))
+    }
+}
@@ -0,0 +1,331 @@
+// GDB Remote Serial Protocol server, so GDB and IDE front-ends can attach.
+//
+// The stub sits on top of `Debugger` the same way the terminal REPL does: the
+// frontend calls `poll` every frame to answer packets, and `run_frame` instead
+// of `GameBoy::run_frame` while the client has the target running. GDB has no
+// LR35902 architecture, so the register file is described by a custom target
+// description (AF BC DE HL SP PC, 16 bits each, little-endian on the wire).
+//
+// Supported: ? g G p P m M c s Z0-Z4 z0-z4 k D qSupported qXfer:features
+// QStartNoAckMode, Ctrl-C, and `monitor frame [N]`, `monitor bank`, `monitor bt`.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::debugger::{Debugger, Location, Stop, WatchKind};
+use crate::gameboy::GameBoy;
+use crate::registers::Reg;
+
+const REGS: [Reg; 6] = [Reg::AF, Reg::BC, Reg::DE, Reg::HL, Reg::SP, Reg::PC];
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.pokegb.lr35902">
+    <reg name="af" bitsize="16" type="uint16" regnum="0"/>
+    <reg name="bc" bitsize="16" type="uint16"/>
+    <reg name="de" bitsize="16" type="uint16"/>
+    <reg name="hl" bitsize="16" type="uint16"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+pub struct GdbStub {
+    stream: TcpStream,
+    input: Vec<u8>,
+    pub dbg: Debugger,
+    points: HashMap<(u8, u16), Vec<usize>>, // Z packet type and address -> debugger ids
+    running: bool,
+    ack: bool,
+    connected: bool,
+}
+
+impl GdbStub {
+    /// Binds `addr` and waits for a debugger to attach
+    pub fn host<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::accept(&TcpListener::bind(addr)?)
+    }
+
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            input: Vec::new(),
+            dbg: Debugger::new(),
+            points: HashMap::new(),
+            running: false,
+            ack: true,
+            connected: true,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Whether the client resumed execution (`c` or `s`)
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// Answers every packet that has arrived. Never blocks.
+    pub fn poll(&mut self, gb: &mut GameBoy) {
+        let mut buf = [0u8; 4096];
+        while self.connected {
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.connected = false,
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => self.connected = false,
+            }
+        }
+        while let Some(packet) = self.next_packet() {
+            match packet {
+                Incoming::Interrupt => {
+                    if self.running {
+                        self.running = false;
+                        self.dbg.cancel();
+                        self.send("S02");
+                    }
+                }
+                Incoming::Packet(data) => {
+                    let reply = self.handle(gb, &data);
+                    if let Some(reply) = reply { self.send(&reply); }
+                }
+            }
+        }
+    }
+
+    /// Runs the rest of the frame while the client has the target running.
+    /// Returns false when execution stopped (the stop reply has been sent).
+    pub fn run_frame(&mut self, gb: &mut GameBoy) -> bool {
+        if !self.running { return false; }
+        let Some(stop) = self.dbg.run_frame(gb) else { return true };
+        self.running = false;
+        let reply = match stop {
+            Stop::Step => "S05".to_string(),
+            Stop::Breakpoint(_) => "T05swbreak:;".to_string(),
+            Stop::Watchpoint { id, addr, .. } => {
+                let kind = self.dbg.watchpoints().iter().find(|w| w.id == id).map(|w| w.kind);
+                let name = match kind { Some(WatchKind::Read) => "rwatch", Some(WatchKind::Access) => "awatch", _ => "watch" };
+                format!("T05{name}:{addr:04x};")
+            }
+        };
+        self.send(&reply);
+        false
+    }
+
+    // --- Framing ---
+
+    fn next_packet(&mut self) -> Option<Incoming> {
+        loop {
+            let start = self.input.iter().position(|&b| b == b'$' || b == 0x03)?;
+            if self.input[start] == 0x03 {
+                self.input.drain(..=start);
+                return Some(Incoming::Interrupt);
+            }
+            let hash = start + self.input[start..].iter().position(|&b| b == b'#')?;
+            if self.input.len() < hash + 3 { return None; }
+            let data = self.input[start + 1..hash].to_vec();
+            let sum = std::str::from_utf8(&self.input[hash + 1..hash + 3]).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+            self.input.drain(..hash + 3);
+            let ok = sum == Some(data.iter().fold(0u8, |a, &b| a.wrapping_add(b)));
+            if self.ack { self.write(if ok { b"+" } else { b"-" }); }
+            if ok { return Some(Incoming::Packet(String::from_utf8_lossy(&data).into_owned())); }
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |a, b| a.wrapping_add(b));
+        self.write(format!("${data}#{sum:02x}").as_bytes());
+    }
+
+    // Acks from the client are skipped, so nothing is ever retransmitted (fine over TCP)
+    fn write(&mut self, bytes: &[u8]) {
+        let mut left = bytes;
+        while !left.is_empty() && self.connected {
+            match self.stream.write(left) {
+                Ok(n) => left = &left[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::yield_now(),
+                Err(_) => self.connected = false,
+            }
+        }
+    }
+
+    // --- Commands ---
+
+    fn handle(&mut self, gb: &mut GameBoy, packet: &str) -> Option<String> {
+        let (cmd, args) = packet.split_at(packet.len().min(1));
+        Some(match cmd {
+            "?" => "S05".into(),
+            "g" => REGS.iter().map(|&r| hex_u16(gb.cpu.regs.get(r))).collect(),
+            "G" => {
+                for (i, &r) in REGS.iter().enumerate() {
+                    match args.get(i * 4..i * 4 + 4).and_then(parse_u16_le) {
+                        Some(v) => gb.cpu.regs.set(r, v),
+                        None => return Some("E01".into()),
+                    }
+                }
+                "OK".into()
+            }
+            "p" => match usize::from_str_radix(args, 16).ok().and_then(|i| REGS.get(i)) {
+                Some(&r) => hex_u16(gb.cpu.regs.get(r)),
+                None => "E01".into(),
+            },
+            "P" => {
+                let Some((n, v)) = args.split_once('=') else { return Some("E01".into()) };
+                let reg = usize::from_str_radix(n, 16).ok().and_then(|i| REGS.get(i));
+                match (reg, parse_u16_le(v)) {
+                    (Some(&r), Some(v)) => { gb.cpu.regs.set(r, v); "OK".into() }
+                    _ => "E01".into(),
+                }
+            }
+            "m" => {
+                let Some((addr, len)) = parse_range(args) else { return Some("E01".into()) };
+                (0..len.min(0x1000)).map(|i| format!("{:02x}", gb.mmu.peek(addr.wrapping_add(i as u16)))).collect()
+            }
+            "M" => {
+                let Some((range, data)) = args.split_once(':') else { return Some("E01".into()) };
+                let Some((addr, len)) = parse_range(range) else { return Some("E01".into()) };
+                let bytes = decode_hex(data);
+                if bytes.len() != len { return Some("E01".into()); }
+                for (i, b) in bytes.into_iter().enumerate() {
+                    self.dbg.poke(gb, addr.wrapping_add(i as u16), b);
+                }
+                "OK".into()
+            }
+            "c" => { self.running = true; return None; }
+            "s" => { self.dbg.step_into(); self.running = true; return None; }
+            "Z" | "z" => self.point(cmd == "Z", args),
+            "k" => { self.connected = false; return None; }
+            "D" => {
+                self.send("OK");
+                self.running = false;
+                self.connected = false;
+                return None;
+            }
+            "H" => "OK".into(),
+            "q" | "Q" => return self.query(gb, packet),
+            _ => String::new(),
+        })
+    }
+
+    fn query(&mut self, gb: &mut GameBoy, packet: &str) -> Option<String> {
+        if packet.starts_with("qSupported") {
+            return Some("PacketSize=4000;qXfer:features:read+;swbreak+;QStartNoAckMode+".into());
+        }
+        if packet == "QStartNoAckMode" {
+            self.send("OK");
+            self.ack = false;
+            return None;
+        }
+        if let Some(rest) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((off, len)) = rest.split_once(',') else { return Some("E01".into()) };
+            let (Ok(off), Ok(len)) = (usize::from_str_radix(off, 16), usize::from_str_radix(len, 16)) else { return Some("E01".into()) };
+            let xml = TARGET_XML.as_bytes();
+            let chunk = &xml[off.min(xml.len())..(off + len).min(xml.len())];
+            let more = off + len < xml.len();
+            return Some(format!("{}{}", if more { 'm' } else { 'l' }, String::from_utf8_lossy(chunk)));
+        }
+        if let Some(hex) = packet.strip_prefix("qRcmd,") {
+            let text = String::from_utf8_lossy(&decode_hex(hex)).into_owned();
+            let out = self.monitor(gb, text.trim());
+            // Console output goes out as O packets before the final reply
+            if !out.is_empty() { self.send(&format!("O{}", encode_hex(out.as_bytes()))); }
+            return Some("OK".into());
+        }
+        Some(match packet {
+            "qAttached" => "1".into(),
+            "qfThreadInfo" => "m1".into(),
+            "qsThreadInfo" => "l".into(),
+            "qC" => "QC1".into(),
+            _ => String::new(),
+        })
+    }
+
+    fn monitor(&mut self, gb: &mut GameBoy, cmd: &str) -> String {
+        let mut out = String::new();
+        let (word, arg) = cmd.split_once(' ').unwrap_or((cmd, ""));
+        match word {
+            "frame" => {
+                let n: u32 = arg.trim().parse().unwrap_or(1);
+                match self.dbg.run(gb, n) {
+                    Some(stop) => writeln!(out, "Stopped ({stop:?}) at PC={:04X}", gb.cpu.regs.pc).unwrap(),
+                    None => writeln!(out, "Ran {n} frame(s), PC={:04X}", gb.cpu.regs.pc).unwrap(),
+                }
+            }
+            "bank" => {
+                let m = &gb.mmu;
+                writeln!(out, "ROM {:02X}  SRAM {:02X}  WRAM {:X}  VRAM {:X}", m.bank_at(0x4000), m.bank_at(0xA000), m.bank_at(0xD000), m.bank_at(0x8000)).unwrap();
+            }
+            "bt" => {
+                writeln!(out, "#0 {:04X}", gb.cpu.regs.pc).unwrap();
+                for (i, f) in self.dbg.stack.frames().iter().rev().enumerate() {
+                    writeln!(out, "#{} {:04X} ({:?} to {:02X}:{:04X})", i + 1, f.ret, f.kind, f.bank, f.entry).unwrap();
+                }
+            }
+            _ => out.push_str("monitor commands: frame [N], bank, bt\n"),
+        }
+        out
+    }
+
+    // Z/z type,addr,kind
+    fn point(&mut self, insert: bool, args: &str) -> String {
+        let mut parts = args.split(',');
+        let (Some(ty), Some(addr), kind) = (parts.next(), parts.next(), parts.next()) else { return "E01".into() };
+        let (Ok(ty), Ok(addr)) = (ty.parse::<u8>(), u32::from_str_radix(addr, 16)) else { return "E01".into() };
+        let addr = addr as u16;
+        let len = kind.and_then(|k| usize::from_str_radix(k, 16).ok()).unwrap_or(1).clamp(1, 16);
+        if !insert {
+            for id in self.points.remove(&(ty, addr)).unwrap_or_default() { self.dbg.delete(id); }
+            return "OK".into();
+        }
+        let ids = match ty {
+            0 | 1 => vec![self.dbg.break_at(Location::new(addr), None)],
+            2..=4 => {
+                let kind = [WatchKind::Write, WatchKind::Read, WatchKind::Access][ty as usize - 2];
+                (0..len).map(|i| self.dbg.watch(addr.wrapping_add(i as u16), kind, None)).collect()
+            }
+            _ => return String::new(),
+        };
+        self.points.entry((ty, addr)).or_default().extend(ids);
+        "OK".into()
+    }
+}
+
+enum Incoming {
+    Packet(String),
+    Interrupt, // Ctrl-C
+}
+
+fn hex_u16(v: u16) -> String {
+    let [lo, hi] = v.to_le_bytes();
+    format!("{lo:02x}{hi:02x}")
+}
+
+fn parse_u16_le(s: &str) -> Option<u16> {
+    match decode_hex(s)[..] {
+        [lo, hi] if s.len() == 4 => Some(u16::from_le_bytes([lo, hi])),
+        _ => None,
+    }
+}
+
+fn parse_range(s: &str) -> Option<(u16, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((u32::from_str_radix(addr, 16).ok()? as u16, usize::from_str_radix(len, 16).ok()?))
+}
+
+fn decode_hex(s: &str) -> Vec<u8> {
+    (0..s.len() / 2).filter_map(|i| s.get(i * 2..i * 2 + 2).and_then(|b| u8::from_str_radix(b, 16).ok())).collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,220 @@
+// JOYP (FF00): button matrix behind P14/P15.
+//
+// Writing bits 4/5 low selects the d-pad (P14) and/or the buttons (P15).
+// P10-P13 read the selected groups wired-AND together, active low. Whenever
+// the lines change, be it from a select write or an input change, a line
+// going high to low raises the joypad interrupt.
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// Pressed buttons, active high. The low nibble is the d-pad and the high
+    /// nibble the face buttons, each in P10-P13 order.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct Buttons: u8 {
+        const RIGHT  = 0x01;
+        const LEFT   = 0x02;
+        const UP     = 0x04;
+        const DOWN   = 0x08;
+        const A      = 0x10;
+        const B      = 0x20;
+        const SELECT = 0x40;
+        const START  = 0x80;
+    }
+}
+
+impl Buttons {
+    /// From the active-low nibbles P10-P13 would show for each group
+    pub fn from_active_low(dpad: u8, buttons: u8) -> Self {
+        Self::from_bits_truncate(!(dpad & 0x0F | (buttons & 0x0F) << 4))
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::from_name(&name.to_ascii_uppercase())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Joypad {
+    select: u8, // bits 4-5 as last written
+    pressed: Buttons,
+    lines: u8,  // P10-P13, active low
+}
+
+impl Default for Joypad {
+    fn default() -> Self { Self::new() }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self { select: 0x30, pressed: Buttons::empty(), lines: 0x0F }
+    }
+
+    pub fn pressed(&self) -> Buttons {
+        self.pressed
+    }
+
+    /// Bits 4-5 of JOYP: 0x30 means neither group is selected
+    pub fn select(&self) -> u8 {
+        self.select
+    }
+
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | self.lines
+    }
+
+    /// Returns true if a line fell and the joypad interrupt should fire
+    pub fn write(&mut self, val: u8) -> bool {
+        self.select = val & 0x30;
+        self.update()
+    }
+
+    /// Returns true if a line fell and the joypad interrupt should fire
+    pub fn set_pressed(&mut self, pressed: Buttons) -> bool {
+        self.pressed = pressed;
+        self.update()
+    }
+
+    fn update(&mut self) -> bool {
+        let bits = self.pressed.bits();
+        let mut low = 0;
+        if self.select & 0x10 == 0 { low |= bits & 0x0F; }
+        if self.select & 0x20 == 0 { low |= bits >> 4; }
+        let lines = !low & 0x0F;
+        let fell = self.lines & !lines != 0;
+        self.lines = lines;
+        fell
+    }
+}
+
+// --- Per-frame input stage ---
+//
+// Frontends only report which buttons are physically held; turbo and macros
+// are expanded here, once per frame, from the frame counter alone. The same
+// held/turbo/macro calls at the same frames always give the same JOYP input,
+// so recordings replay exactly.
+
+/// Sequence of (buttons, frames) steps, e.g. "up*16, a" holds Up for 16
+/// frames and then A for one. `wait*N` (or `-*N`) releases everything and
+/// `+` combines buttons: "a+b*4".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputMacro(pub Vec<(Buttons, u32)>);
+
+impl InputMacro {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        for step in text.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (names, frames) = match step.split_once('*') {
+                Some((n, f)) => {
+                    let f: u32 = f.trim().parse().map_err(|_| format!("\"{}\": frame count must be a number", step))?;
+                    (n.trim(), f)
+                }
+                None => (step, 1),
+            };
+            let mut buttons = Buttons::empty();
+            if names != "wait" && names != "-" {
+                for name in names.split('+').map(str::trim) {
+                    buttons |= Buttons::by_name(name).ok_or_else(|| {
+                        format!("\"{}\": unknown button \"{}\", expected up, down, left, right, a, b, select, start or wait", step, name)
+                    })?;
+                }
+            }
+            steps.push((buttons, frames));
+        }
+        if steps.is_empty() { return Err("macro has no steps".into()); }
+        Ok(Self(steps))
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.0.iter().map(|&(_, f)| f).sum()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InputStage {
+    held: Buttons,
+    turbo: Buttons,          // buttons whose turbo key is held
+    turbo_since: [u64; 8],   // frame each turbo started, so every burst opens with a press
+    turbo_period: [u32; 8],  // frames pressed, then as many released, per button bit
+    running: Option<(InputMacro, usize, u32)>, // macro, step, frames left in step
+    frame: u64,
+}
+
+impl Default for InputStage {
+    fn default() -> Self { Self::new() }
+}
+
+pub const DEFAULT_TURBO_PERIOD: u32 = 2;
+
+impl InputStage {
+    pub fn new() -> Self {
+        Self {
+            held: Buttons::empty(), turbo: Buttons::empty(), turbo_since: [0; 8],
+            turbo_period: [DEFAULT_TURBO_PERIOD; 8], running: None, frame: 0,
+        }
+    }
+
+    /// Buttons the player is holding
+    pub fn set_held(&mut self, held: Buttons) {
+        self.held = held;
+    }
+
+    /// Buttons to auto-fire while their turbo key is held
+    pub fn set_turbo(&mut self, turbo: Buttons) {
+        for i in 0..8 {
+            if turbo.bits() & !self.turbo.bits() & (1 << i) != 0 { self.turbo_since[i] = self.frame; }
+        }
+        self.turbo = turbo;
+    }
+
+    pub fn set_turbo_period(&mut self, buttons: Buttons, frames: u32) {
+        for i in 0..8 {
+            if buttons.bits() & (1 << i) != 0 { self.turbo_period[i] = frames.max(1); }
+        }
+    }
+
+    /// Starts a macro, replacing any that is still running
+    pub fn play(&mut self, m: InputMacro) {
+        let first = m.0.first().map_or(0, |&(_, f)| f);
+        self.running = Some((m, 0, first));
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Buttons seen by the game this frame; advances turbo and macros by one frame
+    pub fn next_frame(&mut self) -> Buttons {
+        let mut out = self.held;
+        for i in 0..8 {
+            let bit = 1 << i;
+            if self.turbo.bits() & bit != 0 {
+                let phase = (self.frame - self.turbo_since[i]) / self.turbo_period[i] as u64;
+                if phase.is_multiple_of(2) { out |= Buttons::from_bits_retain(bit); }
+            }
+        }
+
+        // Skip zero-length steps, apply the current one, then count it down
+        while let Some((m, step, left)) = &mut self.running {
+            match m.0.get(*step) {
+                None => { self.running = None; }
+                Some(_) if *left == 0 => {
+                    *step += 1;
+                    *left = m.0.get(*step).map_or(0, |&(_, f)| f);
+                }
+                Some(&(buttons, _)) => {
+                    out |= buttons;
+                    *left -= 1;
+                    break;
+                }
+            }
+        }
+
+        self.frame += 1;
+        out
+    }
+}
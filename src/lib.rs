@@ -1,121 +1,316 @@
 use wasm_bindgen::prelude::*;
-#[warn(dead_code)]
-use web_sys::{CanvasRenderingContext2d, ImageData};
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bindings;
+pub mod boot;
+pub mod callstack;
+pub mod cdl;
+pub mod cgb;
+pub mod cheats;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod dma;
+pub mod gameboy;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gdb_stub;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gamepad;
+pub mod joypad;
+pub mod link;
 pub mod mmu;
+pub mod model;
+pub mod pacing;
+pub mod palette;
 pub mod ppu;
+pub mod printer;
+pub mod profiler;
 pub mod registers;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
+pub mod scheduler;
+pub mod search;
+pub mod serial;
+pub mod sgb;
+pub mod symbols;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tcp_link;
+pub mod timer;
+pub mod viewer;
 
-use cpu::Cpu;
-use mmu::Mmu;
-use ppu::Ppu;
+use cheats::Cheats;
+use gameboy::GameBoy;
+use joypad::Buttons;
+use model::Model;
+use pacing::Pacer;
+use palette::{Layer, Palette, PixelFormat};
+use search::{Compare, RamSearch, ValueType};
 
 // Constant for Game Boy frame timing
 pub const MAX_FRAME_CYCLES: u32 = 70224;
 
 #[wasm_bindgen]
 pub struct EmulatorState {
-    cpu: Cpu,
-    ppu: Ppu,
-    mmu: Mmu,
-    div_acc: u32,
-    timer_acc: u32,
+    gb: GameBoy,
+    pacer: Pacer,
+    search: Option<RamSearch>,
 }
 
 #[wasm_bindgen]
 impl EmulatorState {
     #[wasm_bindgen(constructor)]
     pub fn new(rom: Vec<u8>) -> Self {
+        let model = Model::from_header(&rom);
+        Self::boot(rom, model)
+    }
+
+    /// Boots as a Super Game Boy if the cartridge supports it, else like `new`
+    pub fn new_sgb(rom: Vec<u8>) -> Self {
+        let model = if Model::supports_sgb(&rom) { Model::Sgb } else { Model::from_header(&rom) };
+        Self::boot(rom, model)
+    }
+
+    /// Runs `boot_rom` from 0x0000 before handing over to the cartridge.
+    /// Falls back to the post-boot state if the image does not fit the model.
+    pub fn with_boot_rom(rom: Vec<u8>, boot_rom: Vec<u8>) -> Self {
+        let model = Model::from_header(&rom);
+        let mut emu = Self::boot(rom, model);
+        let _ = emu.gb.map_boot_rom(boot_rom);
+        emu
+    }
+
+    fn boot(rom: Vec<u8>, model: Model) -> Self {
         // Redirect Rust panics to the browser console for easier debugging
         console_error_panic_hook::set_once();
-        
-        Self {
-            cpu: Cpu::new(),
-            ppu: Ppu::new(),
-            mmu: Mmu::new(rom, vec![0u8; 0x8000]),
-            div_acc: 0,
-            timer_acc: 0,
-        }
+
+        Self { gb: GameBoy::with_model(rom, model), pacer: Pacer::new(), search: None }
     }
 
-    /// Executes one full frame of Game Boy logic (~16.7ms)
+    /// Executes one full frame of Game Boy logic (~16.7ms). Also the single-frame advance while paused.
     pub fn tick_frame(&mut self) {
-        let mut frame_cycles = 0;
-
-        while frame_cycles < MAX_FRAME_CYCLES {
-            let s = self.cpu.step(&mut self.mmu);
-            self.ppu.tick(s, &mut self.mmu);
-
-            // --- DIVIDER (DIV) Logic ---
-            self.div_acc += s;
-            if self.div_acc >= 256 {
-                self.div_acc -= 256;
-                // DIV is at 0xFF04. Wrapping_add simulates hardware register behavior.
-                self.mmu.io[0x04] = self.mmu.io[0x04].wrapping_add(1);
-            }
-
-            // --- TIMER Logic (Dialogue/Delay Driver) ---
-            let tac = self.mmu.read(0xFF07);
-            if tac & 0x04 != 0 { // Timer is enabled
-                self.timer_acc += s;
-                let threshold = match tac & 0x03 {
-                    0x00 => 1024, // 4096 Hz
-                    0x01 => 16,   // 262144 Hz
-                    0x02 => 64,   // 65536 Hz
-                    0x03 => 256,  // 16384 Hz
-                    _ => 1024,
-                };
-
-                while self.timer_acc >= threshold {
-                    self.timer_acc -= threshold;
-                    let tima = self.mmu.read(0xFF05);
-                    if tima == 0xFF {
-                        // Overflow: Reload from TMA (0xFF06) and trigger IRQ (Bit 2)
-                        self.mmu.write(0xFF05, self.mmu.read(0xFF06));
-                        let if_val = self.mmu.read(0xFF0F);
-                        self.mmu.write(0xFF0F, if_val | 0x04);
-                    } else {
-                        self.mmu.write(0xFF05, tima + 1);
-                    }
-                }
-            }
-            frame_cycles += s;
+        self.gb.run_frame();
+    }
+
+    /// Runs as many frames as `elapsed_ms` of wall-clock time is worth at the
+    /// current speed (59.73 fps at 1x) and returns how many ran
+    pub fn advance(&mut self, elapsed_ms: f64) -> u32 {
+        let frames = self.pacer.frames_due(std::time::Duration::from_secs_f64(elapsed_ms.max(0.0) / 1000.0));
+        for _ in 0..frames {
+            self.gb.run_frame();
         }
+        frames
+    }
+
+    /// Drops the pacing backlog, e.g. when resuming from pause or fast-forward
+    pub fn reset_pacing(&mut self) {
+        self.pacer.reset();
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.pacer.speed()
+    }
+
+    /// Snaps to the nearest of 0.25x, 0.5x, 1x, 2x, 4x, 8x and returns it
+    pub fn set_speed(&mut self, speed: f64) -> f64 {
+        self.pacer.set_speed(speed);
+        self.pacer.speed()
+    }
+
+    pub fn faster(&mut self) -> f64 {
+        self.pacer.faster()
+    }
+
+    pub fn slower(&mut self) -> f64 {
+        self.pacer.slower()
     }
 
     /// Returns a pointer to the PPU framebuffer for zero-copy drawing in JS
     pub fn framebuffer_ptr(&self) -> *const u8 {
-        self.ppu.framebuffer.as_ptr()
+        self.gb.ppu.framebuffer.as_ptr()
+    }
+
+    pub fn has_sgb(&self) -> bool {
+        self.gb.mmu.sgb.is_some()
+    }
+
+    /// Composites the SGB border and colours; returns a pointer to the
+    /// 256x224 RGBA8 buffer, or null when not running as an SGB
+    pub fn render_sgb(&mut self) -> *const u8 {
+        match self.gb.mmu.sgb.as_mut() {
+            Some(sgb) => sgb.render(&self.gb.ppu.pixels).as_ptr(),
+            None => std::ptr::null(),
+        }
+    }
+
+    /// Frame converted to RGB565 (little-endian u16 per pixel)
+    pub fn framebuffer_rgb565(&self) -> Vec<u8> {
+        self.gb.ppu.frame(PixelFormat::Rgb565)
+    }
+
+    /// Applies a preset ("grey", "dmg", "pocket", "light") to all layers.
+    /// Returns false for an unknown name.
+    pub fn set_palette(&mut self, name: &str) -> bool {
+        match Palette::by_name(name) {
+            Some(p) => { self.gb.ppu.set_all_palettes(p); true }
+            None => false,
+        }
+    }
+
+    /// Custom 4-colour palette (0xRRGGBB, lightest first) for one layer:
+    /// 0 = BG/window, 1 = OBJ0, 2 = OBJ1
+    pub fn set_layer_palette(&mut self, layer: u8, colors: Vec<u32>) {
+        let layer = match layer { 0 => Layer::Bg, 1 => Layer::Obj0, _ => Layer::Obj1 };
+        let mut c = [0u32; 4];
+        colors.iter().take(4).enumerate().for_each(|(i, &v)| c[i] = v);
+        self.gb.ppu.set_palette(layer, Palette::from_rgb(c));
     }
 
     #[wasm_bindgen]
     pub fn save_wasm(&self) -> Vec<u8> {
-        self.mmu.get_save_data()
+        self.gb.mmu.get_save_data()
     }
 
     #[wasm_bindgen]
     pub fn load_save_wasm(&mut self, data: Vec<u8>) {
-        self.mmu.load_save_data(data);
+        self.gb.mmu.load_save_data(data);
     }
 
     /// Updates Joypad state from JavaScript key events
     /// dpad_mask and button_mask should be passed as bitflags (Active Low)
     pub fn update_joypad(&mut self, d_pad: u8, buttons: u8) {
-        // Calculate transition for Joypad Interrupt
-        let select = self.mmu.io[0x00] & 0x30;
-        let mut current_joyp = 0x0F;
-        
-        if (select & 0x10) == 0 { current_joyp &= d_pad; }
-        if (select & 0x20) == 0 { current_joyp &= buttons; }
-
-        if (self.mmu.prev_joyp & !current_joyp) & 0x0F != 0 {
-            let if_val = self.mmu.read(0xFF0F);
-            self.mmu.write(0xFF0F, if_val | 0x10);
+        self.gb.input.set_held(Buttons::from_active_low(d_pad, buttons));
+    }
+
+    // --- Viewers: RGBA8 buffers, sizes as documented in `viewer` ---
+
+    /// 384 tiles of VRAM bank `bank`, 128x192
+    pub fn tiles_image(&self, bank: usize) -> Vec<u8> {
+        viewer::tiles(&self.gb.mmu, &self.gb.ppu, bank.min(1)).rgba
+    }
+
+    /// Both BG maps side by side with the viewport and window outlined, 512x256
+    pub fn tilemaps_image(&self) -> Vec<u8> {
+        viewer::tilemaps(&self.gb.mmu, &self.gb.ppu).rgba
+    }
+
+    /// The 40 sprites in 16x24 cells, 128x120
+    pub fn oam_image(&self) -> Vec<u8> {
+        viewer::oam(&self.gb.mmu, &self.gb.ppu).rgba
+    }
+
+    /// Palette swatches, 64 wide: 48 tall on DMG, 256 on CGB
+    pub fn palettes_image(&self) -> Vec<u8> {
+        viewer::palettes(&self.gb.mmu, &self.gb.ppu).rgba
+    }
+
+    pub fn oam_table(&self) -> String {
+        viewer::oam_table(&self.gb.mmu)
+    }
+
+    pub fn palette_table(&self) -> String {
+        viewer::palette_table(&self.gb.mmu)
+    }
+
+    /// Per-line register changes and LCD register writes of the last frame
+    pub fn raster_table(&self) -> String {
+        viewer::raster_table(&self.gb.ppu)
+    }
+
+    /// LCDC, SCY, SCX, WY, WX and BGP for each of the 144 lines of the last frame
+    pub fn scanline_registers(&self) -> Vec<u8> {
+        self.gb.ppu.line_regs.iter().flat_map(|r| [r.lcdc, r.scy, r.scx, r.wy, r.wx, r.bgp]).collect()
+    }
+
+    /// Shows or hides "bg", "window" or "sprites", or switches sprite "highlight"; false for an unknown name
+    pub fn set_layer(&mut self, name: &str, on: bool) -> bool {
+        self.gb.ppu.layer(name).map(|l| *l = on).is_ok()
+    }
+
+    // --- Cheats ---
+
+    /// Database key for this ROM (header and global checksum), e.g. for localStorage
+    pub fn rom_key(&self) -> String {
+        cheats::rom_key(self.gb.mmu.rom())
+    }
+
+    /// One "+ CODE name" line per cheat, - for disabled ones, in index order
+    pub fn cheat_list(&self) -> Vec<String> {
+        self.gb.mmu.cheats.list().iter()
+            .map(|c| format!("{} {} {}", if c.enabled { '+' } else { '-' }, c.text, c.name).trim_end().to_string())
+            .collect()
+    }
+
+    /// Adds an enabled GameShark or Game Genie code; false if it doesn't parse
+    pub fn cheat_add(&mut self, code: &str, name: &str) -> bool {
+        self.gb.mmu.cheats.add(code, name).is_ok()
+    }
+
+    pub fn cheat_remove(&mut self, index: usize) -> bool {
+        self.gb.mmu.cheats.remove(index).is_ok()
+    }
+
+    pub fn cheat_set_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        self.gb.mmu.cheats.set_enabled(index, enabled).is_ok()
+    }
+
+    /// Master switch for every cheat
+    pub fn set_cheats_active(&mut self, active: bool) {
+        self.gb.mmu.cheats.set_active(active);
+    }
+
+    /// Replaces the cheats with this ROM's section of a database text; false if it doesn't parse
+    pub fn load_cheats(&mut self, database: &str) -> bool {
+        match Cheats::from_database(database, &self.rom_key()) {
+            Ok(cheats) => { self.gb.mmu.cheats = cheats; true }
+            Err(_) => false,
         }
+    }
+
+    /// `database` with this ROM's section replaced by the current cheats
+    pub fn save_cheats(&self, database: &str) -> String {
+        let rom = self.gb.mmu.rom();
+        self.gb.mmu.cheats.to_database(database, &cheats::rom_key(rom), &cheats::rom_title(rom))
+    }
+
+    // --- RAM search ---
+
+    /// Snapshots WRAM, HRAM and cartridge RAM for a new search. `value_type` is
+    /// u8, u16, u24, bcd8, bcd16 or bcd24, optionally followed by "be".
+    /// Returns the candidate count, or undefined for an unknown type.
+    pub fn search_start(&mut self, value_type: &str) -> Option<usize> {
+        let search = RamSearch::new(&self.gb.mmu, ValueType::parse(value_type).ok()?);
+        let n = search.candidates().len();
+        self.search = Some(search);
+        Some(n)
+    }
+
+    /// Narrows the search: `op` is eq, gt, lt, changed or unchanged, compared
+    /// with `value` or, without one, the previous snapshot. Returns the candidates left.
+    pub fn search_filter(&mut self, op: &str, value: Option<u32>) -> Option<usize> {
+        let cmp = Compare::parse(op).ok()?;
+        Some(self.search.as_mut()?.filter(&self.gb.mmu, cmp, value))
+    }
+
+    /// Addresses of the first `max` candidates
+    pub fn search_addresses(&self, max: usize) -> Vec<u16> {
+        self.search.iter().flat_map(|s| s.candidates()).take(max).map(|c| c.addr).collect()
+    }
+
+    /// Values of the first `max` candidates at the last search, in the same order
+    pub fn search_values(&self, max: usize) -> Vec<u32> {
+        self.search.iter().flat_map(|s| s.candidates()).take(max).map(|c| c.value).collect()
+    }
+
+    pub fn search_stop(&mut self) {
+        self.search = None;
+    }
+
+    /// Holds the value of `value_type` at `addr` at `value`; false if it doesn't fit
+    pub fn freeze(&mut self, addr: u16, value: u32, value_type: &str) -> bool {
+        ValueType::parse(value_type).and_then(|ty| ty.freeze(&mut self.gb.mmu, addr, value)).is_ok()
+    }
 
-        self.mmu.dpad = d_pad;
-        self.mmu.buttons = buttons;
-        self.mmu.prev_joyp = current_joyp;
+    pub fn unfreeze(&mut self, addr: u16, value_type: &str) {
+        if let Ok(ty) = ValueType::parse(value_type) { ty.unfreeze(&mut self.gb.mmu, addr); }
     }
 }
\ No newline at end of file
@@ -0,0 +1,102 @@
+// Link cable between two cores in the same process.
+//
+// Each core's serial port is plugged into one end of a shared wire. The
+// coordinator runs the two cores alternately in short slices so that neither
+// gets more than one slice ahead of the other. When one side starts a transfer
+// on its internal clock, the other side picks the byte up the next time it
+// polls its external clock and shifts it in at the master's bit rate, so both
+// ends finish their 8 bits about the same time.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::gameboy::GameBoy;
+use crate::serial::{SerialEndpoint, BIT_PERIOD};
+use crate::MAX_FRAME_CYCLES;
+
+pub const LINK_SLICE: u32 = 456; // one scanline of dots per turn
+
+#[derive(Default)]
+struct Wire {
+    waiting: [Option<u8>; 2],   // SB of a side armed on the external clock
+    delivered: [Option<(u8, u32)>; 2], // byte its partner clocked out and its bit period, not yet picked up
+}
+
+pub struct LinkPort {
+    wire: Rc<RefCell<Wire>>,
+    side: usize,
+}
+
+impl SerialEndpoint for LinkPort {
+    fn exchange(&mut self, out: u8) -> u8 {
+        self.exchange_at(out, BIT_PERIOD, 0)
+    }
+
+    // Both cores run in lockstep, so the slave picks the byte up at the master's clock anyway
+    fn exchange_at(&mut self, out: u8, bit_period: u32, _now: u64) -> u8 {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        match wire.waiting[other].take() {
+            Some(theirs) => {
+                wire.delivered[other] = Some((out, bit_period));
+                theirs
+            }
+            None => 0xFF, // partner not listening
+        }
+    }
+
+    fn poll_external(&mut self, out: u8) -> Option<(u8, u32)> {
+        let mut wire = self.wire.borrow_mut();
+        match wire.delivered[self.side].take() {
+            Some(clocked) => {
+                wire.waiting[self.side] = None;
+                Some(clocked)
+            }
+            None => {
+                wire.waiting[self.side] = Some(out);
+                None
+            }
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.wire.borrow_mut().waiting[self.side] = None;
+    }
+}
+
+/// A pair of endpoints joined by one wire, for plugging into two serial ports
+pub fn cable() -> (LinkPort, LinkPort) {
+    let wire = Rc::new(RefCell::new(Wire::default()));
+    (LinkPort { wire: wire.clone(), side: 0 }, LinkPort { wire, side: 1 })
+}
+
+pub struct LinkCable {
+    pub a: GameBoy,
+    pub b: GameBoy,
+    pub slice: u32,
+    clock: [u64; 2], // dots each side has run
+}
+
+impl LinkCable {
+    pub fn new(mut a: GameBoy, mut b: GameBoy) -> Self {
+        let (port_a, port_b) = cable();
+        a.mmu.serial.connect(Box::new(port_a));
+        b.mmu.serial.connect(Box::new(port_b));
+        Self { a, b, slice: LINK_SLICE, clock: [0; 2] }
+    }
+
+    /// Runs both cores for `dots` PPU dots, always advancing whichever is behind
+    pub fn run_for(&mut self, dots: u32) {
+        let target = self.clock[0].min(self.clock[1]) + dots as u64;
+        while self.clock[0] < target || self.clock[1] < target {
+            let side = if self.clock[0] <= self.clock[1] { 0 } else { 1 };
+            let budget = (target - self.clock[side]).min(self.slice as u64) as u32;
+            let gb = if side == 0 { &mut self.a } else { &mut self.b };
+            self.clock[side] += gb.run_for(budget) as u64;
+        }
+    }
+
+    pub fn run_frame(&mut self) {
+        self.run_for(MAX_FRAME_CYCLES);
+    }
+}
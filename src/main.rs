@@ -1,104 +1,479 @@
 #[cfg(not(target_arch = "wasm32"))]
 use minifb::{Key, KeyRepeat, Window, WindowOptions};
 #[cfg(not(target_arch = "wasm32"))]
-use pokegameboy::{cpu::Cpu, mmu::Mmu, ppu::Ppu, MAX_FRAME_CYCLES};
+use pokegameboy::{gameboy::GameBoy, model::Model, palette::Palette};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::bindings::{Action, Bindings, Hotkey};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::cdl::Cdl;
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::cheats::{self, Cheats};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::gamepad::Gamepad;
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::gdb_stub::GdbStub;
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::pacing::{frame_duration, Pacer};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::printer::{Printer, Saved};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::serial::{Loopback, SerialEndpoint, TextCapture};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::repl::{Flow, Repl};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::sgb::{SGB_HEIGHT, SGB_WIDTH};
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::tcp_link::TcpLink;
+#[cfg(not(target_arch = "wasm32"))]
+use pokegameboy::viewer::{self, Image};
+#[cfg(not(target_arch = "wasm32"))]
+use std::cell::RefCell;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{BufRead, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::rc::Rc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(not(target_arch = "wasm32"))]
+const HISTORY_FILE: &str = ".pokegb_history";
+
+// Renders one viewer window's image
+#[cfg(not(target_arch = "wasm32"))]
+type Draw = fn(&GameBoy) -> Image;
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Args {
+    rom: String,
+    sgb: bool,              // --sgb: boot SGB-enhanced games as a Super Game Boy
+    model: Option<Model>,   // --model dmg|mgb|sgb|cgb overrides header detection
+    boot_rom: Option<String>,
+    serial: Option<Box<dyn SerialEndpoint>>, // --serial stdout|loopback|printer
+    printouts: Option<Rc<RefCell<Vec<Saved>>>>, // files written by --serial printer
+    link_host: Option<String>,    // --link-host ADDR: wait for a peer emulator
+    link_connect: Option<String>, // --link-connect ADDR: join a hosting emulator
+    bindings: Option<String>,     // --bindings PATH, else ./bindings.toml if present
+    debug: bool,                  // --debug: stop at the first instruction with a debugger prompt
+    debug_script: Option<String>, // --debug-script PATH: debugger commands to run first (implies --debug)
+    gdb: Option<String>,          // --gdb ADDR: wait for a GDB remote protocol client
+    symbols: Option<String>,      // --symbols PATH, else <rom>.sym if present
+    cdl: Option<String>,          // --cdl PATH: log ROM code/data use, merged into PATH on exit
+    viewers: bool,                // --viewers: tile, BG map, OAM and palette windows
+    cheats: Option<String>,       // --cheats PATH, else ./cheats.txt: cheat database, updated on exit
+    cheat_codes: Vec<String>,     // --cheat CODE (repeatable): add a code for this session and the database
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_args() -> Args {
+    let mut args = Args { rom: "rom.gb".into(), sgb: false, model: None, boot_rom: None, serial: None, printouts: None, link_host: None, link_connect: None, bindings: None, debug: false, debug_script: None, gdb: None, symbols: None, cdl: None, viewers: false, cheats: None, cheat_codes: Vec::new() };
+    let mut it = std::env::args().skip(1);
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--sgb" => args.sgb = true,
+            "--model" => {
+                let m = it.next().and_then(|m| Model::by_name(&m));
+                args.model = Some(m.expect("--model expects dmg, mgb, sgb or cgb"));
+            }
+            "--boot-rom" => args.boot_rom = Some(it.next().expect("--boot-rom expects a path")),
+            "--serial" => {
+                args.serial = match it.next().as_deref() {
+                    Some("stdout") => Some(Box::new(TextCapture::new(true))),
+                    Some("loopback") => Some(Box::new(Loopback)),
+                    Some("printer") => {
+                        let printer = Printer::saving_to(".");
+                        args.printouts = Some(printer.saved());
+                        Some(Box::new(printer))
+                    }
+                    Some("none") => None,
+                    _ => panic!("--serial expects none, stdout, loopback or printer"),
+                };
+            }
+            "--link-host" => args.link_host = Some(link_addr(it.next().expect("--link-host expects a port or address"))),
+            "--link-connect" => args.link_connect = Some(link_addr(it.next().expect("--link-connect expects a port or address"))),
+            "--bindings" => args.bindings = Some(it.next().expect("--bindings expects a path")),
+            "--debug" => args.debug = true,
+            "--symbols" => args.symbols = Some(it.next().expect("--symbols expects a path")),
+            "--viewers" => args.viewers = true,
+            "--cheats" => args.cheats = Some(it.next().expect("--cheats expects a path")),
+            "--cheat" => args.cheat_codes.push(it.next().expect("--cheat expects a code")),
+            "--cdl" => args.cdl = Some(it.next().expect("--cdl expects a path")),
+            "--gdb" => args.gdb = Some(link_addr(it.next().expect("--gdb expects a port or address"))),
+            "--debug-script" => {
+                args.debug_script = Some(it.next().expect("--debug-script expects a path"));
+                args.debug = true;
+            }
+            _ => args.rom = a,
+        }
+    }
+    args
+}
+
+// A bare port means localhost
+#[cfg(not(target_arch = "wasm32"))]
+fn link_addr(a: String) -> String {
+    if a.contains(':') { a } else { format!("127.0.0.1:{}", a) }
+}
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
-    let rom = std::fs::read("rom.gb").expect("rom.gb missing");
-    let mut mmu = Mmu::new(rom, vec![0u8; 0x8000]);
+    let mut args = parse_args();
+    let bindings = load_bindings(args.bindings.as_deref());
+    let mut gamepad = bindings.gamepad.as_ref().and_then(|c| match Gamepad::open(c) {
+        Ok(pad) => Some(pad),
+        Err(e) => { println!("Gamepad: {}", e); None }
+    });
+    let rom = std::fs::read(&args.rom).unwrap_or_else(|_| panic!("{} missing", args.rom));
+    let sav_path = std::path::Path::new(&args.rom).with_extension("sav");
+
+    let model = match args.model {
+        Some(m) => m,
+        None if args.sgb && Model::supports_sgb(&rom) => Model::Sgb,
+        None => Model::from_header(&rom),
+    };
+    let mut gb = GameBoy::with_model(rom, model);
+    for &(button, frames) in &bindings.turbo {
+        gb.input.set_turbo_period(button, frames);
+    }
+    if let Some(endpoint) = args.serial.take() {
+        gb.mmu.serial.connect(endpoint);
+    }
+
+    // --- TCP LINK CABLE ---
+    let link = match (&args.link_host, &args.link_connect) {
+        (Some(addr), _) => {
+            println!("Principal: Waiting for link partner on {}", addr);
+            Some(TcpLink::host(addr.as_str()).expect("Link cable: host failed"))
+        }
+        (None, Some(addr)) => Some(TcpLink::connect(addr.as_str()).expect("Link cable: connect failed")),
+        _ => None,
+    };
+    if let Some(link) = &link {
+        gb.mmu.serial.connect(Box::new(link.endpoint()));
+        println!("Principal: Link cable connected");
+    }
+    let mut cycles = 0u64;
     
     // --- LOAD SAVE DATA ---
-    if let Ok(save_data) = std::fs::read("rom.sav") {
-        mmu.load_save_data(save_data);
-        println!("Principal: Existing save state loaded from rom.sav");
+    if let Ok(save_data) = std::fs::read(&sav_path) {
+        gb.mmu.load_save_data(save_data);
+        println!("Principal: Existing save state loaded from {}", sav_path.display());
+    }
+
+    // --- BOOT ROM: run the real power-on sequence instead of the post-boot table ---
+    if let Some(path) = &args.boot_rom {
+        let boot = std::fs::read(path).unwrap_or_else(|_| panic!("{} missing", path));
+        gb.map_boot_rom(boot).expect("Unusable boot ROM");
+    }
+
+    // --- CHEATS: this game's section of the database, keyed by header checksum ---
+    let cheat_db = args.cheats.clone().unwrap_or_else(|| "cheats.txt".into());
+    let cheat_key = cheats::rom_key(gb.mmu.rom());
+    let db_text = std::fs::read_to_string(&cheat_db).unwrap_or_default();
+    gb.mmu.cheats = Cheats::from_database(&db_text, &cheat_key).unwrap_or_else(|e| panic!("{}: {}", cheat_db, e));
+    for code in &args.cheat_codes {
+        gb.mmu.cheats.add(code, "").unwrap_or_else(|e| panic!("--cheat: {}", e));
+    }
+    if !gb.mmu.cheats.is_empty() {
+        println!("Principal: {} cheats for {} (C toggles them)", gb.mmu.cheats.list().len(), cheat_key);
+    }
+
+    // --- CODE/DATA LOG: sessions accumulate in one file ---
+    if let Some(path) = &args.cdl {
+        let mut cdl = Cdl::new(gb.mmu.rom_len(), gb.mmu.extram.len());
+        if std::path::Path::new(path).exists() {
+            let merged = Cdl::load(path).and_then(|old| { cdl.format = old.format; cdl.merge(&old) });
+            if let Err(e) = merged { panic!("--cdl {}: {}", path, e); }
+            println!("Principal: Continuing code/data log {}", path);
+        }
+        gb.mmu.cdl = Some(Box::new(cdl));
     }
 
-    let (mut cpu, mut ppu) = (Cpu::new(), Ppu::new());
+    // --- DEBUGGER: commands come from stdin on a helper thread so the window keeps drawing ---
+    let mut repl = args.debug.then(|| {
+        let mut repl = Repl::new();
+        repl.load_history(HISTORY_FILE);
+        let sym_path = std::path::Path::new(&args.rom).with_extension("sym");
+        let symbols = args.symbols.clone().or_else(|| sym_path.exists().then(|| sym_path.display().to_string()));
+        if let Some(path) = symbols {
+            match repl.load_symbols(&path) {
+                Ok(n) => println!("Debugger: {} symbols from {}", n, path),
+                Err(e) => eprintln!("Debugger: {}", e),
+            }
+        }
+        if let Some(path) = &args.debug_script && let Err(e) = repl.source(path) {
+            eprintln!("Debugger: {}", e);
+        }
+        println!("{}", repl.where_is(&gb));
+        repl
+    });
+    assert!(repl.is_none() || args.gdb.is_none(), "--debug and --gdb cannot be combined");
+    let mut gdb = args.gdb.as_ref().map(|addr| {
+        println!("Principal: Waiting for GDB on {}", addr);
+        let stub = GdbStub::host(addr.as_str()).expect("GDB stub: host failed");
+        println!("Principal: GDB attached");
+        stub
+    });
+    let (line_tx, lines) = mpsc::channel::<String>();
+    if repl.is_some() {
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+                if line_tx.send(line).is_err() { break; }
+            }
+        });
+        prompt();
+    }
 
-    let (w, h, sc) = (160, 144, 4);
+    let sgb = gb.mmu.sgb.is_some();
+    let (w, h, sc) = if sgb { (SGB_WIDTH, SGB_HEIGHT, 3) } else { (160, 144, 4) };
     let mut window = Window::new("PokéGB Principal Build", w * sc, h * sc, WindowOptions::default()).unwrap();
-    window.limit_update_rate(Some(std::time::Duration::from_micros(8333))); 
+    // Pacing is ours: frames are timed from the 4.194304 MHz clock, not the display
+    window.limit_update_rate(None);
+    let mut pacer = Pacer::new();
+    let mut last = Instant::now();
+
+    // --- VIEWERS: extra windows redrawn with the main one ---
+    let mut viewers: Vec<(Window, Draw)> = Vec::new();
+    if args.viewers {
+        let mut open = |title: &str, draw: Draw| {
+            let img = draw(&gb);
+            let options = WindowOptions { scale: minifb::Scale::X2, ..WindowOptions::default() };
+            let mut win = Window::new(title, img.width, img.height, options).expect("Cannot open viewer window");
+            win.limit_update_rate(None);
+            viewers.push((win, draw));
+        };
+        open("Tiles 8000-97FF", |gb| viewer::tiles(&gb.mmu, &gb.ppu, 0));
+        if gb.mmu.is_cgb() { open("Tiles (VRAM bank 1)", |gb| viewer::tiles(&gb.mmu, &gb.ppu, 1)); }
+        open("BG maps 9800 | 9C00", |gb| viewer::tilemaps(&gb.mmu, &gb.ppu));
+        open("OAM", |gb| viewer::oam(&gb.mmu, &gb.ppu));
+        open("Palettes", |gb| viewer::palettes(&gb.mmu, &gb.ppu));
+    }
 
     let mut fb = vec![0u32; (w * sc) * (h * sc)];
+    let mut frame = vec![0u32; w * h];
     let mut paused = false;
-    
-    let mut div_acc: u32 = 0;
-    let mut timer_acc: u32 = 0;
+    let mut palette_idx = 0;
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        if window.is_key_pressed(Key::Space, KeyRepeat::No) { paused = !paused; }
-
-        // ---  MANUAL SAVE TRIGGER ---
-        if window.is_key_pressed(Key::F5, KeyRepeat::No) {
-            let data = mmu.get_save_data();
-            std::fs::write("rom.sav", data).expect("Failed to write save file");
-            println!("Principal: Manual save successful (rom.sav)");
-        }
-
-        if !paused {
-            update_joypad(&window, &mut mmu);
-            
-            let mut frame_cycles = 0;
-            while frame_cycles < MAX_FRAME_CYCLES {
-                let s = cpu.step(&mut mmu);
-                ppu.tick(s, &mut mmu);
-                
-                div_acc += s;
-                if div_acc >= 256 {
-                    div_acc -= 256;
-                    mmu.io[0x04] = mmu.io[0x04].wrapping_add(1);
+        let pad_actions = gamepad.as_mut().map_or(Vec::new(), |p| p.poll());
+        if gamepad.as_ref().is_some_and(|p| !p.is_connected()) {
+            println!("Gamepad: device disconnected");
+            gamepad = None;
+        }
+        let triggered = |h: Hotkey| {
+            pad_actions.contains(&Action::Hotkey(h)) || bindings.hotkey_keys(h).any(|k| window.is_key_pressed(k, KeyRepeat::No))
+        };
+        let held = |h: Hotkey| {
+            gamepad.as_ref().is_some_and(|p| p.is_held(h)) || bindings.hotkey_keys(h).any(|k| window.is_key_down(k))
+        };
+
+        if triggered(Hotkey::Pause) { paused = !paused; }
+
+        // Debugger commands run while execution is stopped; any input interrupts a run
+        if let Some(repl) = &mut repl {
+            if repl.running() {
+                if lines.try_recv().is_ok() {
+                    println!("{}", repl.interrupt(&gb));
+                    prompt();
+                }
+            } else {
+                let mut quit = false;
+                while !repl.running() && let Some(line) = repl.next_queued().or_else(|| lines.try_recv().ok()) {
+                    let mut out = String::new();
+                    match repl.command(&mut gb, &line, &mut out) {
+                        Ok(Flow::Quit) => { quit = true; break; }
+                        Ok(_) => print!("{}", out),
+                        Err(e) => println!("{}", e),
+                    }
+                    if !repl.running() { prompt(); }
+                }
+                if quit { break; }
+            }
+        }
+        if let Some(stub) = &mut gdb {
+            stub.poll(&mut gb);
+            if !stub.is_connected() {
+                println!("Principal: GDB detached");
+                gdb = None;
+            }
+        }
+        let stopped = repl.as_ref().is_some_and(|r| !r.running()) || gdb.as_ref().is_some_and(|g| !g.running());
+
+        // Cycle palette presets
+        if triggered(Hotkey::Palette) {
+            palette_idx = (palette_idx + 1) % Palette::PRESETS.len();
+            let (name, pal) = Palette::PRESETS[palette_idx];
+            gb.ppu.set_all_palettes(pal);
+            println!("Principal: Palette set to {}", name);
+        }
+
+        if triggered(Hotkey::Cheats) {
+            let active = !gb.mmu.cheats.active();
+            gb.mmu.cheats.set_active(active);
+            println!("Principal: Cheats {}", if active { "on" } else { "off" });
+        }
+
+        for (hotkey, name) in [(Hotkey::ToggleBg, "bg"), (Hotkey::ToggleWindow, "window"),
+                               (Hotkey::ToggleSprites, "sprites"), (Hotkey::HighlightSprites, "highlight")] {
+            if !triggered(hotkey) { continue; }
+            let layer = gb.ppu.layer(name).expect("known layer");
+            *layer = !*layer;
+            println!("Principal: Layer {} {}", name, if *layer { "on" } else { "off" });
+        }
+
+        // ---  MANUAL SAVE / RELOAD ---
+        if triggered(Hotkey::Save) {
+            let data = gb.mmu.get_save_data();
+            std::fs::write(&sav_path, data).expect("Failed to write save file");
+            println!("Principal: Manual save successful ({})", sav_path.display());
+        }
+        if triggered(Hotkey::Load) {
+            match std::fs::read(&sav_path) {
+                Ok(data) => {
+                    gb.mmu.load_save_data(data);
+                    println!("Principal: Save reloaded from {}", sav_path.display());
                 }
+                Err(e) => println!("Principal: Cannot reload {}: {}", sav_path.display(), e),
+            }
+        }
+        let screenshot = triggered(Hotkey::Screenshot);
 
-                let tac = mmu.read(0xFF07);
-                if tac & 0x04 != 0 {
-                    timer_acc += s;
-                    let threshold = match tac & 0x03 {
-                        0x00 => 1024, 0x01 => 16, 0x02 => 64, 0x03 => 256,
-                        _ => 1024,
-                    };
-
-                    while timer_acc >= threshold {
-                        timer_acc -= threshold;
-                        let tima = mmu.read(0xFF05);
-                        if tima == 0xFF {
-                            mmu.write(0xFF05, mmu.read(0xFF06)); 
-                            let if_val = mmu.read(0xFF0F);
-                            mmu.write(0xFF0F, if_val | 0x04); 
-                        } else {
-                            mmu.write(0xFF05, tima + 1);
+        if triggered(Hotkey::SpeedUp) { println!("Principal: Speed {}x", pacer.faster()); }
+        if triggered(Hotkey::SpeedDown) { println!("Principal: Speed {}x", pacer.slower()); }
+        let advance = paused && triggered(Hotkey::FrameAdvance);
+        let fast_forward = !paused && held(Hotkey::FastForward);
+
+        let now = Instant::now();
+        let elapsed = now - last;
+        last = now;
+
+        if !stopped && (!paused || advance) {
+            let mut pressed = bindings.buttons(|k| window.is_key_down(k));
+            let mut turbo = bindings.turbo(|k| window.is_key_down(k));
+            if let Some(pad) = &gamepad {
+                pressed |= pad.buttons();
+                turbo |= pad.turbo();
+            }
+            gb.input.set_held(pressed);
+            gb.input.set_turbo(turbo);
+
+            let mut macros = bindings.triggered_macros(|k| window.is_key_pressed(k, KeyRepeat::No));
+            macros.extend(pad_actions.iter().filter_map(|a| match a { Action::Macro(i) => Some(*i), _ => None }));
+            if let Some(&i) = macros.last() {
+                gb.input.play(bindings.macros[i].1.clone());
+            }
+
+            // Returns false once the debugger has stopped execution
+            let mut run_frame = |gb: &mut GameBoy| {
+                let mut running = true;
+                let start = gb.mmu.scheduler.now();
+                match (&mut repl, &mut gdb) {
+                    (Some(repl), _) => {
+                        if let Some(report) = repl.run_frame(gb) {
+                            println!("{}", report);
+                            prompt();
+                            running = false;
+                        }
+                    }
+                    (None, Some(stub)) => running = stub.run_frame(gb),
+                    (None, None) => { gb.run_frame(); }
+                }
+                cycles += gb.mmu.scheduler.now() - start;
+                if let Some(link) = &link && !link.sync(cycles) && link.is_connected() {
+                    println!("Principal: Link partner is not keeping up");
+                }
+                if let Some(printouts) = &args.printouts {
+                    for (path, result) in printouts.borrow_mut().drain(..) {
+                        match result {
+                            Ok(()) => println!("Printer: wrote {}", path.display()),
+                            Err(e) => println!("Printer: could not write {}: {}", path.display(), e),
                         }
                     }
                 }
-                frame_cycles += s;
+                running
+            };
+            if advance {
+                run_frame(&mut gb);
+            } else if fast_forward {
+                // Uncapped: as many frames as fit in one display frame
+                let deadline = now + frame_duration();
+                while Instant::now() < deadline && run_frame(&mut gb) {}
+                pacer.reset();
+            } else {
+                for _ in 0..pacer.frames_due(elapsed) {
+                    if !run_frame(&mut gb) { break; }
+                }
             }
         }
+        if paused || stopped { pacer.reset(); }
 
         window.set_title(&format!(
-            "PokéGB | PC:{:04X} | LY:{:02X} | IF:{:02X} | IE:{:02X}",
-            cpu.regs.pc, mmu.read(0xFF44), mmu.read(0xFF0F), mmu.read(0xFFFF)
+            "PokéGB | {} | PC:{:04X} | LY:{:02X} | IF:{:02X} | IE:{:02X}",
+            if paused { "paused".to_string() } else if stopped { "debugger".to_string() } else if fast_forward { ">>".to_string() } else { format!("{}x", pacer.speed()) },
+            gb.cpu.regs.pc, gb.mmu.peek(0xFF44), gb.mmu.peek(0xFF0F), gb.mmu.peek(0xFFFF)
         ));
 
-        render_frame(&mut fb, &ppu, w, h, sc);
+        match gb.mmu.sgb.as_mut() {
+            Some(sgb) => {
+                let rgba = sgb.render(&gb.ppu.pixels);
+                for (dst, px) in frame.iter_mut().zip(rgba.chunks_exact(4)) {
+                    *dst = u32::from_be_bytes([0xFF, px[0], px[1], px[2]]);
+                }
+            }
+            None => gb.ppu.write_argb32(&mut frame),
+        }
+        if screenshot { save_screenshot(&frame, w, h, std::path::Path::new(&args.rom)); }
+        render_frame(&mut fb, &frame, w, h, sc);
         window.update_with_buffer(&fb, w * sc, h * sc).unwrap();
+        viewers.retain(|(win, _)| win.is_open());
+        for (win, draw) in &mut viewers {
+            let img = draw(&gb);
+            win.update_with_buffer(&img.argb32(), img.width, img.height).unwrap();
+        }
+
+        if paused || stopped {
+            std::thread::sleep(frame_duration());
+        } else if !fast_forward {
+            std::thread::sleep(pacer.until_next());
+        }
+    }
+
+    if let Some(repl) = &repl && let Err(e) = repl.save_history(HISTORY_FILE) {
+        eprintln!("Debugger: cannot save history: {}", e);
+    }
+
+    // Write back cheats added or toggled this session, keeping other games' sections
+    let db_text = std::fs::read_to_string(&cheat_db).unwrap_or_default();
+    let updated = gb.mmu.cheats.to_database(&db_text, &cheat_key, &cheats::rom_title(gb.mmu.rom()));
+    if updated != db_text && let Err(e) = std::fs::write(&cheat_db, updated) {
+        eprintln!("Principal: {}: {}", cheat_db, e);
+    }
+
+    if let Some(path) = &args.cdl && let Some(cdl) = &gb.mmu.cdl {
+        match cdl.save(path) {
+            Ok(()) => println!("Principal: Code/data log saved to {}", path),
+            Err(e) => eprintln!("Principal: {}", e),
+        }
     }
 
     // --- 🏛️ AUTO-SAVE ON EXIT ---
-    let data = mmu.get_save_data();
-    let _ = std::fs::write("rom.sav", data);
+    let data = gb.mmu.get_save_data();
+    let _ = std::fs::write(&sav_path, data);
     println!("Principal: Shutdown successful. Auto-save completed.");
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn render_frame(fb: &mut [u32], ppu: &Ppu, w: usize, h: usize, sc: usize) {
+fn prompt() {
+    print!("(gb) ");
+    let _ = std::io::stdout().flush();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn render_frame(fb: &mut [u32], src: &[u32], w: usize, h: usize, sc: usize) {
     for y in 0..h {
-        let src_row = y * 640;
         for x in 0..w {
-            let i = src_row + (x * 4);
-            let color = ((ppu.framebuffer[i] as u32) << 16) | 
-                        ((ppu.framebuffer[i+1] as u32) << 8) | 
-                         (ppu.framebuffer[i+2] as u32);
-            
+            let color = src[y * w + x];
             for dy in 0..sc {
                 let start = ((y * sc + dy) * (w * sc)) + (x * sc);
                 fb[start..start + sc].fill(color);
@@ -108,33 +483,32 @@ fn render_frame(fb: &mut [u32], ppu: &Ppu, w: usize, h: usize, sc: usize) {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn update_joypad(w: &Window, m: &mut Mmu) {
-    let mut d = 0x0F; // D-pad: Down, Up, Left, Right
-    let mut b = 0x0F; // Buttons: Start, Select, B, A
+fn load_bindings(path: Option<&str>) -> Bindings {
+    let path = match path {
+        Some(p) => p,
+        None if std::path::Path::new("bindings.toml").exists() => "bindings.toml",
+        None => return Bindings::default(),
+    };
+    match Bindings::load(path) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Bindings: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    // D-Pad mapping
-    if w.is_key_down(Key::Down)  { d &= !0x08; }
-    if w.is_key_down(Key::Up)    { d &= !0x04; }
-    if w.is_key_down(Key::Left)  { d &= !0x02; }
-    if w.is_key_down(Key::Right) { d &= !0x01; }
-    
-    // Face button mappign
-    if w.is_key_down(Key::Enter) { b &= !0x08; } // Start 
-    if w.is_key_down(Key::S)     { b &= !0x04; } // Select
-    if w.is_key_down(Key::B)     { b &= !0x02; } // Map B
-    if w.is_key_down(Key::A)     { b &= !0x01; } // Map A
-
-    let select = m.io[0x00] & 0x30;
-    let mut current_joyp = 0x0F;
-    if (select & 0x10) == 0 { current_joyp &= d; }
-    if (select & 0x20) == 0 { current_joyp &= b; }
-
-    if (m.prev_joyp & !current_joyp) & 0x0F != 0 {
-        let if_val = m.read(0xFF0F);
-        m.write(0xFF0F, if_val | 0x10);
-    }
-
-    m.dpad = d;
-    m.buttons = b;
-    m.prev_joyp = current_joyp;
-}
\ No newline at end of file
+// Binary PPM next to the ROM, never overwriting an earlier one
+#[cfg(not(target_arch = "wasm32"))]
+fn save_screenshot(frame: &[u32], w: usize, h: usize, rom: &std::path::Path) {
+    let stem = rom.file_stem().and_then(|s| s.to_str()).unwrap_or("screenshot");
+    let path = (1..).map(|n| rom.with_file_name(format!("{}-{:04}.ppm", stem, n))).find(|p| !p.exists()).unwrap();
+    let mut data = format!("P6\n{} {}\n255\n", w, h).into_bytes();
+    for px in frame {
+        data.extend_from_slice(&px.to_be_bytes()[1..]);
+    }
+    match std::fs::write(&path, data) {
+        Ok(()) => println!("Principal: Screenshot saved to {}", path.display()),
+        Err(e) => println!("Principal: Screenshot failed: {}", e),
+    }
+}
@@ -1,41 +1,142 @@
+use std::collections::BTreeMap;
+
+use crate::boot;
+use crate::cdl::Cdl;
+use crate::cheats::Cheats;
+use crate::cgb::{ColorPalettes, Hdma};
+use crate::debugger::Watch;
+use crate::dma::{Bus, Dma};
+use crate::joypad::{Buttons, Joypad};
+use crate::model::Model;
+use crate::ppu::RasterEvent;
+use crate::scheduler::{Event, Scheduler};
+use crate::serial::Serial;
+use crate::sgb::Sgb;
+use crate::timer::Timer;
+
+const MAX_RASTER_EVENTS: usize = 4096; // with the LCD off there is no VBlank to clear the log
+
 pub struct Mmu {
     rom:          Vec<u8>,
     pub rom_bank:     usize,
-    pub vram:     [u8; 0x2000],
+    pub vram:     [u8; 0x4000], // two 8KB banks, bank 1 is CGB only
+    vram_bank:    usize,
     pub extram:   Vec<u8>,
     extram_bank:  usize,
-    wram:         [u8; 0x4000],
+    wram:         [u8; 0x8000], // eight 4KB banks, 2-7 are CGB only
+    wram_bank:    usize,
     pub oam:      [u8; 0xA0],
     pub io:       [u8; 0x80],
     hram:         [u8; 0x7F],
     pub ie:       u8,
-    pub buttons: u8, // face buttons: Start | Select | B | A (active-low, 0=pressed)
-    pub dpad: u8,   // directions: Down | Up | Left | Right 
-    pub prev_joyp: u8,
+    pub joypad:   Joypad,
+    pub dma: Dma,
+    pub timer: Timer,
+    pub serial: Serial,
+    pub scheduler: Scheduler,
+    // Cycle each lazily clocked peripheral was last brought up to
+    timer_synced: u64,
+    serial_synced: u64,
+    dma_synced: u64,
+    pub model: Model,
+    pub bg_palettes: ColorPalettes,
+    pub obj_palettes: ColorPalettes,
+    hdma: Hdma,
+    pub double_speed: bool,
+    speed_switch_armed: bool, // KEY1 bit 0
+    pub sgb: Option<Sgb>,
+    boot_rom: Option<Vec<u8>>, // unmapped by the first non-zero write to FF50
+    pub watch: Option<Box<Watch>>, // debugger access log, off unless watchpoints are set
+    pub cdl: Option<Box<Cdl>>,     // code/data log of ROM accesses, off unless started
+    frozen: BTreeMap<(usize, u16), u8>, // RAM bytes held at a value by the RAM search, by (bank, address)
+    pub cheats: Cheats,
+    raster: Vec<RasterEvent>,      // LCD register writes since the last VBlank
+    ticked: u32,  // T-cycles of the running instruction already on the clock
+    stalled: u32, // T-cycles the CPU was held off the bus by general-purpose HDMA
 }
 
 impl Mmu {
     pub fn new(rom: Vec<u8>, extram: Vec<u8>) -> Self {
+        let model = Model::from_header(&rom);
+        Self::with_model(rom, extram, model)
+    }
+
+    pub fn with_model(rom: Vec<u8>, extram: Vec<u8>, model: Model) -> Self {
         let mut mmu = Self {
             rom,
             rom_bank:    1,
-            vram:        [0; 0x2000],
+            vram:        [0; 0x4000],
+            vram_bank:   0,
             extram,
             extram_bank: 0,
-            wram:        [0; 0x4000],
+            wram:        [0; 0x8000],
+            wram_bank:   1,
             oam:         [0; 0xA0],
             io:          [0; 0x80],
             hram:        [0; 0x7F],
             ie:          0,
-            buttons: 0x0F,
-            dpad: 0x0F,     // nothing pressed
-            prev_joyp: 0x0F,
+            joypad: Joypad::new(),
+            dma: Dma::new(),
+            timer: Timer::new(),
+            serial: Serial::new(model.is_cgb()),
+            scheduler: Scheduler::new(),
+            timer_synced: 0,
+            serial_synced: 0,
+            dma_synced: 0,
+            model,
+            bg_palettes: ColorPalettes::new(),
+            obj_palettes: ColorPalettes::new(),
+            hdma: Hdma::default(),
+            double_speed: false,
+            speed_switch_armed: false,
+            sgb: (model == Model::Sgb).then(Sgb::new),
+            boot_rom: None,
+            watch: None,
+            cdl: None,
+            frozen: BTreeMap::new(),
+            cheats: Cheats::new(),
+            raster: Vec::new(),
+            ticked: 0,
+            stalled: 0,
         };
-        // Boot state
-        mmu.io[0x40] = 0x91; // LCDC
-        mmu.io[0x47] = 0xFC; // BGP
+        // Boot state: what the boot ROM would have left behind
+        for (addr, val) in boot::post_boot_io(model) {
+            match addr {
+                0xFF00 => { mmu.joypad.write(val); }
+                0xFF01 => mmu.serial.sb = val,
+                0xFF02 => mmu.serial.sc = val,
+                0xFF04..=0xFF07 => mmu.timer.set_register(addr, val),
+                _ => mmu.io[addr as usize - 0xFF00] = val,
+            }
+        }
+        // CGB registers backed by their own state start from the same values
+        if model.is_cgb() {
+            for addr in [0xFF4D, 0xFF4F, 0xFF68, 0xFF6A, 0xFF70] {
+                mmu.cgb_io_write(addr, mmu.io[addr as usize - 0xFF00]);
+            }
+        }
+        mmu.scheduler.schedule(0, Event::Ppu);
+        mmu.schedule_timer();
         mmu
     }
+
+    /// Maps a boot ROM over the cartridge and puts I/O back to its power-on state.
+    /// Pair with `Cpu::power_on` so execution starts at 0x0000.
+    pub fn map_boot_rom(&mut self, boot: Vec<u8>) -> Result<(), String> {
+        boot::validate(self.model, &boot)?;
+        self.io = [0; 0x80];
+        self.timer = Timer::new();
+        self.timer_synced = self.scheduler.now();
+        self.schedule_timer();
+        (self.serial.sb, self.serial.sc) = (0, 0);
+        self.scheduler.cancel(Event::Serial);
+        self.boot_rom = Some(boot);
+        Ok(())
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
     // save data
     pub fn get_save_data(&self) -> Vec<u8> {
         self.extram.clone()
@@ -51,41 +152,339 @@ impl Mmu {
             self.extram[..data.len()].copy_from_slice(&data);
         }
     }
+
+    /// New input state from the frontend; a newly pressed selected button raises the joypad interrupt
+    pub fn set_buttons(&mut self, pressed: Buttons) {
+        if self.joypad.set_pressed(pressed) { self.io[0x0F] |= 0x10; }
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.model.is_cgb()
+    }
+
+    /// VRAM byte from an explicit bank, as the CGB PPU fetches it
+    pub fn vram_at(&self, bank: usize, addr: u16) -> u8 {
+        self.vram[bank * 0x2000 + (addr as usize & 0x1FFF)]
+    }
+
+    fn wram_index(&self, addr: u16) -> usize {
+        let off = addr as usize & 0x1FFF;
+        if off < 0x1000 { off } else { self.wram_bank * 0x1000 + (off - 0x1000) }
+    }
+
+    /// Executes STOP. Returns true if it performed a pending CGB speed switch.
+    pub fn stop(&mut self) -> bool {
+        if self.is_cgb() && self.speed_switch_armed {
+            self.speed_switch_armed = false;
+            self.double_speed = !self.double_speed;
+            self.timer_write(0xFF04, 0); // DIV resets on the switch
+            return true;
+        }
+        false
+    }
+
+    /// Called by the PPU on every HBlank entry (LY 0-143)
+    pub fn hdma_hblank(&mut self) {
+        if self.hdma.hblank {
+            self.hdma_copy_block();
+        }
+    }
+
+    fn hdma_copy_block(&mut self) {
+        if let Some((src, dst)) = self.hdma.next_block() {
+            for j in 0..0x10 {
+                let v = self.dma_read(src.wrapping_add(j));
+                self.vram[self.vram_bank * 0x2000 + dst as usize + j as usize] = v;
+            }
+        }
+    }
+
+    /// Called by the PPU on VBlank entry; completes a pending SGB VRAM transfer
+    pub fn sgb_vblank(&mut self) {
+        if self.sgb.as_ref().is_none_or(|s| s.pending.is_none()) {
+            return;
+        }
+        let data = self.screen_tiles();
+        if let Some(sgb) = self.sgb.as_mut() {
+            sgb.finish_transfer(&data);
+        }
+    }
+
+    // The 256 tiles laid out 20 per row on the BG map, as the SGB sees them
+    fn screen_tiles(&self) -> Vec<u8> {
+        let lcdc = self.io[0x40];
+        let map_base: u16 = if lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+        (0..256u16).flat_map(|i| {
+            let idx = self.vram_at(0, map_base + (i / 20) * 32 + i % 20);
+            let addr = if lcdc & 0x10 != 0 {
+                0x8000 + idx as u16 * 16
+            } else {
+                (0x9000i32 + idx as i8 as i32 * 16) as u16
+            };
+            (0..16).map(move |j| self.vram_at(0, addr + j))
+        }).collect()
+    }
+
+    // --- CPU clock ---
+
+    /// Puts one M-cycle of the running instruction on the clock, so every bus access
+    /// happens at its own cycle. Due timer, serial and DMA events run straight away;
+    /// PPU events wait for the instruction to end.
+    pub fn tick(&mut self) {
+        self.scheduler.advance(4);
+        self.ticked += 4;
+        self.run_due();
+    }
+
+    fn run_due(&mut self) {
+        for event in [Event::Timer, Event::Serial, Event::Dma] {
+            if self.scheduler.take_due(event) { self.event(event); }
+        }
+    }
+
+    /// Puts the rest of an instruction that took `cycles` on the clock and returns
+    /// how long it really took, HDMA stalls included
+    pub fn finish_step(&mut self, cycles: u32) -> u32 {
+        let ticked = std::mem::take(&mut self.ticked);
+        self.scheduler.advance(cycles.saturating_sub(ticked));
+        cycles.max(ticked) + std::mem::take(&mut self.stalled)
+    }
+
+    // Holds the CPU while something else owns the bus
+    fn stall(&mut self, cycles: u32) {
+        self.scheduler.advance(cycles);
+        self.stalled += cycles;
+        self.run_due();
+    }
+
+    // --- Lazily clocked peripherals ---
+
+    /// Handles a due timer, serial or DMA event (the PPU's are handled by `GameBoy`)
+    pub fn event(&mut self, event: Event) {
+        match event {
+            Event::Timer => { self.sync_timer(); self.schedule_timer(); }
+            Event::Serial => { self.sync_serial(); self.schedule_serial(); }
+            Event::Dma => self.sync_dma(),
+            Event::Ppu => {}
+        }
+    }
+
+    /// Any interrupt requested and enabled, i.e. HALT would end now
+    pub fn interrupt_pending(&self) -> bool {
+        self.io[0x0F] & self.ie & 0x1F != 0
+    }
+
+    fn sync_timer(&mut self) {
+        let now = self.scheduler.now();
+        if self.timer.tick((now - self.timer_synced) as u32) {
+            self.io[0x0F] |= 0x04;
+        }
+        self.timer_synced = now;
+    }
+
+    // Even with no interrupt coming, wake up now and then so catch-up spans stay short
+    fn schedule_timer(&mut self) {
+        let until = self.timer.cycles_until_irq().unwrap_or(u32::MAX).min(0x10000);
+        self.scheduler.schedule(self.timer_synced + until as u64, Event::Timer);
+    }
+
+    fn timer_write(&mut self, addr: u16, val: u8) {
+        self.sync_timer();
+        self.timer.write(addr, val);
+        self.schedule_timer();
+    }
+
+    fn sync_serial(&mut self) {
+        let now = self.scheduler.now();
+        if self.serial.tick((now - self.serial_synced) as u32) {
+            self.io[0x0F] |= 0x08;
+        }
+        self.serial_synced = now;
+    }
+
+    fn schedule_serial(&mut self) {
+        match self.serial.cycles_until_event() {
+            Some(c) => self.scheduler.schedule(self.serial_synced + c as u64, Event::Serial),
+            None => self.scheduler.cancel(Event::Serial),
+        }
+    }
+
+    /// Copies every OAM DMA byte due by now
+    pub fn sync_dma(&mut self) {
+        let now = self.scheduler.now();
+        if self.dma.busy() {
+            for _ in 0..(now - self.dma_synced) / 4 {
+                if let Some((src, idx)) = self.dma.step() {
+                    let val = self.dma_read(src);
+                    self.dma.bus_value = val;
+                    self.oam[idx as usize] = val;
+                }
+            }
+        }
+        self.dma_synced = now;
+    }
+
+    // DMA state as of now without copying anything: (blocking the bus, byte on the source bus)
+    fn dma_view(&self) -> (bool, u8) {
+        let mut dma = self.dma.clone();
+        let mut last = None;
+        for _ in 0..(self.scheduler.now() - self.dma_synced) / 4 {
+            if let Some((src, _)) = dma.step() { last = Some(src); }
+            if !dma.busy() { break; }
+        }
+        (dma.is_active(), last.map_or(dma.bus_value, |src| self.read_raw(src)))
+    }
+
+    // CPU view of the bus: while OAM DMA runs, OAM reads 0xFF and the
+    // source bus returns whatever byte the DMA is currently moving
     pub fn read(&self, addr: u16) -> u8 {
+        let val = self.patched(addr, self.bus_read(addr));
+        if let Some(watch) = &self.watch { watch.note_read(addr, val); }
+        if let Some(cdl) = &self.cdl && let Some(offset) = self.rom_offset(addr) { cdl.note_read(offset, addr); }
+        val
+    }
+
+    /// A CPU instruction fetch: a read the code/data log counts as code
+    pub fn fetch(&self, addr: u16) -> u8 {
+        let val = self.patched(addr, self.bus_read(addr));
+        if let Some(watch) = &self.watch { watch.note_read(addr, val); }
+        if let Some(cdl) = &self.cdl { cdl.note_fetch(self.rom_offset(addr), addr, val); }
+        val
+    }
+
+    // Game Genie codes replace what the CPU reads from the cartridge ROM
+    fn patched(&self, addr: u16, val: u8) -> u8 {
+        if addr >= 0x8000 || !self.cheats.patches_rom() { return val; }
+        if let Some(boot) = &self.boot_rom && boot::maps(boot, addr) { return val; }
+        self.cheats.patch(addr, val)
+    }
+
+    /// Applies GameShark codes; called by the PPU on VBlank entry
+    pub fn apply_cheats(&mut self) {
+        let cheats = std::mem::take(&mut self.cheats);
+        for (bank, addr, val) in cheats.ram_writes() {
+            match bank {
+                Some(bank) if (0xD000..=0xDFFF).contains(&addr) => self.store_banked(bank, addr, val),
+                _ => self.store_ram(addr, val),
+            }
+        }
+        self.cheats = cheats;
+    }
+
+    // --- Raster log ---
+
+    // Records a write that changes an LCD register, placing it on the line by
+    // how far off the PPU's next event is
+    fn note_raster(&mut self, addr: u16, val: u8) {
+        let old = self.io[addr as usize - 0xFF00];
+        if old == val || self.raster.len() >= MAX_RASTER_EVENTS { return; }
+        let next = match self.io[0x41] & 0x03 { 2 => 80, 3 => 252, _ => 456 };
+        let left = self.scheduler.pending(Event::Ppu).map_or(0, |at| at.saturating_sub(self.scheduler.now()));
+        let left = if self.double_speed { left / 2 } else { left };
+        let dot = next - left.min(next as u64) as u32;
+        self.raster.push(RasterEvent { ly: self.io[0x44], dot: dot.min(455), addr, old, new: val });
+    }
+
+    /// Hands over the raster log; called by the PPU on VBlank entry
+    pub fn take_raster(&mut self) -> Vec<RasterEvent> {
+        std::mem::take(&mut self.raster)
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn rom_len(&self) -> usize {
+        self.rom.len()
+    }
+
+    /// Offset in the ROM file of the cartridge byte mapped at `addr`, if any
+    pub fn rom_offset(&self, addr: u16) -> Option<usize> {
+        if let Some(boot) = &self.boot_rom && boot::maps(boot, addr) { return None; }
+        let offset = match addr {
+            0x0000..=0x3FFF => addr as usize,
+            0x4000..=0x7FFF => self.rom_bank * 0x4000 + (addr as usize - 0x4000),
+            _ => return None,
+        };
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    // DMA engines reading the cartridge count as data reads
+    fn dma_read(&self, addr: u16) -> u8 {
+        if let Some(cdl) = &self.cdl && let Some(offset) = self.rom_offset(addr) { cdl.note_read(offset, addr); }
+        self.read_raw(addr)
+    }
+
+    /// Reads without DMA blocking or watchpoints, for debuggers
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.read_raw(addr)
+    }
+
+    /// The bank mapped at `addr`: ROM, VRAM, cartridge RAM or WRAM, else 0
+    pub fn bank_at(&self, addr: u16) -> usize {
+        match addr {
+            0x4000..=0x7FFF => self.rom_bank,
+            0x8000..=0x9FFF => self.vram_bank,
+            0xA000..=0xBFFF => self.extram_bank,
+            0xD000..=0xDFFF | 0xF000..=0xFDFF => self.wram_bank,
+            _ => 0,
+        }
+    }
+
+    fn bus_read(&self, addr: u16) -> u8 {
+        if self.dma.busy() {
+            let (active, bus_value) = self.dma_view();
+            if active {
+                match Bus::of(addr) {
+                    Bus::Internal if addr <= 0xFEFF => return 0xFF,
+                    Bus::Internal => {}
+                    bus if bus == self.dma.source_bus() => return bus_value,
+                    _ => {}
+                }
+            }
+        }
+        self.read_raw(addr)
+    }
+
+    fn read_raw(&self, addr: u16) -> u8 {
+        if let Some(boot) = &self.boot_rom && boot::maps(boot, addr) {
+            return boot[addr as usize];
+        }
         match addr {
             0x0000..=0x3FFF => self.rom[addr as usize],
             0x4000..=0x7FFF => self.rom[self.rom_bank * 0x4000 + (addr as usize - 0x4000)],
-            0x8000..=0x9FFF => self.vram[addr as usize - 0x8000],
+            0x8000..=0x9FFF => self.vram_at(self.vram_bank, addr),
             0xA000..=0xBFFF => {
                 let offset = self.extram_bank * 0x2000 + (addr as usize - 0xA000);
                 *self.extram.get(offset).unwrap_or(&0xFF)
             },
-            0xC000..=0xDFFF => self.wram[addr as usize - 0xC000],
-            0xE000..=0xFDFF => self.wram[addr as usize - 0xE000], // Echo RAM
+            0xC000..=0xFDFF => self.wram[self.wram_index(addr)], // E000+ is echo RAM
             0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00],
             0xFEA0..=0xFEFF => 0xFF,
             
             // --- IMPROVED IO HANDLING ---
-            0xFF00 => {
-                let select = self.io[0x00] & 0x30; // Only bits 4 & 5 matter for selection
-                let mut res = select | 0xC0;      // Bits 6 & 7 are always 1
-                
-                if (select & 0x10) == 0 { // Direction keys selected
-                    res |= self.dpad & 0x0F;
-                } else if (select & 0x20) == 0 { // Button keys selected
-                    res |= self.buttons & 0x0F;
-                } else {
-                    res |= 0x0F; // Nothing selected, bits are high
-                }
-                res
+            0xFF00 => match &self.sgb {
+                // SGB reports the current MLT_REQ controller while nothing is selected
+                Some(sgb) if self.joypad.select() == 0x30 => 0xF0 | sgb.joyp_id(),
+                _ => self.joypad.read(),
             },
-            0xFF00..=0xFF7F => self.io[addr as usize - 0xFF00],
+            0xFF01..=0xFF7F => self.io_read(addr),
             0xFF80..=0xFFFE => self.hram[addr as usize - 0xFF80],
             0xFFFF          => self.ie,
         }
     }
 
     pub fn write(&mut self, addr: u16, val: u8) {
+        if let Some(watch) = &self.watch { watch.note_write(addr, self.read_raw(addr), val); }
+        if self.dma.busy() { self.sync_dma(); }
+        if self.dma.is_active() {
+            match Bus::of(addr) {
+                Bus::Internal if addr <= 0xFEFF => return,
+                Bus::Internal => {}
+                bus if bus == self.dma.source_bus() => return,
+                _ => {}
+            }
+        }
         match addr {
             // MBC3 ROM bank select
             0x0000..=0x1FFF => {
@@ -95,42 +494,192 @@ impl Mmu {
                 self.rom_bank = if val == 0 { 1 } else { (val & 0x7F) as usize };
             }
             // MBC3 RAM bank select
-            0x4000..=0x5FFF => {
-                if val <= 3 { self.extram_bank = val as usize; }
+            0x4000..=0x5FFF if val <= 3 => self.extram_bank = val as usize,
+            0x8000..=0x9FFF => self.vram[self.vram_bank * 0x2000 + (addr as usize - 0x8000)] = val,
+            0xA000..=0xBFFF | 0xC000..=0xFDFF | 0xFF80..=0xFFFE if !self.is_frozen(addr) => self.store_ram(addr, val),
+            0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00] = val,
+            0xFF40 | 0xFF42 | 0xFF43 | 0xFF45 | 0xFF47..=0xFF4B => {
+                self.note_raster(addr, val);
+                self.io_write(addr, val);
             }
-            0x8000..=0x9FFF => self.vram[addr as usize - 0x8000] = val,
+            0xFF00..=0xFF7F => self.io_write(addr, val),
+            0xFFFF          => self.ie = val,
+            _               => {}
+        }
+    }
+
+    // Cartridge RAM, WRAM (and its echo) or HRAM: the writes without side effects
+    fn store_ram(&mut self, addr: u16, val: u8) {
+        match addr {
             0xA000..=0xBFFF => {
                 let offset = self.extram_bank * 0x2000 + (addr as usize - 0xA000);
                 if offset < self.extram.len() {
                     self.extram[offset] = val;
                 }
             }
-            0xC000..=0xDFFF => self.wram[addr as usize - 0xC000] = val,
-            0xE000..=0xFDFF => self.wram[addr as usize - 0xE000] = val,
-            0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00] = val,
-            0xFF00..=0xFF7F => self.io_write(addr, val),
+            0xC000..=0xFDFF => {
+                let i = self.wram_index(addr);
+                self.wram[i] = val;
+            }
             0xFF80..=0xFFFE => self.hram[addr as usize - 0xFF80] = val,
-            0xFFFF          => self.ie = val,
-            _               => {}
+            _ => {}
         }
     }
-    #[warn(dead_code)]
+
+    // Like `store_ram`, but into `bank` of cartridge RAM or WRAM D000-DFFF whatever is mapped
+    fn store_banked(&mut self, bank: usize, addr: u16, val: u8) {
+        match addr {
+            0xA000..=0xBFFF => {
+                let offset = bank * 0x2000 + (addr as usize - 0xA000);
+                if offset < self.extram.len() {
+                    self.extram[offset] = val;
+                }
+            }
+            0xD000..=0xDFFF => self.wram[bank * 0x1000 + (addr as usize - 0xD000)] = val,
+            _ => self.store_ram(addr, val),
+        }
+    }
+
+    // The (bank, address) a RAM byte is frozen under: echo RAM folds onto C000-DDFF
+    fn frozen_key(&self, addr: u16) -> (usize, u16) {
+        let addr = if (0xE000..=0xFDFF).contains(&addr) { addr - 0x2000 } else { addr };
+        (self.bank_at(addr), addr)
+    }
+
+    /// Holds a RAM byte, in the bank mapped now, at `val`: CPU writes to it
+    /// are dropped and it is rewritten at the start of every frame. Other
+    /// addresses are ignored.
+    pub fn freeze(&mut self, addr: u16, val: u8) {
+        if matches!(addr, 0xA000..=0xBFFF | 0xC000..=0xFDFF | 0xFF80..=0xFFFE) {
+            self.frozen.insert(self.frozen_key(addr), val);
+            self.store_ram(addr, val);
+        }
+    }
+
+    pub fn unfreeze(&mut self, addr: u16) {
+        self.frozen.remove(&self.frozen_key(addr));
+    }
+
+    /// Whether the byte mapped at `addr` is frozen
+    pub fn is_frozen(&self, addr: u16) -> bool {
+        self.frozen.contains_key(&self.frozen_key(addr))
+    }
+
+    /// Frozen bytes by (bank, address)
+    pub fn frozen(&self) -> &BTreeMap<(usize, u16), u8> {
+        &self.frozen
+    }
+
+    /// Rewrites every frozen byte into its own bank, mapped or not
+    pub fn apply_frozen(&mut self) {
+        let frozen = std::mem::take(&mut self.frozen);
+        for (&(bank, addr), &val) in &frozen { self.store_banked(bank, addr, val); }
+        self.frozen = frozen;
+    }
     fn io_read(&self, addr: u16) -> u8 {
-        self.io[addr as usize - 0xFF00]
+        if self.is_cgb() {
+            match addr {
+                0xFF4D => return 0x7E | (self.double_speed as u8) << 7 | self.speed_switch_armed as u8,
+                0xFF4F => return 0xFE | self.vram_bank as u8,
+                0xFF55 => return self.hdma.status(),
+                0xFF68 => return self.bg_palettes.read_index(),
+                0xFF69 => return self.bg_palettes.read_data(),
+                0xFF6A => return self.obj_palettes.read_index(),
+                0xFF6B => return self.obj_palettes.read_data(),
+                _ => {}
+            }
+        }
+        match addr {
+            0xFF01 | 0xFF02 => self.serial.read_at(addr, (self.scheduler.now() - self.serial_synced) as u32),
+            0xFF04..=0xFF07 => {
+                let mut timer = self.timer.clone();
+                timer.tick((self.scheduler.now() - self.timer_synced) as u32);
+                timer.read(addr)
+            }
+            _ => self.io[addr as usize - 0xFF00],
+        }
+    }
+
+    // KEY1, VBK, HDMA, palette RAM and SVBK. Returns false if `addr` is not one of them.
+    fn cgb_io_write(&mut self, addr: u16, val: u8) -> bool {
+        match addr {
+            0xFF4D => self.speed_switch_armed = val & 0x01 != 0,
+            0xFF4F => self.vram_bank = (val & 0x01) as usize,
+            0xFF51 => self.hdma.write_src_hi(val),
+            0xFF52 => self.hdma.write_src_lo(val),
+            0xFF53 => self.hdma.write_dst_hi(val),
+            0xFF54 => self.hdma.write_dst_lo(val),
+            0xFF55 => {
+                if self.hdma.hblank && val & 0x80 == 0 {
+                    // Writing bit 7 = 0 cancels a running HBlank transfer
+                    self.hdma.hblank = false;
+                } else {
+                    self.hdma.blocks = (val & 0x7F) + 1;
+                    if val & 0x80 != 0 {
+                        self.hdma.hblank = true;
+                    } else {
+                        // General-purpose DMA copies everything at once and halts the
+                        // CPU for 8 us per block, twice the cycles in double speed
+                        let blocks = self.hdma.blocks as u32;
+                        while self.hdma.blocks > 0 { self.hdma_copy_block(); }
+                        self.stall(blocks * if self.double_speed { 64 } else { 32 });
+                    }
+                }
+            }
+            0xFF68 => self.bg_palettes.write_index(val),
+            0xFF69 => self.bg_palettes.write_data(val),
+            0xFF6A => self.obj_palettes.write_index(val),
+            0xFF6B => self.obj_palettes.write_data(val),
+            // SVBK reads back as written, but bank 0 selects bank 1
+            0xFF70 => {
+                self.io[0x70] = val | 0xF8;
+                self.wram_bank = ((val & 0x07) as usize).max(1);
+            }
+            _ => return false,
+        }
+        true
     }
 
     fn io_write(&mut self, addr: u16, val: u8) {
         let i = addr as usize - 0xFF00;
+        if self.is_cgb() && self.cgb_io_write(addr, val) {
+            return;
+        }
         match addr {
             // DMA transfer to OAM
             0xFF46 => {
-                let src = (val as u16) << 8;
-                for j in 0..0xA0u16 {
-                    self.oam[j as usize] = self.read(src + j);
+                self.io[i] = val;
+                self.sync_dma();
+                self.dma.start(val);
+                self.scheduler.schedule_in(self.dma.remaining() as u64 * 4, Event::Dma);
+            }
+            // Turning the LCD on or off restarts the PPU right away
+            0xFF40 => {
+                let toggled = (self.io[i] ^ val) & 0x80 != 0;
+                self.io[i] = val;
+                if toggled {
+                    if val & 0x80 == 0 {
+                        self.io[0x44] = 0;
+                        self.io[0x41] &= 0xFC;
+                    }
+                    self.scheduler.schedule_in(0, Event::Ppu);
                 }
             }
-            // DIV resets to 0 on any write
-            0xFF04 => self.io[i] = 0,
+            0xFF00 => {
+                if self.joypad.write(val) { self.io[0x0F] |= 0x10; }
+                if let Some(sgb) = self.sgb.as_mut() { sgb.write_joyp(val); }
+            }
+            0xFF01 | 0xFF02 => {
+                self.sync_serial();
+                self.serial.write(addr, val, self.scheduler.now());
+                self.schedule_serial();
+            }
+            0xFF04..=0xFF07 => self.timer_write(addr, val),
+            // Boot ROM lock: once unmapped it stays unmapped
+            0xFF50 => {
+                self.io[i] = val;
+                if val != 0 { self.boot_rom = None; }
+            }
             _      => self.io[i] = val,
         }
     }
@@ -0,0 +1,39 @@
+// Hardware model the core emulates, picked from the cartridge header.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Model {
+    #[default]
+    Dmg,
+    Mgb, // Game Boy Pocket
+    Sgb,
+    Cgb,
+}
+
+impl Model {
+    /// CGB flag at 0x0143: bit 7 set means the game supports (0x80) or requires (0xC0) CGB
+    pub fn from_header(rom: &[u8]) -> Self {
+        match rom.get(0x143) {
+            Some(flag) if flag & 0x80 != 0 => Model::Cgb,
+            _ => Model::Dmg,
+        }
+    }
+
+    /// SGB flag at 0x0146 only counts with the new licensee code (0x33 at 0x014B)
+    pub fn supports_sgb(rom: &[u8]) -> bool {
+        rom.get(0x146) == Some(&0x03) && rom.get(0x14B) == Some(&0x33)
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "dmg" => Some(Model::Dmg),
+            "mgb" => Some(Model::Mgb),
+            "sgb" => Some(Model::Sgb),
+            "cgb" => Some(Model::Cgb),
+            _ => None,
+        }
+    }
+
+    pub fn is_cgb(self) -> bool {
+        self == Model::Cgb
+    }
+}
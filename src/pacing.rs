@@ -0,0 +1,115 @@
+// Real-time frame pacing. A frame is MAX_FRAME_CYCLES dots of the 4.194304 MHz
+// clock, so the Game Boy runs at ~59.73 frames per second, not the host's
+// refresh rate. The pacer turns elapsed wall-clock time into a number of
+// frames to emulate, scaled by the speed multiplier.
+
+use std::time::Duration;
+
+use crate::MAX_FRAME_CYCLES;
+
+pub const CLOCK_HZ: f64 = 4_194_304.0;
+pub const FRAME_RATE: f64 = CLOCK_HZ / MAX_FRAME_CYCLES as f64;
+
+/// Fixed speed multipliers, slowest to fastest
+pub const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL: usize = 2;
+
+// After a stall (window dragged, tab hidden) don't try to catch up more than this
+const MAX_BACKLOG_SECS: f64 = 0.1;
+
+pub fn frame_duration() -> Duration {
+    Duration::from_secs_f64(1.0 / FRAME_RATE)
+}
+
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    speed: usize, // index into SPEEDS
+    owed: f64,    // frames due but not yet emulated
+}
+
+impl Default for Pacer {
+    fn default() -> Self { Self::new() }
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Self { speed: NORMAL, owed: 0.0 }
+    }
+
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    /// Picks the closest fixed multiplier
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = (0..SPEEDS.len())
+            .min_by(|&a, &b| (SPEEDS[a] - speed).abs().total_cmp(&(SPEEDS[b] - speed).abs()))
+            .unwrap();
+    }
+
+    pub fn faster(&mut self) -> f64 {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+        self.speed()
+    }
+
+    pub fn slower(&mut self) -> f64 {
+        self.speed = self.speed.saturating_sub(1);
+        self.speed()
+    }
+
+    /// Forgets any backlog, e.g. after pausing or fast-forwarding
+    pub fn reset(&mut self) {
+        self.owed = 0.0;
+    }
+
+    /// Accounts for `elapsed` wall-clock time and returns how many frames to run now
+    pub fn frames_due(&mut self, elapsed: Duration) -> u32 {
+        let rate = FRAME_RATE * self.speed();
+        self.owed = (self.owed + elapsed.as_secs_f64() * rate).min(rate * MAX_BACKLOG_SECS + 1.0);
+        let n = self.owed.floor();
+        self.owed -= n;
+        n as u32
+    }
+
+    /// Wall-clock time until the next frame is due
+    pub fn until_next(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.owed) / (FRAME_RATE * self.speed()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_due_carries_the_remainder() {
+        let mut pacer = Pacer::new();
+        assert_eq!(pacer.frames_due(Duration::from_millis(50)), 2);  // 2.99 frames
+        assert_eq!(pacer.frames_due(Duration::from_millis(1)), 1);   // 0.99 + 0.06 carried over
+        let next = pacer.until_next().as_secs_f64();
+        assert!((0.0159..0.0161).contains(&next), "{next}");
+        pacer.set_speed(2.0);
+        assert_eq!(pacer.frames_due(Duration::from_millis(50)), 6);  // 0.05 + 5.97
+    }
+
+    #[test]
+    fn speeds_snap_to_the_nearest_step() {
+        let mut pacer = Pacer::new();
+        let snapped = [0.3, 0.7, 3.0, 100.0, -1.0].map(|s| { pacer.set_speed(s); pacer.speed() });
+        assert_eq!(snapped, [0.25, 0.5, 2.0, 8.0, 0.25]); // a tie goes to the slower step
+        assert_eq!((pacer.slower(), pacer.faster(), pacer.faster()), (0.25, 0.5, 1.0));
+        pacer.set_speed(8.0);
+        assert_eq!(pacer.faster(), 8.0);
+    }
+
+    #[test]
+    fn backlog_is_capped_and_reset() {
+        let mut pacer = Pacer::new();
+        assert_eq!(pacer.frames_due(Duration::from_secs(1)), 6); // 0.1 s worth plus the frame in progress
+        pacer.set_speed(4.0);
+        assert_eq!(pacer.frames_due(Duration::from_secs(1)), 24);
+        pacer.reset();
+        assert_eq!(pacer.frames_due(Duration::ZERO), 0);
+        assert_eq!(pacer.until_next(), Duration::from_secs_f64(1.0 / (FRAME_RATE * 4.0)));
+    }
+}
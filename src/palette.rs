@@ -0,0 +1,119 @@
+// Colour palettes and output pixel formats.
+//
+// The PPU only produces 2-bit shades; a Palette maps those four shades to RGB.
+// BG/window, OBJ0 and OBJ1 each carry their own palette, like the GBC does
+// when it colourises DMG games.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 3]; 4]); // RGB for shade 0 (lightest) .. shade 3 (darkest)
+
+impl Palette {
+    pub const GREY: Palette = Palette::from_rgb([0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000]);
+    pub const DMG_GREEN: Palette = Palette::from_rgb([0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F]);
+    pub const POCKET: Palette = Palette::from_rgb([0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F]);
+    pub const LIGHT: Palette = Palette::from_rgb([0x00B581, 0x009A71, 0x00694A, 0x004F3B]);
+
+    pub const PRESETS: [(&'static str, Palette); 4] = [
+        ("grey", Palette::GREY),
+        ("dmg", Palette::DMG_GREEN),
+        ("pocket", Palette::POCKET),
+        ("light", Palette::LIGHT),
+    ];
+
+    /// Builds a custom palette from four 0xRRGGBB colours, lightest first.
+    pub const fn from_rgb(c: [u32; 4]) -> Self {
+        let mut out = [[0u8; 3]; 4];
+        let mut i = 0;
+        while i < 4 {
+            out[i] = [(c[i] >> 16) as u8, (c[i] >> 8) as u8, c[i] as u8];
+            i += 1;
+        }
+        Palette(out)
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::PRESETS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, p)| *p)
+    }
+
+    #[inline(always)]
+    pub fn rgb(&self, shade: u8) -> [u8; 3] {
+        self.0[(shade & 0x03) as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self { Palette::GREY }
+}
+
+// Which palette a pixel is coloured with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Bg = 0,
+    Obj0 = 1,
+    Obj1 = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba8,  // 4 bytes: R, G, B, A (canvas ImageData layout)
+    Argb32, // u32 0xAARRGGBB (minifb), stored little-endian
+    Rgb565, // u16, stored little-endian
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Argb32 => 4,
+            PixelFormat::Rgb565 => 2,
+        }
+    }
+}
+
+#[inline(always)]
+pub fn argb32([r, g, b]: [u8; 3]) -> u32 {
+    0xFF00_0000 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+#[inline(always)]
+pub fn rgb565([r, g, b]: [u8; 3]) -> u16 {
+    (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::Ppu;
+
+    #[test]
+    fn presets_by_name() {
+        assert_eq!(Palette::by_name("DMG"), Some(Palette::DMG_GREEN));
+        assert_eq!(Palette::by_name("pocket"), Some(Palette::POCKET));
+        assert_eq!(Palette::by_name("sepia"), None);
+        assert_eq!(Palette::default(), Palette::GREY);
+    }
+
+    #[test]
+    fn from_rgb_splits_channels() {
+        let p = Palette::from_rgb([0x123456, 0xABCDEF, 0x000000, 0xFF0080]);
+        assert_eq!(p.0, [[0x12, 0x34, 0x56], [0xAB, 0xCD, 0xEF], [0, 0, 0], [0xFF, 0x00, 0x80]]);
+        assert_eq!(p.rgb(5), [0xAB, 0xCD, 0xEF]); // only the low two bits pick the shade
+    }
+
+    #[test]
+    fn pixel_formats_pack_little_endian() {
+        assert_eq!(argb32([0x12, 0x34, 0x56]), 0xFF12_3456);
+        assert_eq!(rgb565([0xFF, 0x00, 0xFF]), 0xF81F);
+        assert_eq!(rgb565([0x08, 0x04, 0x08]), 0x0821);
+        assert_eq!([PixelFormat::Rgba8, PixelFormat::Argb32, PixelFormat::Rgb565].map(|f| f.bytes_per_pixel()), [4, 4, 2]);
+
+        // Pixel 0 is BG shade 1, pixel 1 OBJ0 shade 3 in a custom sprite palette
+        let mut ppu = Ppu::new();
+        ppu.pixels[0] = 1 | (Layer::Bg as u8) << 2;
+        ppu.pixels[1] = 3 | (Layer::Obj0 as u8) << 2;
+        ppu.set_palette(Layer::Obj0, Palette::from_rgb([0, 0, 0, 0x102030]));
+        assert_eq!(ppu.frame(PixelFormat::Rgba8)[..8], [0xAA, 0xAA, 0xAA, 0xFF, 0x10, 0x20, 0x30, 0xFF]);
+        assert_eq!(ppu.frame(PixelFormat::Argb32)[..8], [0xAA, 0xAA, 0xAA, 0xFF, 0x30, 0x20, 0x10, 0xFF]);
+        assert_eq!(ppu.frame(PixelFormat::Rgb565)[..4], [0x55, 0xAD, 0x06, 0x11]);
+        assert_eq!(ppu.framebuffer[4..8], [0x10, 0x20, 0x30, 0xFF]);
+    }
+}
@@ -1,78 +1,232 @@
+use crate::cgb::bgr555_to_rgb;
 use crate::mmu::Mmu;
+use crate::palette::{argb32, rgb565, Layer, Palette, PixelFormat};
+
+const HIGHLIGHT: u8 = 0x10;     // `pixels` bit marking a highlighted sprite pixel
+const HIGHLIGHT_CGB: u16 = 0x8000; // the same in `colors`, above BGR555
+
+/// The LCD registers a line was drawn with, read when it is rendered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LineRegs {
+    pub lcdc: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub bgp: u8,
+}
+
+impl LineRegs {
+    fn capture(mmu: &Mmu) -> Self {
+        let io = &mmu.io;
+        Self { lcdc: io[0x40], scy: io[0x42], scx: io[0x43], wy: io[0x4A], wx: io[0x4B], bgp: io[0x47] }
+    }
+}
+
+/// A CPU write that changed an LCD register, with where the beam was
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RasterEvent {
+    pub ly: u8,
+    pub dot: u32, // to the instruction, 0-455
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
 
 pub struct Ppu {
-    pub framebuffer: [u8; 160 * 144 * 4],
-    pub dot: u32,
+    pub framebuffer: [u8; 160 * 144 * 4], // RGBA8, refreshed per scanline for the WASM zero-copy path
+    pub pixels: [u8; 160 * 144],          // bits 0-1: shade, bits 2-3: Layer
+    pub palettes: [Palette; 3],           // indexed by Layer
+    pub colors: [u16; 160 * 144],         // BGR555, used instead of `pixels` in CGB mode
+    pub cgb: bool,
+    pub dot: u32,          // line position of the next event: 0, 80 or 252
     pub ly:  u8,
+    pub frame_done: bool,  // set on VBlank entry
+    // Debug layer switches; hidden BG draws colour 0, a hidden window shows the BG
+    pub show_bg: bool,
+    pub show_window: bool,
+    pub show_sprites: bool,
+    pub highlight_sprites: bool, // tint sprite pixels magenta
+    pub line_regs: [LineRegs; 144],   // the last complete frame
+    pub raster_events: Vec<RasterEvent>, // register writes between the last two VBlanks
+    next_regs: [LineRegs; 144],       // the frame being drawn
+    lcd_on: bool,          // false until the first line after the LCD is switched on
+}
+
+impl Default for Ppu {
+    fn default() -> Self { Self::new() }
 }
 
 impl Ppu {
     pub fn new() -> Self {
-        Self { framebuffer: [0xFF; 160 * 144 * 4], dot: 0, ly: 0 }
+        Self {
+            framebuffer: [0xFF; 160 * 144 * 4],
+            pixels: [0; 160 * 144],
+            palettes: [Palette::GREY; 3],
+            colors: [0x7FFF; 160 * 144],
+            cgb: false,
+            dot: 0,
+            ly: 0,
+            frame_done: false,
+            show_bg: true,
+            show_window: true,
+            show_sprites: true,
+            highlight_sprites: false,
+            line_regs: [LineRegs::default(); 144],
+            raster_events: Vec::new(),
+            next_regs: [LineRegs::default(); 144],
+            lcd_on: false,
+        }
+    }
+
+    pub fn set_palette(&mut self, layer: Layer, palette: Palette) {
+        self.palettes[layer as usize] = palette;
+        (0..144).for_each(|y| self.flush_line(y));
     }
 
-    pub fn tick(&mut self, cycles: u32, mmu: &mut Mmu) {
+    pub fn set_all_palettes(&mut self, palette: Palette) {
+        self.palettes = [palette; 3];
+        (0..144).for_each(|y| self.flush_line(y));
+    }
+
+    /// The switch for layer `name`: bg, window, sprites or highlight
+    pub fn layer(&mut self, name: &str) -> Result<&mut bool, String> {
+        match name {
+            "bg" => Ok(&mut self.show_bg),
+            "window" | "win" => Ok(&mut self.show_window),
+            "sprites" | "obj" => Ok(&mut self.show_sprites),
+            "highlight" => Ok(&mut self.highlight_sprites),
+            _ => Err(format!("unknown layer '{name}', expected bg, window, sprites or highlight")),
+        }
+    }
+
+    #[inline(always)]
+    fn rgb(&self, i: usize) -> [u8; 3] {
+        let (rgb, highlight) = if self.cgb {
+            let c = self.colors[i];
+            (bgr555_to_rgb(c & !HIGHLIGHT_CGB), c & HIGHLIGHT_CGB != 0)
+        } else {
+            let pixel = self.pixels[i];
+            (self.palettes[((pixel >> 2) & 0x03) as usize].rgb(pixel), pixel & HIGHLIGHT != 0)
+        };
+        if !highlight { return rgb; }
+        let [r, g, b] = rgb;
+        [r / 2 + 0x80, g / 2, b / 2 + 0x80]
+    }
+
+    /// Converts the current frame into `out`, which must hold 160*144 pixels of `format`.
+    pub fn write_frame(&self, format: PixelFormat, out: &mut [u8]) {
+        let bpp = format.bytes_per_pixel();
+        for (i, dst) in out.chunks_exact_mut(bpp).take(160 * 144).enumerate() {
+            let rgb = self.rgb(i);
+            match format {
+                PixelFormat::Rgba8 => dst.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 0xFF]),
+                PixelFormat::Argb32 => dst.copy_from_slice(&argb32(rgb).to_le_bytes()),
+                PixelFormat::Rgb565 => dst.copy_from_slice(&rgb565(rgb).to_le_bytes()),
+            }
+        }
+    }
+
+    pub fn frame(&self, format: PixelFormat) -> Vec<u8> {
+        let mut out = vec![0; 160 * 144 * format.bytes_per_pixel()];
+        self.write_frame(format, &mut out);
+        out
+    }
+
+    /// ARGB32 words as minifb expects them, one per pixel
+    pub fn write_argb32(&self, out: &mut [u32]) {
+        for (i, dst) in out.iter_mut().take(160 * 144).enumerate() {
+            *dst = argb32(self.rgb(i));
+        }
+    }
+
+    /// Handles the PPU event that is due and returns the dots until the next one.
+    /// A line is OAM search until dot 80, pixel transfer until 252, then HBlank
+    /// until 456; lines 144-153 are VBlank.
+    pub fn event(&mut self, mmu: &mut Mmu) -> u32 {
         let lcdc = mmu.io[0x40];
-        if lcdc & 0x80 == 0 { 
-            self.ly = 0; 
-            self.dot = 0; 
+        if lcdc & 0x80 == 0 {
+            self.ly = 0;
+            self.dot = 0;
+            self.lcd_on = false;
             mmu.io[0x44] = 0;
             // Reset STAT to Mode 0 when LCD is off
-            mmu.io[0x41] = mmu.io[0x41] & 0xFC; 
-            return; 
+            mmu.io[0x41] &= 0xFC;
+            // Idle; turning the LCD back on reschedules us straight away
+            return 456;
         }
 
-        self.dot += cycles;
+        match self.dot {
+            0 => {
+                // Line start: a freshly enabled LCD begins on line 0
+                if self.lcd_on {
+                    self.ly = (self.ly + 1) % 154;
+                }
+                self.lcd_on = true;
+                mmu.io[0x44] = self.ly;
 
-        // A scanline takes exactly 456 dots
-        if self.dot >= 456 {
-            self.dot -= 456;
-            self.ly = (self.ly + 1) % 154;
-            mmu.io[0x44] = self.ly;
+                // LYC Check: Bit 2 of STAT is set if LY == LYC
+                if self.ly == mmu.io[0x45] {
+                    mmu.io[0x41] |= 0x04;
+                    if mmu.io[0x41] & 0x40 != 0 { mmu.io[0x0F] |= 0x02; } // STAT IRQ
+                } else {
+                    mmu.io[0x41] &= !0x04;
+                }
 
-            // LYC Check: Bit 2 of STAT is set if LY == LYC
-            if self.ly == mmu.io[0x45] {
-                mmu.io[0x41] |= 0x04;
-                if mmu.io[0x41] & 0x40 != 0 { mmu.io[0x0F] |= 0x02; } // STAT IRQ
-            } else {
-                mmu.io[0x41] &= !0x04;
-            }
+                if self.ly == 144 {
+                    mmu.io[0x0F] |= 0x01; // Request V-Blank Interrupt
+                    mmu.sgb_vblank();
+                    mmu.apply_cheats();
+                    self.line_regs = self.next_regs;
+                    self.raster_events = mmu.take_raster();
+                    self.frame_done = true;
+                }
 
-            if self.ly == 144 {
-                mmu.io[0x0F] |= 0x01; // Request V-Blank Interrupt
+                if self.ly >= 144 {
+                    self.set_mode(1, mmu);
+                    456
+                } else {
+                    self.set_mode(2, mmu);
+                    self.dot = 80;
+                    80
+                }
+            }
+            80 => {
+                self.set_mode(3, mmu);
+                self.dot = 252;
+                172
+            }
+            _ => {
+                self.set_mode(0, mmu);
+                // Render exactly once per line (transition to H-Blank)
+                self.cgb = mmu.is_cgb();
+                self.next_regs[self.ly as usize] = LineRegs::capture(mmu);
+                if self.cgb {
+                    self.render_scanline_cgb(mmu, lcdc);
+                } else {
+                    self.render_scanline(mmu, lcdc);
+                }
+                self.flush_line(self.ly as usize);
+                mmu.hdma_hblank();
+                self.dot = 0;
+                204
             }
         }
+    }
 
-        // --- MODE SWITCHING (The Oak Fix) ---
+    fn set_mode(&mut self, mode: u8, mmu: &mut Mmu) {
         let mut stat = mmu.io[0x41];
-        let old_mode = stat & 0x03;
-        let new_mode = if self.ly >= 144 {
-            1 // Mode 1: V-Blank
-        } else if self.dot < 80 {
-            2 // Mode 2: OAM Search
-        } else if self.dot < 252 {
-            3 // Mode 3: Data Transfer
-        } else {
-            0 // Mode 0: H-Blank
-        };
+        if stat & 0x03 == mode { return; }
+        stat = (stat & 0xFC) | mode;
 
-        if old_mode != new_mode {
-            stat = (stat & 0xFC) | new_mode;
-            
-            // Mode Interrupts: Many games wait for these to progress
-            let interrupt = match new_mode {
-                0 => stat & 0x08 != 0, // H-Blank IRQ
-                1 => stat & 0x10 != 0, // V-Blank IRQ
-                2 => stat & 0x20 != 0, // OAM IRQ
-                _ => false,
-            };
-            if interrupt { mmu.io[0x0F] |= 0x02; } // Trigger STAT Interrupt
-            
-            // Render exactly once per line (transition to H-Blank)
-            if new_mode == 0 && self.ly < 144 {
-                self.render_scanline(mmu, lcdc);
-            }
-        }
+        // Mode Interrupts: Many games wait for these to progress
+        let interrupt = match mode {
+            0 => stat & 0x08 != 0, // H-Blank IRQ
+            1 => stat & 0x10 != 0, // V-Blank IRQ
+            2 => stat & 0x20 != 0, // OAM IRQ
+            _ => false,
+        };
+        if interrupt { mmu.io[0x0F] |= 0x02; } // Trigger STAT Interrupt
         mmu.io[0x41] = stat;
     }
 
@@ -83,23 +237,23 @@ impl Ppu {
 
         (0u8..160).for_each(|x| {
             // Flattened logic: use window if enabled and within bounds, else background
-            let (win, px, py) = if (lcdc & 0x20 != 0) && self.ly >= wy && x >= wx {
+            let (win, px, py) = if self.show_window && (lcdc & 0x20 != 0) && self.ly >= wy && x >= wx {
                 (true, x - wx, self.ly - wy)
             } else {
                 (false, x.wrapping_add(scx), self.ly.wrapping_add(scy))
             };
 
-            let color = self.get_bg_pixel(mmu, lcdc, px as u16, py as u16, bgp, win);
-            self.set_pixel(x as usize, self.ly as usize, color);
+            let color = if self.show_bg || win { self.get_bg_pixel(mmu, lcdc, px as u16, py as u16, bgp, win) } else { bgp & 0x03 };
+            self.set_pixel(x as usize, self.ly as usize, color, Layer::Bg);
         });
 
-        if lcdc & 0x02 != 0 { self.render_sprites(mmu); }
+        if lcdc & 0x02 != 0 && self.show_sprites { self.render_sprites(mmu); }
     }
 
     fn get_bg_pixel(&self, mmu: &Mmu, lcdc: u8, px: u16, py: u16, palette: u8, is_win: bool) -> u8 {
         let map_bit = if is_win { 0x40 } else { 0x08 };
         let map_base = if lcdc & map_bit != 0 { 0x9C00 } else { 0x9800 };
-        let tile_idx = mmu.read(map_base + (py / 8) * 32 + (px / 8));
+        let tile_idx = mmu.vram_at(0, map_base + (py / 8) * 32 + (px / 8));
         
         let tile_addr = if lcdc & 0x10 != 0 {
             0x8000 + (tile_idx as u16 * 16)
@@ -108,7 +262,7 @@ impl Ppu {
         };
 
         let row = tile_addr + (py % 8) * 2;
-        let (lo, hi) = (mmu.read(row), mmu.read(row + 1));
+        let (lo, hi) = (mmu.vram_at(0, row), mmu.vram_at(0, row + 1));
         let bit = 7 - (px % 8);
         let id = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
         (palette >> (id * 2)) & 0x03
@@ -129,16 +283,16 @@ impl Ppu {
             .take(10) 
             .for_each(|(s, sy)| {
                 let (sx, tile, attr) = (s[1] as i16 - 8, s[2], s[3]);
-                let pal = if attr & 0x10 != 0 { obp1 } else { obp0 };
+                let (pal, layer) = if attr & 0x10 != 0 { (obp1, Layer::Obj1) } else { (obp0, Layer::Obj0) };
                 let mut row = (current_ly as i16 - sy) as u16;
                 if attr & 0x40 != 0 { row = 7 - row; }
 
                 let addr = 0x8000 + (tile as u16 * 16) + (row * 2);
-                let (lo, hi) = (mmu.read(addr), mmu.read(addr + 1));
+                let (lo, hi) = (mmu.vram_at(0, addr), mmu.vram_at(0, addr + 1));
 
                 (0..8i16).for_each(|px| {
                     let tx = sx + px;
-                    if tx < 0 || tx >= 160 { return; }
+                    if !(0..160).contains(&tx) { return; }
                     let bit = if attr & 0x20 != 0 { px } else { 7 - px } as u8;
                     let id = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
                     
@@ -146,29 +300,108 @@ impl Ppu {
 
                     // Priority check
                     if attr & 0x80 != 0 {
-                        let i = (current_ly as usize * 160 + tx as usize) * 4;
-                        if self.framebuffer[i] != 0xFF { return; }
+                        let i = current_ly as usize * 160 + tx as usize;
+                        if self.pixels[i] & 0x03 != 0 { return; }
                     }
 
-                    let shade = self.shade((pal >> (id * 2)) & 0x03);
-                    self.set_raw(tx as usize, current_ly as usize, shade);
+                    let shade = (pal >> (id * 2)) & 0x03;
+                    self.set_pixel(tx as usize, current_ly as usize, shade, layer);
+                    if self.highlight_sprites { self.pixels[current_ly as usize * 160 + tx as usize] |= HIGHLIGHT; }
                 });
             });
     }
 
-    #[inline(always)]
-    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
-        self.set_raw(x, y, self.shade(color));
+    // CGB: tile attributes from VRAM bank 1, colour palette RAM, OAM-order sprite priority
+    fn render_scanline_cgb(&mut self, mmu: &Mmu, lcdc: u8) {
+        let ly = self.ly;
+        let (scx, scy) = (mmu.io[0x43], mmu.io[0x42]);
+        let (wx, wy) = (mmu.io[0x4B].wrapping_sub(7), mmu.io[0x4A]);
+        let row_start = ly as usize * 160;
+        let mut bg_info = [0u8; 160]; // colour id | BG-to-OAM priority (bit 7)
+
+        for x in 0u8..160 {
+            let (win, px, py) = if self.show_window && (lcdc & 0x20 != 0) && ly >= wy && x >= wx {
+                (true, x - wx, ly - wy)
+            } else {
+                (false, x.wrapping_add(scx), ly.wrapping_add(scy))
+            };
+            let map_bit = if win { 0x40 } else { 0x08 };
+            let map_base: u16 = if lcdc & map_bit != 0 { 0x9C00 } else { 0x9800 };
+            let map_addr = map_base + (py as u16 / 8) * 32 + (px as u16 / 8);
+            let tile_idx = mmu.vram_at(0, map_addr);
+            let attr = mmu.vram_at(1, map_addr);
+
+            let tile_addr = if lcdc & 0x10 != 0 {
+                0x8000 + (tile_idx as u16 * 16)
+            } else {
+                (0x9000i32 + (tile_idx as i8 as i32 * 16)) as u16
+            };
+            let mut row = py as u16 % 8;
+            if attr & 0x40 != 0 { row = 7 - row; }
+            let bank = ((attr >> 3) & 1) as usize;
+            let (lo, hi) = (mmu.vram_at(bank, tile_addr + row * 2), mmu.vram_at(bank, tile_addr + row * 2 + 1));
+            let bit = if attr & 0x20 != 0 { px % 8 } else { 7 - px % 8 };
+            let id = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
+            if !self.show_bg && !win {
+                self.colors[row_start + x as usize] = mmu.bg_palettes.color(0, 0);
+                continue;
+            }
+
+            bg_info[x as usize] = id | (attr & 0x80);
+            self.colors[row_start + x as usize] = mmu.bg_palettes.color(attr & 0x07, id);
+        }
+
+        if lcdc & 0x02 == 0 || !self.show_sprites { return; }
+
+        let height: i16 = if lcdc & 0x04 != 0 { 16 } else { 8 };
+        let visible: Vec<&[u8]> = mmu.oam.chunks_exact(4)
+            .filter(|s| {
+                let sy = s[0] as i16 - 16;
+                (ly as i16) >= sy && (ly as i16) < sy + height
+            })
+            .take(10)
+            .collect();
+
+        // Draw back to front so the lowest OAM index ends up on top
+        for s in visible.iter().rev() {
+            let (sy, sx, attr) = (s[0] as i16 - 16, s[1] as i16 - 8, s[3]);
+            let tile = if height == 16 { s[2] & 0xFE } else { s[2] };
+            let mut row = (ly as i16 - sy) as u16;
+            if attr & 0x40 != 0 { row = height as u16 - 1 - row; }
+
+            let bank = ((attr >> 3) & 1) as usize;
+            let addr = 0x8000 + tile as u16 * 16 + row * 2;
+            let (lo, hi) = (mmu.vram_at(bank, addr), mmu.vram_at(bank, addr + 1));
+
+            for px in 0..8i16 {
+                let tx = sx + px;
+                if !(0..160).contains(&tx) { continue; }
+                let bit = if attr & 0x20 != 0 { px } else { 7 - px } as u8;
+                let id = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
+                if id == 0 { continue; }
+
+                // LCDC bit 0 clear gives sprites master priority over the background
+                let bg = bg_info[tx as usize];
+                if lcdc & 0x01 != 0 && bg & 0x03 != 0 && (attr & 0x80 != 0 || bg & 0x80 != 0) {
+                    continue;
+                }
+                let highlight = if self.highlight_sprites { HIGHLIGHT_CGB } else { 0 };
+                self.colors[row_start + tx as usize] = mmu.obj_palettes.color(attr & 0x07, id) | highlight;
+            }
+        }
     }
 
     #[inline(always)]
-    fn set_raw(&mut self, x: usize, y: usize, shade: u8) {
-        let i = (y * 160 + x) * 4;
-        self.framebuffer[i..i+3].fill(shade);
-        self.framebuffer[i+3] = 0xFF;
+    fn set_pixel(&mut self, x: usize, y: usize, shade: u8, layer: Layer) {
+        self.pixels[y * 160 + x] = shade | (layer as u8) << 2;
     }
 
-    fn shade(&self, color: u8) -> u8 {
-        match color { 0 => 0xFF, 1 => 0xAA, 2 => 0x55, _ => 0x00 }
+    // Re-colours one row of the RGBA8 framebuffer from the shade indices
+    fn flush_line(&mut self, y: usize) {
+        for x in 0..160 {
+            let [r, g, b] = self.rgb(y * 160 + x);
+            let i = (y * 160 + x) * 4;
+            self.framebuffer[i..i + 4].copy_from_slice(&[r, g, b, 0xFF]);
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,274 @@
+// Game Boy Printer on the far end of the link cable.
+//
+// The console is always the master. A packet is
+//   0x88 0x33 command compression len_lo len_hi data[len] sum_lo sum_hi 0x00 0x00
+// where the checksum is the 16-bit sum of command..data. While the last two
+// bytes are clocked out the printer answers 0x81 (alive) and then its status.
+//
+// Commands: INIT (0x01) clears the buffer, DATA (0x04) appends 2bpp tiles
+// (optionally RLE compressed, 640 bytes = one 160x16 band), PRINT (0x02)
+// prints the buffer and STATUS (0x0F) only reports. Every print becomes a
+// greyscale `Strip`, optionally written out as a PGM file; the frontend
+// reports how each write went.
+
+use std::cell::RefCell;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::serial::SerialEndpoint;
+
+pub const PRINT_WIDTH: usize = 160;
+const BAND_BYTES: usize = 0x280; // 20 tiles x 2 rows x 16 bytes
+const BUFFER_BYTES: usize = BAND_BYTES * 9;
+const BUSY_POLLS: u8 = 4; // STATUS packets answered "printing" after each PRINT
+
+const CMD_INIT: u8 = 0x01;
+const CMD_PRINT: u8 = 0x02;
+const CMD_DATA: u8 = 0x04;
+const CMD_STATUS: u8 = 0x0F;
+
+// Status bits
+const CHECKSUM_ERROR: u8 = 0x01;
+const PRINTING: u8 = 0x02;
+const IMAGE_FULL: u8 = 0x04;
+const UNPROCESSED: u8 = 0x08;
+const PACKET_ERROR: u8 = 0x10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage { Magic1, Magic2, Command, Compression, LenLo, LenHi, Data, SumLo, SumHi, Alive, Status }
+
+/// Where a printout was written, and whether the write worked
+pub type Saved = (PathBuf, io::Result<()>);
+
+/// One printed image, 0x00 black to 0xFF white
+#[derive(Clone, Debug)]
+pub struct Strip {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Strip {
+    /// Binary greyscale PGM (P5)
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut out = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.pixels);
+        out
+    }
+}
+
+pub struct Printer {
+    stage: Stage,
+    command: u8,
+    compressed: bool,
+    len: u16,
+    packet: Vec<u8>,
+    sum: u16,
+    received_sum: u16,
+    buffer: Vec<u8>,
+    status: u8,
+    busy: u8,
+    strips: Rc<RefCell<Vec<Strip>>>,
+    out_dir: Option<PathBuf>,
+    next_file: u32,
+    saved: Rc<RefCell<Vec<Saved>>>,
+}
+
+impl Default for Printer {
+    fn default() -> Self { Self::new() }
+}
+
+impl Printer {
+    /// Keeps printed strips in memory only
+    pub fn new() -> Self {
+        Self {
+            stage: Stage::Magic1, command: 0, compressed: false, len: 0, packet: Vec::new(),
+            sum: 0, received_sum: 0, buffer: Vec::new(), status: 0, busy: 0,
+            strips: Rc::new(RefCell::new(Vec::new())), out_dir: None, next_file: 1,
+            saved: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Also writes every strip to `dir` as print-NNNN.pgm
+    pub fn saving_to(dir: impl Into<PathBuf>) -> Self {
+        Self { out_dir: Some(dir.into()), ..Self::new() }
+    }
+
+    /// Shared handle to the printed strips, still readable after the printer is plugged in
+    pub fn strips(&self) -> Rc<RefCell<Vec<Strip>>> {
+        self.strips.clone()
+    }
+
+    /// Shared handle to the outcome of every file write, for the frontend to report and drain
+    pub fn saved(&self) -> Rc<RefCell<Vec<Saved>>> {
+        self.saved.clone()
+    }
+
+    fn receive(&mut self, byte: u8) {
+        self.stage = match self.stage {
+            Stage::Magic1 if byte == 0x88 => Stage::Magic2,
+            Stage::Magic1 => Stage::Magic1,
+            Stage::Magic2 if byte == 0x33 => Stage::Command,
+            Stage::Magic2 if byte == 0x88 => Stage::Magic2,
+            Stage::Magic2 => Stage::Magic1,
+            Stage::Command => {
+                self.command = byte;
+                self.sum = byte as u16;
+                Stage::Compression
+            }
+            Stage::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                Stage::LenLo
+            }
+            Stage::LenLo => {
+                self.len = byte as u16;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                Stage::LenHi
+            }
+            Stage::LenHi => {
+                self.len |= (byte as u16) << 8;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                self.packet.clear();
+                if self.len == 0 { Stage::SumLo } else { Stage::Data }
+            }
+            Stage::Data => {
+                self.packet.push(byte);
+                self.sum = self.sum.wrapping_add(byte as u16);
+                if self.packet.len() == self.len as usize { Stage::SumLo } else { Stage::Data }
+            }
+            Stage::SumLo => {
+                self.received_sum = byte as u16;
+                Stage::SumHi
+            }
+            Stage::SumHi => {
+                self.received_sum |= (byte as u16) << 8;
+                if self.received_sum == self.sum {
+                    self.status &= !CHECKSUM_ERROR;
+                    self.execute();
+                } else {
+                    self.status |= CHECKSUM_ERROR;
+                }
+                Stage::Alive
+            }
+            Stage::Alive => Stage::Status,
+            Stage::Status => Stage::Magic1,
+        };
+    }
+
+    fn execute(&mut self) {
+        match self.command {
+            CMD_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.busy = 0;
+            }
+            CMD_DATA => {
+                // An empty DATA packet only marks the end of the image
+                let data = std::mem::take(&mut self.packet);
+                if self.compressed { decompress(&data, &mut self.buffer) } else { self.buffer.extend_from_slice(&data) }
+                self.buffer.truncate(BUFFER_BYTES);
+                if !self.buffer.is_empty() { self.status |= UNPROCESSED; }
+                if self.buffer.len() >= BUFFER_BYTES { self.status |= IMAGE_FULL; }
+            }
+            CMD_PRINT if self.packet.len() >= 4 => {
+                let (margins, palette, exposure) = (self.packet[1], self.packet[2], self.packet[3]);
+                let strip = render(&self.buffer, margins, palette, exposure);
+                if let Some(saved) = self.save(&strip) {
+                    self.saved.borrow_mut().push(saved);
+                }
+                self.strips.borrow_mut().push(strip);
+                self.buffer.clear();
+                self.status = (self.status & !(UNPROCESSED | IMAGE_FULL)) | PRINTING;
+                self.busy = BUSY_POLLS;
+            }
+            CMD_STATUS => {
+                if self.busy > 0 {
+                    self.busy -= 1;
+                    if self.busy == 0 { self.status &= !PRINTING; }
+                }
+            }
+            _ => self.status |= PACKET_ERROR,
+        }
+    }
+
+    fn save(&mut self, strip: &Strip) -> Option<Saved> {
+        let dir = self.out_dir.as_ref()?;
+        // Never overwrite printouts from an earlier session
+        let mut path = dir.join(format!("print-{:04}.pgm", self.next_file));
+        while path.exists() {
+            self.next_file += 1;
+            path = dir.join(format!("print-{:04}.pgm", self.next_file));
+        }
+        self.next_file += 1;
+        let result = std::fs::write(&path, strip.to_pgm());
+        Some((path, result))
+    }
+}
+
+impl SerialEndpoint for Printer {
+    fn exchange(&mut self, out: u8) -> u8 {
+        let reply = match self.stage {
+            Stage::Alive => 0x81,
+            Stage::Status => self.status,
+            _ => 0x00,
+        };
+        self.receive(out);
+        reply
+    }
+}
+
+// Control byte with bit 7 set: repeat the next byte (n & 0x7F) + 2 times.
+// Otherwise copy the next n + 1 bytes as they are.
+fn decompress(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        let ctrl = data[i];
+        i += 1;
+        if ctrl & 0x80 != 0 {
+            if let Some(&b) = data.get(i) {
+                out.extend(std::iter::repeat_n(b, (ctrl & 0x7F) as usize + 2));
+            }
+            i += 1;
+        } else {
+            let end = (i + ctrl as usize + 1).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+}
+
+// Margins are in band units (16 lines): high nibble before, low nibble after.
+// Exposure 0x40 is neutral; each step away from it lightens or darkens the
+// non-white shades slightly.
+fn render(buffer: &[u8], margins: u8, palette: u8, exposure: u8) -> Strip {
+    let palette = if palette == 0 { 0xE4 } else { palette };
+    let darken = ((exposure & 0x7F) as i32 - 0x40) / 2;
+    let grey = |shade: u8| -> u8 {
+        match (palette >> (shade * 2)) & 0x03 {
+            0 => 0xFF,
+            c => (0xFF - 0x55 * c as i32 - darken).clamp(0, 0xFF) as u8,
+        }
+    };
+
+    let before = (margins >> 4) as usize * 16;
+    let after = (margins & 0x0F) as usize * 16;
+    let rows = buffer.len() / BAND_BYTES * 16;
+    let height = before + rows + after;
+    let mut pixels = vec![0xFF; PRINT_WIDTH * height];
+
+    // A trailing partial band never made it to paper
+    for (t, tile) in buffer[..rows / 16 * BAND_BYTES].chunks_exact(16).enumerate() {
+        let (tx, ty) = (t % 20, t / 20);
+        for row in 0..8 {
+            let (lo, hi) = (tile[row * 2], tile[row * 2 + 1]);
+            for bit in 0..8 {
+                let shade = (((hi >> (7 - bit)) & 1) << 1) | ((lo >> (7 - bit)) & 1);
+                let y = before + ty * 8 + row;
+                pixels[y * PRINT_WIDTH + tx * 8 + bit] = grey(shade);
+            }
+        }
+    }
+    Strip { width: PRINT_WIDTH, height, pixels }
+}
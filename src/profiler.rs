@@ -0,0 +1,187 @@
+// Function profiler on top of the shadow call stack.
+//
+// Every distinct call path is a node in a tree, so attributing time is one
+// add per instruction. Instrumenting mode charges each instruction's cycles
+// to the path it ran in; sampling mode only looks every `interval` cycles and
+// charges the whole interval to whatever path was current. Exclusive time is
+// what a path's innermost function spent itself, inclusive time adds its
+// callees (counting a recursive function once per path). The folded export
+// ("a;b;c cycles" per line) feeds straight into flamegraph.pl or inferno.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::callstack::Change;
+use crate::symbols::Symbols;
+
+/// A function by entry point: (bank, address)
+pub type Func = (usize, u16);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Instrument,
+    Sample { interval: u64 },
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    func: Option<Func>, // None for the root: whatever ran before the first call we saw
+    parent: usize,
+    cycles: u64,
+}
+
+/// A named bank:address range charged for the instructions executed inside it
+#[derive(Clone, Debug)]
+pub struct Range {
+    pub name: String,
+    pub bank: Option<usize>,
+    pub start: u16,
+    pub end: u16, // inclusive
+    pub cycles: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FuncStats {
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    mode: Mode,
+    nodes: Vec<Node>,
+    children: HashMap<(usize, Func), usize>,
+    calls: HashMap<Func, u64>,
+    path: Vec<usize>,  // node per live frame; the current node is last
+    until_sample: u64,
+    total: u64,
+    ranges: Vec<Range>,
+}
+
+impl Profiler {
+    pub fn new(mode: Mode) -> Self {
+        let root = Node { func: None, parent: 0, cycles: 0 };
+        let until_sample = match mode { Mode::Sample { interval } => interval.max(1), Mode::Instrument => 0 };
+        Self { mode, nodes: vec![root], children: HashMap::new(), calls: HashMap::new(), path: vec![0], until_sample, total: 0, ranges: Vec::new() }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Cycles observed, sampled or not
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn add_range(&mut self, name: &str, bank: Option<usize>, start: u16, end: u16) {
+        self.ranges.push(Range { name: name.to_string(), bank, start, end, cycles: 0 });
+    }
+
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    /// Charges one instruction at `pc` (in `bank`) that took `cycles`, then
+    /// applies the stack change it caused
+    pub fn record(&mut self, pc: u16, bank: usize, cycles: u64, change: Change) {
+        self.total += cycles;
+        let weight = match self.mode {
+            Mode::Instrument => cycles,
+            Mode::Sample { interval } => {
+                // Every sample point that falls inside this instruction
+                let mut n = 0;
+                let mut left = cycles;
+                while left >= self.until_sample {
+                    left -= self.until_sample;
+                    self.until_sample = interval.max(1);
+                    n += 1;
+                }
+                self.until_sample -= left;
+                n * interval
+            }
+        };
+        if weight > 0 {
+            let node = *self.path.last().unwrap();
+            self.nodes[node].cycles += weight;
+            for r in self.ranges.iter_mut().filter(|r| (r.start..=r.end).contains(&pc) && r.bank.is_none_or(|b| b == bank)) {
+                r.cycles += weight;
+            }
+        }
+
+        // The root never pops, even when SP climbs above every frame we know
+        let keep = self.path.len().saturating_sub(change.popped).max(1);
+        self.path.truncate(keep);
+        if let Some(frame) = change.pushed {
+            let func = (frame.bank, frame.entry);
+            let parent = *self.path.last().unwrap();
+            let next = self.nodes.len();
+            let node = *self.children.entry((parent, func)).or_insert(next);
+            if node == next { self.nodes.push(Node { func: Some(func), parent, cycles: 0 }); }
+            *self.calls.entry(func).or_default() += 1;
+            self.path.push(node);
+        }
+    }
+
+    fn funcs_on_path(&self, mut node: usize) -> Vec<Option<Func>> {
+        let mut funcs = Vec::new();
+        loop {
+            funcs.push(self.nodes[node].func);
+            if node == 0 { break; }
+            node = self.nodes[node].parent;
+        }
+        funcs.reverse();
+        funcs
+    }
+
+    /// Per-function totals; the root (code outside any call seen) is keyed None
+    pub fn functions(&self) -> HashMap<Option<Func>, FuncStats> {
+        let mut stats: HashMap<Option<Func>, FuncStats> = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate().filter(|(_, n)| n.cycles > 0) {
+            stats.entry(node.func).or_default().exclusive += node.cycles;
+            let mut seen = self.funcs_on_path(i);
+            seen.sort();
+            seen.dedup();
+            for func in seen {
+                stats.entry(func).or_default().inclusive += node.cycles;
+            }
+        }
+        for (&func, &calls) in &self.calls {
+            stats.entry(Some(func)).or_default().calls = calls;
+        }
+        stats
+    }
+
+    /// Flamegraph input: one `root;caller;callee cycles` line per call path
+    pub fn folded(&self, symbols: &Symbols) -> String {
+        let mut out = String::new();
+        for (i, node) in self.nodes.iter().enumerate().filter(|(_, n)| n.cycles > 0) {
+            let names: Vec<String> = self.funcs_on_path(i).into_iter().map(|f| func_name(symbols, f)).collect();
+            writeln!(out, "{} {}", names.join(";"), node.cycles).unwrap();
+        }
+        out
+    }
+
+    /// The `top` functions by inclusive cycles, then the ranges
+    pub fn report(&self, symbols: &Symbols, top: usize) -> String {
+        let mut funcs: Vec<_> = self.functions().into_iter().collect();
+        funcs.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(b.1.exclusive.cmp(&a.1.exclusive)));
+        let sampled = if let Mode::Sample { interval } = self.mode { format!(", sampled every {interval}") } else { String::new() };
+        let mut out = format!("{} cycles{}\n{:>12} {:>12} {:>8}  function\n", self.total, sampled, "inclusive", "exclusive", "calls");
+        for (func, s) in funcs.into_iter().take(top) {
+            writeln!(out, "{:>12} {:>12} {:>8}  {}", s.inclusive, s.exclusive, s.calls, func_name(symbols, func)).unwrap();
+        }
+        for r in &self.ranges {
+            writeln!(out, "{:>12} {:>12} {:>8}  range {}", r.cycles, r.cycles, "", r.name).unwrap();
+        }
+        out
+    }
+}
+
+fn func_name(symbols: &Symbols, func: Option<Func>) -> String {
+    match func {
+        Some((bank, addr)) => symbols.describe_at(bank, addr),
+        None => "(root)".to_string(),
+    }
+}
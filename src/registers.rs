@@ -1,3 +1,34 @@
+use crate::model::Model;
+
+/// A register by name, for debuggers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg { A, F, B, C, D, E, H, L, AF, BC, DE, HL, SP, PC }
+
+impl Reg {
+    pub const ALL: [Reg; 14] = [
+        Reg::A, Reg::F, Reg::B, Reg::C, Reg::D, Reg::E, Reg::H, Reg::L,
+        Reg::AF, Reg::BC, Reg::DE, Reg::HL, Reg::SP, Reg::PC,
+    ];
+
+    /// Case-insensitive
+    pub fn by_name(name: &str) -> Option<Reg> {
+        Self::ALL.into_iter().find(|r| r.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Reg::A => "A", Reg::F => "F", Reg::B => "B", Reg::C => "C",
+            Reg::D => "D", Reg::E => "E", Reg::H => "H", Reg::L => "L",
+            Reg::AF => "AF", Reg::BC => "BC", Reg::DE => "DE", Reg::HL => "HL",
+            Reg::SP => "SP", Reg::PC => "PC",
+        }
+    }
+
+    pub fn is_wide(self) -> bool {
+        matches!(self, Reg::AF | Reg::BC | Reg::DE | Reg::HL | Reg::SP | Reg::PC)
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Copy, Debug)]
 pub struct Registers {
@@ -9,24 +40,63 @@ pub struct Registers {
     pub ime: bool,
 }
 
+impl Default for Registers {
+    fn default() -> Self { Self::new(Model::Dmg) }
+}
+
 impl Registers {
 
-    pub fn new() -> Self {
-        Self {
-            a: 0x01,
-            f: 0xB0,        // Z, H, C flags set
-            b: 0x00,
-            c: 0x13,
-            d: 0x00,
-            e: 0xD8,
-            h: 0x01,
-            l: 0x4D,
-            sp: 0xFFFE,
-            pc: 0x0100,    // Entry point of ROM
-            ime: false,
+    pub fn new(model: Model) -> Self {
+        match model {
+            // MGB differs only in A, which lets games tell a Pocket apart
+            Model::Dmg | Model::Mgb => Self {
+                a: if model == Model::Mgb { 0xFF } else { 0x01 },
+                f: 0xB0,        // Z, H, C flags set
+                b: 0x00,
+                c: 0x13,
+                d: 0x00,
+                e: 0xD8,
+                h: 0x01,
+                l: 0x4D,
+                sp: 0xFFFE,
+                pc: 0x0100,    // Entry point of ROM
+                ime: false,
+            },
+            Model::Sgb => Self {
+                a: 0x01,
+                f: 0x00,
+                b: 0x00,
+                c: 0x14,
+                d: 0x00,
+                e: 0x00,
+                h: 0xC0,
+                l: 0x60,
+                sp: 0xFFFE,
+                pc: 0x0100,
+                ime: false,
+            },
+            // A = 0x11 is how games detect they are running on CGB hardware
+            Model::Cgb => Self {
+                a: 0x11,
+                f: 0x80,        // Z flag set
+                b: 0x00,
+                c: 0x00,
+                d: 0xFF,
+                e: 0x56,
+                h: 0x00,
+                l: 0x0D,
+                sp: 0xFFFE,
+                pc: 0x0100,
+                ime: false,
+            },
         }
     }
    
+    /// All zero with PC at 0x0000, where a boot ROM takes over
+    pub fn power_on() -> Self {
+        Self { a: 0, f: 0, b: 0, c: 0, d: 0, e: 0, h: 0, l: 0, sp: 0, pc: 0, ime: false }
+    }
+
     // get 16 methods
     pub fn get_af(&self) -> u16 { u16::from_le_bytes([self.f, self.a]) }
     pub fn get_bc(&self) -> u16 { u16::from_le_bytes([self.c, self.b]) }
@@ -80,4 +150,30 @@ impl Registers {
         self.set_flag_h(h);
         self.set_flag_c(c);
     }
-}
\ No newline at end of file
+
+    // BY NAME
+    pub fn get(&self, reg: Reg) -> u16 {
+        match reg {
+            Reg::A => self.a as u16, Reg::F => self.f as u16,
+            Reg::B => self.b as u16, Reg::C => self.c as u16,
+            Reg::D => self.d as u16, Reg::E => self.e as u16,
+            Reg::H => self.h as u16, Reg::L => self.l as u16,
+            Reg::AF => self.get_af(), Reg::BC => self.get_bc(),
+            Reg::DE => self.get_de(), Reg::HL => self.get_hl(),
+            Reg::SP => self.sp, Reg::PC => self.pc,
+        }
+    }
+
+    /// 8-bit registers take the low byte; the low nibble of F always reads 0
+    pub fn set(&mut self, reg: Reg, v: u16) {
+        match reg {
+            Reg::A => self.a = v as u8, Reg::F => self.f = v as u8 & 0xF0,
+            Reg::B => self.b = v as u8, Reg::C => self.c = v as u8,
+            Reg::D => self.d = v as u8, Reg::E => self.e = v as u8,
+            Reg::H => self.h = v as u8, Reg::L => self.l = v as u8,
+            Reg::AF => self.set_af(v), Reg::BC => self.set_bc(v),
+            Reg::DE => self.set_de(v), Reg::HL => self.set_hl(v),
+            Reg::SP => self.sp = v, Reg::PC => self.pc = v,
+        }
+    }
+}
@@ -0,0 +1,543 @@
+// GDB-style command line for the desktop debugger (`--debug`).
+//
+// The REPL only turns text into `Debugger` calls. The frontend feeds it lines
+// (typed or from a script) while execution is stopped, and calls `run_frame`
+// instead of `GameBoy::run_frame` while it runs. An empty line repeats the
+// previous command and `!N` recalls history entry N.
+//
+// Addresses are hex (`C000`, `$C000`, `03:4000`) or labels from a `.sym` file
+// or `label`. Anything else is evaluated as an expression, so registers go in
+// parentheses: `x/8 (HL)`. Values for `set` and `print` are expressions.
+// Locations print as `Bank:Label+offset` wherever a label covers them.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::callstack::FrameKind;
+use crate::cdl::{Cdl, Format};
+use crate::debugger::{parse_hex, Debugger, Expr, Location, Stop, WatchKind};
+use crate::disasm;
+use crate::gameboy::GameBoy;
+use crate::profiler::{Mode, Profiler};
+use crate::registers::Reg;
+use crate::search::{Compare, RamSearch, ValueType};
+use crate::symbols::Symbols;
+use crate::viewer;
+
+const HELP: &str = "\
+break LOC [if COND]    stop when execution reaches LOC (b)
+watch ADDR [if COND]   stop when the byte at ADDR changes
+rwatch/wwatch/awatch   ... on a read, write or either
+info                   list breakpoints and watchpoints
+delete/enable/disable N
+continue               run until something stops execution (c)
+step                   execute one instruction (s)
+next                   step over CALL and RST (n)
+finish                 run until the current routine returns
+until LOC              run to LOC (run-to-cursor)
+regs                   registers and flags
+set REG=VAL, set [ADDR]=VAL
+print EXPR             evaluate, e.g. p [HL] + 1 (p)
+x/N ADDR               hex dump of N bytes
+disasm [ADDR] [N]      disassemble N instructions
+oam, pal               sprite attribute table, BGP/OBP0/OBP1 decoded
+raster                 per-line LCD registers and register writes of the last frame
+layer [NAME on|off]    show or hide bg, window, sprites; highlight tints sprites
+bt                     shadow call stack (CALL, RST and interrupts)
+profile start [N]      profile every instruction, or sample every N cycles
+profile range NAME LOC LOC   also total the cycles spent between two addresses
+profile report [N]     top N functions by inclusive cycles
+profile save FILE      folded stacks for flamegraph.pl / inferno
+profile stop
+cdl start              log which ROM bytes run as code or are read as data
+cdl load FILE          merge a log from an earlier session (FCEUX or BizHawk)
+cdl save FILE [bizhawk]
+cdl coverage           per-bank code/data/unused byte counts
+cdl stop
+search start [TYPE]    snapshot RAM for a search; TYPE is u8 u16 u24 bcd8 bcd16 bcd24, +be
+search OP [VALUE]      keep values that are eq/gt/lt/changed/unchanged vs VALUE or the last search
+search list [N], search update, search remove ADDR, search stop
+freeze ADDR VALUE [TYPE]  hold a RAM value; without arguments, list frozen bytes
+unfreeze ADDR [TYPE]
+cheat add CODE [NAME]  GameShark (01VVLLHH) or Game Genie (ABC-DEF-GHI) code
+cheat list, cheat enable/disable/delete N, cheat on/off
+trace N                step N instructions, printing each
+symbols FILE           load labels from an RGBDS/no$gmb .sym file
+label NAME LOC         name an address for use in other commands
+source FILE            run commands from FILE
+history, help, quit";
+
+pub enum Flow {
+    Stay,   // keep reading commands
+    Resume, // execution continues
+    Quit,
+}
+
+pub struct Repl {
+    pub dbg: Debugger,
+    pub symbols: Symbols,
+    history: Vec<String>,
+    queue: VecDeque<String>, // script lines waiting to run
+    running: bool,
+}
+
+impl Default for Repl {
+    fn default() -> Self { Self::new() }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self { dbg: Debugger::new(), symbols: Symbols::new(), history: Vec::new(), queue: VecDeque::new(), running: false }
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Loads earlier sessions' commands, if the file exists
+    pub fn load_history(&mut self, path: &str) {
+        if let Ok(text) = std::fs::read_to_string(path) {
+            self.history.extend(text.lines().map(str::to_string));
+        }
+    }
+
+    pub fn save_history(&self, path: &str) -> std::io::Result<()> {
+        let keep = self.history.len().saturating_sub(1000);
+        std::fs::write(path, self.history[keep..].join("\n") + "\n")
+    }
+
+    /// Queues a script's commands; blank lines and `#` comments are skipped.
+    /// They run in order whenever execution is stopped.
+    pub fn source(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let lines = text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#'));
+        for (i, line) in lines.enumerate() {
+            self.queue.insert(i, line.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn next_queued(&mut self) -> Option<String> {
+        self.queue.pop_front()
+    }
+
+    /// Runs the rest of the frame; on a stop, returns the report to show
+    pub fn run_frame(&mut self, gb: &mut GameBoy) -> Option<String> {
+        let stop = self.dbg.run_frame(gb)?;
+        self.running = false;
+        let why = match stop {
+            Stop::Step => String::new(),
+            Stop::Breakpoint(id) => format!("Breakpoint {id}\n"),
+            Stop::Watchpoint { id, addr, old, new } => format!("Watchpoint {id}: [{}] {old:02X} -> {new:02X}\n", self.symbols.describe(gb, addr)),
+        };
+        Some(why + &self.where_is(gb))
+    }
+
+    /// Stops a running session from the outside (input while running)
+    pub fn interrupt(&mut self, gb: &GameBoy) -> String {
+        self.running = false;
+        self.dbg.cancel();
+        format!("Interrupted\n{}", self.where_is(gb))
+    }
+
+    /// Loads a `.sym` file on top of the labels already known; returns how many it had
+    pub fn load_symbols(&mut self, path: &str) -> Result<usize, String> {
+        let syms = Symbols::load(path)?;
+        let n = syms.len();
+        self.symbols.merge(syms);
+        Ok(n)
+    }
+
+    /// The current instruction, e.g. `01:OverworldLoop+3: CALL JoypadLowSensitivity`
+    pub fn where_is(&self, gb: &GameBoy) -> String {
+        let pc = gb.cpu.regs.pc;
+        format!("{}: {}{}", self.symbols.describe(gb, pc), self.instruction(gb, pc).0, if gb.cpu.halted { "  (halted)" } else { "" })
+    }
+
+    fn instruction(&self, gb: &GameBoy, addr: u16) -> (String, u16) {
+        let (text, len) = disasm::disassemble(&gb.mmu, addr);
+        (disasm::symbolize(&text, &gb.mmu, &self.symbols), len)
+    }
+
+    /// Runs one command line, writing its output to `out`
+    pub fn command(&mut self, gb: &mut GameBoy, line: &str, out: &mut String) -> Result<Flow, String> {
+        let line = line.trim();
+        let line = if line.is_empty() {
+            match self.history.last() {
+                Some(last) => last.clone(),
+                None => return Ok(Flow::Stay),
+            }
+        } else if let Some(n) = line.strip_prefix('!') {
+            let n: usize = n.parse().map_err(|_| format!("bad history number '{n}'"))?;
+            let line = self.history.get(n.wrapping_sub(1)).ok_or(format!("no history entry {n}"))?.clone();
+            self.history.push(line.clone());
+            line
+        } else {
+            self.history.push(line.to_string());
+            line.to_string()
+        };
+
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+        let rest = rest.trim();
+        let (cmd, suffix) = word.split_once('/').unwrap_or((word, ""));
+        let flow = match cmd {
+            "b" | "break" => {
+                let (at, cond) = self.with_cond(rest)?;
+                let at = self.location(gb, at)?;
+                let id = self.dbg.break_at(at, cond);
+                writeln!(out, "Breakpoint {id} at {at}").unwrap();
+                Flow::Stay
+            }
+            "watch" | "rwatch" | "wwatch" | "awatch" => {
+                let kind = match cmd {
+                    "rwatch" => WatchKind::Read,
+                    "wwatch" => WatchKind::Write,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Change,
+                };
+                let (addr, cond) = self.with_cond(rest)?;
+                let addr = self.address(gb, addr)?;
+                let id = self.dbg.watch(addr, kind, cond);
+                writeln!(out, "Watchpoint {id} ({kind:?}) on {}", self.symbols.describe(gb, addr)).unwrap();
+                Flow::Stay
+            }
+            "info" => {
+                for b in self.dbg.breakpoints() {
+                    write!(out, "{:>3} break {}{}", b.id, b.at, if b.enabled { "" } else { " (disabled)" }).unwrap();
+                    if let Some(c) = &b.cond { write!(out, " if {}", c.text()).unwrap(); }
+                    writeln!(out, ", hit {} times", b.hits).unwrap();
+                }
+                for w in self.dbg.watchpoints() {
+                    write!(out, "{:>3} watch {:?} {}{}", w.id, w.kind, self.symbols.describe(gb, w.addr), if w.enabled { "" } else { " (disabled)" }).unwrap();
+                    if let Some(c) = &w.cond { write!(out, " if {}", c.text()).unwrap(); }
+                    writeln!(out, ", hit {} times", w.hits).unwrap();
+                }
+                Flow::Stay
+            }
+            "delete" | "d" | "enable" | "disable" => {
+                let id: usize = rest.parse().map_err(|_| format!("{cmd} expects a breakpoint or watchpoint number"))?;
+                let found = match cmd {
+                    "enable" => self.dbg.set_enabled(id, true),
+                    "disable" => self.dbg.set_enabled(id, false),
+                    _ => self.dbg.delete(id),
+                };
+                if !found { return Err(format!("no breakpoint or watchpoint {id}")); }
+                Flow::Stay
+            }
+            "c" | "continue" => Flow::Resume,
+            "s" | "step" | "si" | "stepi" => { self.dbg.step_into(); Flow::Resume }
+            "n" | "next" | "ni" | "nexti" => { self.dbg.step_over(gb); Flow::Resume }
+            "finish" => { self.dbg.step_out(gb); Flow::Resume }
+            "until" | "advance" => {
+                let at = self.location(gb, rest)?;
+                self.dbg.run_to(at);
+                Flow::Resume
+            }
+            "regs" | "r" => {
+                let r = &gb.cpu.regs;
+                let flags: String = ["Z", "N", "H", "C"].iter().enumerate()
+                    .map(|(i, f)| if r.f & (0x80 >> i) != 0 { *f } else { "-" }).collect();
+                writeln!(out, "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X}  {} IME={}{}",
+                    r.get_af(), r.get_bc(), r.get_de(), r.get_hl(), r.sp, r.pc, flags, r.ime as u8,
+                    if gb.cpu.halted { " halted" } else { "" }).unwrap();
+                writeln!(out, "LY={:02X} STAT={:02X} IF={:02X} IE={:02X} ROM={:02X}",
+                    gb.mmu.peek(0xFF44), gb.mmu.peek(0xFF41), gb.mmu.peek(0xFF0F), gb.mmu.peek(0xFFFF), gb.mmu.rom_bank).unwrap();
+                Flow::Stay
+            }
+            "set" => {
+                let (target, value) = rest.split_once('=').ok_or("set expects REG=VALUE or [ADDR]=VALUE")?;
+                let value = Expr::parse_with(value, &self.symbols)?.eval(gb);
+                let target = target.trim();
+                if let Some(addr) = target.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                    let addr = self.address(gb, addr)?;
+                    self.dbg.poke(gb, addr, value as u8);
+                } else {
+                    let reg = Reg::by_name(target).ok_or(format!("unknown register '{target}'"))?;
+                    gb.cpu.regs.set(reg, value as u16);
+                }
+                Flow::Stay
+            }
+            "p" | "print" => {
+                let v = Expr::parse_with(rest, &self.symbols)?.eval(gb);
+                writeln!(out, "{v} (${v:X})").unwrap();
+                Flow::Stay
+            }
+            "x" => {
+                let count: u16 = if suffix.is_empty() { 16 } else { suffix.parse().map_err(|_| format!("bad count '{suffix}'"))? };
+                let start = self.address(gb, rest)?;
+                if let Some(name) = self.symbols.name_at(gb.mmu.bank_at(start), start) {
+                    writeln!(out, "{name}:").unwrap();
+                }
+                for row in (0..count).step_by(16) {
+                    let addr = start.wrapping_add(row);
+                    let bytes: Vec<String> = (row..count.min(row + 16)).map(|i| format!("{:02X}", gb.mmu.peek(start.wrapping_add(i)))).collect();
+                    writeln!(out, "{addr:04X}: {}", bytes.join(" ")).unwrap();
+                }
+                Flow::Stay
+            }
+            "disasm" | "dis" => {
+                let mut args = rest.split_whitespace();
+                let mut addr = match args.next() {
+                    Some(a) => self.address(gb, a)?,
+                    None => gb.cpu.regs.pc,
+                };
+                let count: usize = args.next().map_or(Ok(10), |n| n.parse().map_err(|_| format!("bad count '{n}'")))?;
+                for _ in 0..count {
+                    let bank = gb.mmu.bank_at(addr);
+                    if let Some(name) = self.symbols.name_at(bank, addr) {
+                        writeln!(out, "{name}:").unwrap();
+                    }
+                    let (text, len) = self.instruction(gb, addr);
+                    let mark = if addr == gb.cpu.regs.pc { "=>" } else if self.dbg.breakpoints().iter().any(|b| b.at.addr == addr) { " *" } else { "  " };
+                    let bytes: Vec<String> = (0..len).map(|i| format!("{:02X}", gb.mmu.peek(addr.wrapping_add(i)))).collect();
+                    let at = if (0x4000..0x8000).contains(&addr) { format!("{bank:02X}:{addr:04X}") } else { format!("{addr:04X}") };
+                    writeln!(out, "{mark} {at}: {:<9} {text}", bytes.join(" ")).unwrap();
+                    addr = addr.wrapping_add(len);
+                }
+                Flow::Stay
+            }
+            "bt" | "backtrace" => {
+                writeln!(out, "#0 {}", self.symbols.describe(gb, gb.cpu.regs.pc)).unwrap();
+                for (i, f) in self.dbg.stack.frames().iter().rev().enumerate() {
+                    let irq = if f.kind == FrameKind::Interrupt { " interrupted" } else { "" };
+                    writeln!(out, "#{} {} (SP={:04X}){irq}", i + 1, self.symbols.describe(gb, f.ret), f.ret_sp).unwrap();
+                }
+                Flow::Stay
+            }
+            "profile" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), self.dbg.profiler.as_mut()) {
+                    (Some("start"), _) => {
+                        let mode = match args.next() {
+                            Some(n) => Mode::Sample { interval: n.parse().map_err(|_| format!("bad interval '{n}'"))? },
+                            None => Mode::Instrument,
+                        };
+                        self.dbg.profiler = Some(Profiler::new(mode));
+                    }
+                    (Some("stop"), _) => self.dbg.profiler = None,
+                    (Some(_), None) => return Err("no profile running, use profile start".into()),
+                    (Some("range"), Some(_)) => {
+                        let (Some(name), Some(start), Some(end)) = (args.next(), args.next(), args.next()) else {
+                            return Err("profile range expects NAME START END".into());
+                        };
+                        let start = self.location(gb, start)?;
+                        let end = self.location(gb, end)?;
+                        self.dbg.profiler.as_mut().unwrap().add_range(name, start.bank, start.addr, end.addr);
+                    }
+                    (Some("report"), Some(p)) => {
+                        let top = args.next().map_or(Ok(20), |n| n.parse().map_err(|_| format!("bad count '{n}'")))?;
+                        out.push_str(&p.report(&self.symbols, top));
+                    }
+                    (Some("save"), Some(p)) => {
+                        let path = args.next().ok_or("profile save expects a path")?;
+                        std::fs::write(path, p.folded(&self.symbols)).map_err(|e| format!("{path}: {e}"))?;
+                        writeln!(out, "Saved folded stacks to {path}").unwrap();
+                    }
+                    _ => return Err("profile expects start, range, report, save or stop".into()),
+                }
+                Flow::Stay
+            }
+            "cdl" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), gb.mmu.cdl.as_mut()) {
+                    (Some("start"), None) => gb.mmu.cdl = Some(Box::new(Cdl::new(gb.mmu.rom_len(), gb.mmu.extram.len()))),
+                    (Some("start"), Some(_)) => {}
+                    (Some("stop"), _) => gb.mmu.cdl = None,
+                    (Some(_), None) => return Err("no code/data log running, use cdl start".into()),
+                    (Some("load"), Some(cdl)) => {
+                        let path = args.next().ok_or("cdl load expects a path")?;
+                        let other = Cdl::load(path)?;
+                        cdl.merge(&other).map_err(|e| format!("{path}: {e}"))?;
+                        cdl.format = other.format;
+                        writeln!(out, "Merged {path}").unwrap();
+                    }
+                    (Some("save"), Some(cdl)) => {
+                        let path = args.next().ok_or("cdl save expects a path")?;
+                        match args.next() {
+                            Some("bizhawk") => cdl.format = Format::BizHawk,
+                            Some("fceux") => cdl.format = Format::Fceux,
+                            Some(f) => return Err(format!("unknown format '{f}', expected fceux or bizhawk")),
+                            None => {}
+                        }
+                        cdl.save(path)?;
+                        writeln!(out, "Saved code/data log to {path}").unwrap();
+                    }
+                    (Some("coverage"), Some(cdl)) => out.push_str(&cdl.summary()),
+                    _ => return Err("cdl expects start, load, save, coverage or stop".into()),
+                }
+                Flow::Stay
+            }
+            "search" => {
+                let mut args = rest.split_whitespace();
+                match (args.next(), self.dbg.search.as_mut()) {
+                    (Some("start"), _) => {
+                        let ty = args.next().map_or(Ok(ValueType::U8), ValueType::parse)?;
+                        let search = RamSearch::new(&gb.mmu, ty);
+                        writeln!(out, "{} candidates", search.candidates().len()).unwrap();
+                        self.dbg.search = Some(search);
+                    }
+                    (Some("stop"), _) => self.dbg.search = None,
+                    (Some(_), None) => return Err("no search running, use search start".into()),
+                    (Some("update"), Some(search)) => search.update(&gb.mmu),
+                    (Some("remove"), Some(_)) => {
+                        let addr = self.address(gb, args.next().ok_or("search remove expects an address")?)?;
+                        self.dbg.search.as_mut().unwrap().remove(addr);
+                    }
+                    (Some("list"), Some(_)) => {
+                        let n = args.next().map_or(Ok(20), |n| n.parse().map_err(|_| format!("bad count '{n}'")))?;
+                        self.list_candidates(gb, n, out);
+                    }
+                    (Some(op), Some(_)) => {
+                        let cmp = Compare::parse(op)?;
+                        let rest = args.collect::<Vec<_>>().join(" ");
+                        let against = match rest.as_str() {
+                            "" => None,
+                            v => Some(Expr::parse_with(v, &self.symbols)?.eval(gb) as u32),
+                        };
+                        let left = self.dbg.search.as_mut().unwrap().filter(&gb.mmu, cmp, against);
+                        writeln!(out, "{left} candidates").unwrap();
+                        if left <= 10 { self.list_candidates(gb, 10, out); }
+                    }
+                    _ => return Err("search expects start, an operator, list, update, remove or stop".into()),
+                }
+                Flow::Stay
+            }
+            "freeze" if rest.is_empty() => {
+                for (&(bank, addr), val) in gb.mmu.frozen() {
+                    writeln!(out, "{} = ${val:02X}", self.symbols.describe_at(bank, addr)).unwrap();
+                }
+                Flow::Stay
+            }
+            "freeze" | "unfreeze" => {
+                let args: Vec<&str> = rest.split_whitespace().collect();
+                let ty = match args.get(if cmd == "freeze" { 2 } else { 1 }) {
+                    Some(t) => ValueType::parse(t)?,
+                    None => self.dbg.search.as_ref().map_or(ValueType::U8, RamSearch::value_type),
+                };
+                let addr = self.address(gb, args.first().ok_or(format!("{cmd} expects an address"))?)?;
+                if cmd == "unfreeze" {
+                    ty.unfreeze(&mut gb.mmu, addr);
+                } else {
+                    let value = args.get(1).ok_or("freeze expects ADDR VALUE [TYPE]")?;
+                    let value = Expr::parse_with(value, &self.symbols)?.eval(gb) as u32;
+                    ty.freeze(&mut gb.mmu, addr, value)?;
+                }
+                Flow::Stay
+            }
+            "cheat" | "cheats" => {
+                let (sub, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let cheats = &mut gb.mmu.cheats;
+                let index = || arg.trim().parse::<usize>().map_err(|_| format!("bad cheat number '{arg}'"));
+                match sub {
+                    "add" => {
+                        let (code, name) = arg.trim().split_once(char::is_whitespace).unwrap_or((arg.trim(), ""));
+                        let i = cheats.add(code, name)?;
+                        writeln!(out, "Cheat {i}: {:?}", cheats.list()[i].code).unwrap();
+                    }
+                    "enable" => cheats.set_enabled(index()?, true)?,
+                    "disable" => cheats.set_enabled(index()?, false)?,
+                    "delete" => { cheats.remove(index()?)?; }
+                    "on" => cheats.set_active(true),
+                    "off" => cheats.set_active(false),
+                    "" | "list" => {
+                        if !cheats.active() { writeln!(out, "(all cheats off)").unwrap(); }
+                        for (i, c) in cheats.list().iter().enumerate() {
+                            writeln!(out, "{i:>3} {} {:<12} {}", if c.enabled { '+' } else { '-' }, c.text, c.name).unwrap();
+                        }
+                    }
+                    _ => return Err("cheat expects add, list, enable, disable, delete, on or off".into()),
+                }
+                Flow::Stay
+            }
+            "oam" => { out.push_str(&viewer::oam_table(&gb.mmu)); Flow::Stay }
+            "pal" | "palettes" => { out.push_str(&viewer::palette_table(&gb.mmu)); Flow::Stay }
+            "raster" => { out.push_str(&viewer::raster_table(&gb.ppu)); Flow::Stay }
+            "layer" | "layers" => {
+                let mut args = rest.split_whitespace();
+                if let Some(name) = args.next() {
+                    let on = match args.next() {
+                        Some("on") => true,
+                        Some("off") => false,
+                        _ => return Err("layer expects NAME on|off".into()),
+                    };
+                    *gb.ppu.layer(name)? = on;
+                }
+                for name in ["bg", "window", "sprites", "highlight"] {
+                    writeln!(out, "{name:<9} {}", if *gb.ppu.layer(name)? { "on" } else { "off" }).unwrap();
+                }
+                Flow::Stay
+            }
+            "label" => {
+                let (name, at) = rest.split_once(char::is_whitespace).ok_or("label expects NAME LOC")?;
+                let at = Location::parse(at)?;
+                self.symbols.insert(name, at);
+                Flow::Stay
+            }
+            "symbols" => {
+                let n = self.load_symbols(rest)?;
+                writeln!(out, "Loaded {n} symbols from {rest}").unwrap();
+                Flow::Stay
+            }
+            "trace" => {
+                let n: u32 = if rest.is_empty() { 10 } else { rest.parse().map_err(|_| format!("bad count '{rest}'"))? };
+                for _ in 0..n {
+                    writeln!(out, "{}", self.where_is(gb)).unwrap();
+                    self.dbg.step_into();
+                    // A step can straddle the end of a frame
+                    match self.dbg.run(gb, 2) {
+                        Some(Stop::Step) => {}
+                        Some(stop) => { writeln!(out, "Stopped: {stop:?}").unwrap(); break; }
+                        None => break,
+                    }
+                }
+                Flow::Stay
+            }
+            "source" => { self.source(rest)?; Flow::Stay }
+            "history" => {
+                for (i, h) in self.history.iter().enumerate() {
+                    writeln!(out, "{:>4}  {h}", i + 1).unwrap();
+                }
+                Flow::Stay
+            }
+            "help" | "h" | "?" => { writeln!(out, "{HELP}").unwrap(); Flow::Stay }
+            "q" | "quit" | "exit" => Flow::Quit,
+            _ => return Err(format!("unknown command '{cmd}', try help")),
+        };
+        if let Flow::Resume = flow { self.running = true; }
+        Ok(flow)
+    }
+
+    fn list_candidates(&self, gb: &GameBoy, n: usize, out: &mut String) {
+        let Some(search) = &self.dbg.search else { return };
+        for c in search.candidates().iter().take(n) {
+            let frozen = if gb.mmu.is_frozen(c.addr) { " frozen" } else { "" };
+            writeln!(out, "{:<20} {:>8} (was {}, {} changes){frozen}", self.symbols.describe(gb, c.addr), c.value, c.previous, c.changes).unwrap();
+        }
+        if search.candidates().len() > n { writeln!(out, "... {} more", search.candidates().len() - n).unwrap(); }
+    }
+
+    // Splits `X if COND`
+    fn with_cond<'a>(&self, s: &'a str) -> Result<(&'a str, Option<Expr>), String> {
+        match s.split_once(" if ") {
+            Some((x, cond)) => Ok((x.trim(), Some(Expr::parse_with(cond, &self.symbols)?))),
+            None => Ok((s, None)),
+        }
+    }
+
+    fn location(&self, gb: &GameBoy, s: &str) -> Result<Location, String> {
+        if let Some(at) = self.symbols.lookup(s) { return Ok(at); }
+        if s.contains(':') { return Location::parse(s); }
+        self.address(gb, s).map(Location::new)
+    }
+
+    fn address(&self, gb: &GameBoy, s: &str) -> Result<u16, String> {
+        let s = s.trim();
+        if s.is_empty() { return Err("expected an address".into()); }
+        if let Some(at) = self.symbols.lookup(s) { return Ok(at.addr); }
+        parse_hex(s).or_else(|_| Ok(Expr::parse_with(s, &self.symbols)?.eval(gb) as u16))
+    }
+}
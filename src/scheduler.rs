@@ -0,0 +1,100 @@
+// Event scheduler: a priority queue of (absolute cycle, event) pairs.
+//
+// Time is counted in CPU T-cycles since power-on and never resets, so nothing
+// is lost across frames. Each peripheral keeps at most one event queued: the
+// next moment its state changes on its own (a PPU mode change, a timer
+// interrupt, a finished serial byte or OAM DMA). The CPU advances the clock
+// one M-cycle per bus access; peripherals catch up lazily when their event
+// fires or when the CPU touches their registers.
+//
+// Rescheduling an event bumps its generation, so stale heap entries are
+// dropped when they surface instead of being searched for.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Event {
+    Ppu,    // next mode change or line start
+    Timer,  // TIMA reload and interrupt
+    Serial, // transfer finished, or time to poll the external clock
+    Dma,    // OAM DMA finished
+}
+
+const EVENTS: usize = 4;
+
+#[derive(Clone, Debug, Default)]
+pub struct Scheduler {
+    now: u64,
+    queue: BinaryHeap<Reverse<(u64, u64, Event, u32)>>, // (at, seq, event, generation)
+    seq: u64,
+    generation: [u32; EVENTS],
+    pending: [Option<u64>; EVENTS],
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// T-cycles since power-on
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        self.now += cycles as u64;
+    }
+
+    /// Queues `event` at absolute cycle `at`, replacing any earlier schedule for it
+    pub fn schedule(&mut self, at: u64, event: Event) {
+        let i = event as usize;
+        self.generation[i] = self.generation[i].wrapping_add(1);
+        self.pending[i] = Some(at);
+        self.seq += 1;
+        self.queue.push(Reverse((at, self.seq, event, self.generation[i])));
+    }
+
+    pub fn schedule_in(&mut self, cycles: u64, event: Event) {
+        self.schedule(self.now + cycles, event);
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        let i = event as usize;
+        self.generation[i] = self.generation[i].wrapping_add(1);
+        self.pending[i] = None;
+    }
+
+    /// When `event` is due, if it is queued
+    pub fn pending(&self, event: Event) -> Option<u64> {
+        self.pending[event as usize]
+    }
+
+    /// Takes `event` off the queue if it is due by now, ahead of anything queued before it
+    pub fn take_due(&mut self, event: Event) -> bool {
+        if self.pending[event as usize].is_none_or(|at| at > self.now) { return false; }
+        self.cancel(event);
+        true
+    }
+
+    fn drop_stale(&mut self) {
+        while let Some(&Reverse((_, _, ev, generation))) = self.queue.peek() {
+            if generation == self.generation[ev as usize] { break; }
+            self.queue.pop();
+        }
+    }
+
+    /// Absolute cycle of the earliest live event
+    pub fn next_at(&mut self) -> Option<u64> {
+        self.drop_stale();
+        self.queue.peek().map(|&Reverse((at, ..))| at)
+    }
+
+    /// Pops the earliest event that is due by now, with the cycle it was due at
+    pub fn pop_due(&mut self) -> Option<(u64, Event)> {
+        if self.next_at()? > self.now { return None; }
+        let Reverse((at, _, ev, _)) = self.queue.pop()?;
+        self.pending[ev as usize] = None;
+        Some((at, ev))
+    }
+}
@@ -0,0 +1,165 @@
+// RAM search, as in BizHawk: snapshot every candidate address in WRAM, HRAM
+// and cartridge RAM, then narrow the list by comparing each value with the
+// previous snapshot or a constant until only the variable you want is left.
+//
+// Addresses are as the CPU sees them, so banked WRAM and cartridge RAM are
+// searched in whichever bank is mapped at each step. Multi-byte values start
+// at the candidate address; BCD values that aren't valid BCD never match.
+
+use crate::mmu::Mmu;
+
+// WRAM, HRAM, cartridge RAM
+const REGIONS: [(u16, u16); 3] = [(0xC000, 0xDFFF), (0xFF80, 0xFFFE), (0xA000, 0xBFFF)];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueType {
+    pub size: u8, // bytes, 1-3
+    pub bcd: bool,
+    pub big_endian: bool,
+}
+
+impl ValueType {
+    pub const U8: Self = Self { size: 1, bcd: false, big_endian: false };
+
+    /// `u8`, `u16`, `u24`, `bcd8`, `bcd16` or `bcd24`, with `be` appended for big-endian
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (rest, big_endian) = match text.strip_suffix("be") { Some(r) => (r, true), None => (text, false) };
+        let (bits, bcd) = match rest.strip_prefix("bcd") { Some(b) => (b, true), None => (rest.strip_prefix('u').unwrap_or("?"), false) };
+        let size = match bits {
+            "8" => 1,
+            "16" => 2,
+            "24" => 3,
+            _ => return Err(format!("unknown value type '{text}', expected u8/u16/u24/bcd8/bcd16/bcd24 (+be)")),
+        };
+        Ok(Self { size, bcd, big_endian })
+    }
+
+    fn max(&self) -> u32 {
+        if self.bcd { 10u32.pow(2 * self.size as u32) - 1 } else { (1 << (8 * self.size as u32)) - 1 }
+    }
+
+    /// The value stored at `addr`, or None if it isn't valid BCD
+    pub fn read(&self, mmu: &Mmu, addr: u16) -> Option<u32> {
+        let n = self.size as u16;
+        (0..n).try_fold(0u32, |v, i| {
+            let b = mmu.peek(addr.wrapping_add(if self.big_endian { i } else { n - 1 - i }));
+            if !self.bcd { return Some(v << 8 | b as u32); }
+            (b >> 4 < 10 && b & 0xF < 10).then(|| v * 100 + (b >> 4) as u32 * 10 + (b & 0xF) as u32)
+        })
+    }
+
+    /// The bytes that store `value`, lowest address first
+    pub fn encode(&self, value: u32) -> Result<Vec<u8>, String> {
+        if value > self.max() { return Err(format!("{value} does not fit")); }
+        let mut v = value;
+        let mut bytes: Vec<u8> = (0..self.size).map(|_| {
+            let b = if self.bcd { ((v % 100 / 10) << 4 | (v % 10)) as u8 } else { v as u8 };
+            v = if self.bcd { v / 100 } else { v >> 8 };
+            b
+        }).collect();
+        if self.big_endian { bytes.reverse(); }
+        Ok(bytes)
+    }
+
+    /// Holds the value at `addr` at `value` (see `Mmu::freeze`)
+    pub fn freeze(&self, mmu: &mut Mmu, addr: u16, value: u32) -> Result<(), String> {
+        for (i, b) in self.encode(value)?.into_iter().enumerate() {
+            mmu.freeze(addr.wrapping_add(i as u16), b);
+        }
+        Ok(())
+    }
+
+    pub fn unfreeze(&self, mmu: &mut Mmu, addr: u16) {
+        for i in 0..self.size as u16 { mmu.unfreeze(addr.wrapping_add(i)); }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compare {
+    Equal,
+    Greater,
+    Less,
+    Changed,
+    Unchanged,
+}
+
+impl Compare {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        Ok(match text {
+            "eq" | "==" | "=" => Self::Equal,
+            "gt" | ">" => Self::Greater,
+            "lt" | "<" => Self::Less,
+            "changed" | "ne" | "!=" => Self::Changed,
+            "unchanged" => Self::Unchanged,
+            _ => return Err(format!("unknown comparison '{text}', expected eq, gt, lt, changed or unchanged")),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub addr: u16,
+    pub value: u32,    // at the last search
+    pub previous: u32, // at the search before that
+    pub changes: u32,  // searches it differed in
+}
+
+#[derive(Clone, Debug)]
+pub struct RamSearch {
+    ty: ValueType,
+    candidates: Vec<Candidate>,
+}
+
+impl RamSearch {
+    /// Every address that holds a whole value of type `ty`, snapshotted now
+    pub fn new(mmu: &Mmu, ty: ValueType) -> Self {
+        let candidates = REGIONS.iter()
+            .flat_map(|&(start, end)| start..=end + 1 - ty.size as u16)
+            .filter_map(|addr| ty.read(mmu, addr).map(|value| Candidate { addr, value, previous: value, changes: 0 }))
+            .collect();
+        Self { ty, candidates }
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        self.ty
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    /// Keeps the candidates whose current value compares true against
+    /// `against`, or against the last snapshot when None. Returns how many are left.
+    pub fn filter(&mut self, mmu: &Mmu, cmp: Compare, against: Option<u32>) -> usize {
+        let ty = self.ty;
+        self.candidates.retain_mut(|c| {
+            let Some(now) = ty.read(mmu, c.addr) else { return false };
+            let other = against.unwrap_or(c.value);
+            let keep = match cmp {
+                Compare::Equal | Compare::Unchanged => now == other,
+                Compare::Greater => now > other,
+                Compare::Less => now < other,
+                Compare::Changed => now != other,
+            };
+            if now != c.value { c.changes += 1; }
+            (c.previous, c.value) = (c.value, now);
+            keep
+        });
+        self.candidates.len()
+    }
+
+    /// Takes a new snapshot without narrowing
+    pub fn update(&mut self, mmu: &Mmu) {
+        let ty = self.ty;
+        for c in &mut self.candidates {
+            let now = ty.read(mmu, c.addr).unwrap_or(c.value);
+            if now != c.value { c.changes += 1; }
+            (c.previous, c.value) = (c.value, now);
+        }
+    }
+
+    /// Drops one candidate by hand
+    pub fn remove(&mut self, addr: u16) {
+        self.candidates.retain(|c| c.addr != addr);
+    }
+}
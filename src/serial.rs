@@ -0,0 +1,204 @@
+// Serial link port: SB (FF01) and SC (FF02).
+//
+// Setting SC bit 7 starts a transfer. With the internal clock (SC bit 0) this
+// side is the master and shifts one bit every 512 T-cycles (16 in CGB fast
+// mode); with the external clock it waits for the other side to drive the
+// clock, then shifts at the rate the master clocks it. After 8 bits SC bit 7
+// clears and IF bit 3 is raised.
+//
+// What sits on the other end of the cable is a `SerialEndpoint`.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+pub trait SerialEndpoint {
+    /// This side drives the clock: `out` goes over the wire and the byte the
+    /// other side shifted back is returned.
+    fn exchange(&mut self, out: u8) -> u8;
+
+    /// `exchange` for endpoints that pass the master's bit period (T-cycles per
+    /// bit) and the cycle the transfer started at (`now`) on to a slave
+    fn exchange_at(&mut self, out: u8, _bit_period: u32, _now: u64) -> u8 {
+        self.exchange(out)
+    }
+
+    /// This side waits on the external clock with `out` loaded in SB. Returns
+    /// the incoming byte and the other side's bit period once it has started
+    /// clocking a transfer.
+    fn poll_external(&mut self, _out: u8) -> Option<(u8, u32)> {
+        None
+    }
+
+    /// This side stopped waiting on the external clock (SC bit 7 cleared)
+    fn cancel(&mut self) {}
+}
+
+// Nothing plugged in: the input line floats high
+pub struct Disconnected;
+
+impl SerialEndpoint for Disconnected {
+    fn exchange(&mut self, _out: u8) -> u8 { 0xFF }
+}
+
+// Collects every byte sent, e.g. the text Blargg/Mooneye test ROMs print
+pub struct TextCapture {
+    buffer: Rc<RefCell<Vec<u8>>>,
+    echo: bool,
+}
+
+impl TextCapture {
+    /// `echo` also writes each byte to stdout as it arrives
+    pub fn new(echo: bool) -> Self {
+        Self { buffer: Rc::new(RefCell::new(Vec::new())), echo }
+    }
+
+    /// Shared handle to the captured bytes, still readable after the endpoint is plugged in
+    pub fn buffer(&self) -> Rc<RefCell<Vec<u8>>> {
+        self.buffer.clone()
+    }
+}
+
+impl SerialEndpoint for TextCapture {
+    fn exchange(&mut self, out: u8) -> u8 {
+        self.buffer.borrow_mut().push(out);
+        if self.echo {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(&[out]);
+            let _ = stdout.flush();
+        }
+        0xFF
+    }
+}
+
+// SO wired straight back into SI: every byte sent comes back
+pub struct Loopback;
+
+impl SerialEndpoint for Loopback {
+    fn exchange(&mut self, out: u8) -> u8 { out }
+}
+
+// How often a slave checks whether the other side has clocked a byte in
+const EXTERNAL_POLL: u32 = 128;
+
+pub const BIT_PERIOD: u32 = 512;      // 8192 Hz
+pub const FAST_BIT_PERIOD: u32 = 16;  // CGB fast clock, 262144 Hz
+
+pub struct Serial {
+    pub sb: u8,
+    pub sc: u8,
+    incoming: u8,
+    bits_left: u8,
+    cycles: u32,
+    external_period: u32, // bit period of the master driving this side
+    cgb: bool,
+    endpoint: Box<dyn SerialEndpoint>,
+}
+
+impl Serial {
+    pub fn new(cgb: bool) -> Self {
+        Self { sb: 0, sc: 0, incoming: 0, bits_left: 0, cycles: 0, external_period: BIT_PERIOD, cgb, endpoint: Box::new(Disconnected) }
+    }
+
+    pub fn connect(&mut self, endpoint: Box<dyn SerialEndpoint>) {
+        self.endpoint = endpoint;
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF01 => self.sb,
+            _ if self.cgb => self.sc | 0x7C,
+            _ => self.sc | 0x7E,
+        }
+    }
+
+    /// `now` is the scheduler's clock, passed on to the endpoint when a transfer starts
+    pub fn write(&mut self, addr: u16, val: u8, now: u64) {
+        match addr {
+            0xFF01 => self.sb = val,
+            _ => {
+                self.sc = val;
+                self.bits_left = 0;
+                if val & 0x81 == 0x81 {
+                    // Master: the partner's byte is known up front and shifted in bit by bit
+                    self.incoming = self.endpoint.exchange_at(self.sb, self.bit_period(), now);
+                    self.bits_left = 8;
+                    self.cycles = 0;
+                } else if val & 0x80 == 0 {
+                    self.endpoint.cancel();
+                }
+            }
+        }
+    }
+
+    /// Register value `elapsed` T-cycles after the last `tick`, without advancing
+    pub fn read_at(&self, addr: u16, elapsed: u32) -> u8 {
+        if self.sc & 0x80 == 0 || self.bits_left == 0 {
+            return self.read(addr);
+        }
+        let shifted = ((self.cycles + elapsed) / self.bit_period()).min(self.bits_left as u32) as u8;
+        if shifted == 0 { return self.read(addr); }
+        let left = self.bits_left - shifted;
+        match addr {
+            0xFF01 => ((self.sb as u16) << shifted | ((self.incoming >> left) as u16 & ((1 << shifted) - 1))) as u8,
+            _ if left == 0 => self.read(addr) & 0x7F,
+            _ => self.read(addr),
+        }
+    }
+
+    /// T-cycles until the transfer finishes, or until the external clock should
+    /// be polled again; None while idle
+    pub fn cycles_until_event(&self) -> Option<u32> {
+        match self.sc & 0x81 {
+            0x80 | 0x81 if self.bits_left > 0 => Some((self.bits_left as u32 * self.bit_period()).saturating_sub(self.cycles).max(4)),
+            0x80 => Some(EXTERNAL_POLL),
+            _ => None,
+        }
+    }
+
+    fn bit_period(&self) -> u32 {
+        if self.sc & 0x01 == 0 {
+            self.external_period
+        } else if self.cgb && self.sc & 0x02 != 0 {
+            FAST_BIT_PERIOD
+        } else {
+            BIT_PERIOD
+        }
+    }
+
+    fn complete(&mut self) -> bool {
+        self.sc &= 0x7F;
+        self.bits_left = 0;
+        true
+    }
+
+    /// Advances by `cycles` T-cycles. Returns true if the serial interrupt fired.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        if self.sc & 0x80 == 0 {
+            return false;
+        }
+
+        if self.sc & 0x01 == 0 && self.bits_left == 0 {
+            // Slave: once the remote clock starts, shift at its rate from here on
+            if let Some((byte, period)) = self.endpoint.poll_external(self.sb) {
+                self.incoming = byte;
+                self.external_period = period;
+                self.bits_left = 8;
+                self.cycles = 0;
+            }
+            return false;
+        }
+
+        self.cycles += cycles;
+        let period = self.bit_period();
+        while self.bits_left > 0 && self.cycles >= period {
+            self.cycles -= period;
+            self.bits_left -= 1;
+            self.sb = (self.sb << 1) | ((self.incoming >> self.bits_left) & 1);
+            if self.bits_left == 0 {
+                return self.complete();
+            }
+        }
+        false
+    }
+}
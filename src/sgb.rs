@@ -0,0 +1,363 @@
+// Super Game Boy: command packets bit-banged through JOYP, colourisation of the
+// 160x144 screen by attribute area, and the 256x224 border.
+//
+// A packet starts with a reset pulse (P14 and P15 low), followed by 128 data
+// bits (P14 low = 0, P15 low = 1, LSB first) and a 0 stop bit. The low three
+// bits of the first byte give how many packets the command spans.
+
+use crate::cgb::bgr555_to_rgb;
+
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+const SCREEN_X: usize = 48; // top-left corner of the game screen inside the border
+const SCREEN_Y: usize = 40;
+const ATTR_W: usize = 20; // attribute map is one palette per 8x8 cell
+const ATTR_H: usize = 18;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mask {
+    Off,
+    Freeze,
+    Black,
+    Color0,
+}
+
+// VRAM transfers latch the BG-visible tiles on the next VBlank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    Palettes,         // PAL_TRN
+    BorderTiles(u8),  // CHR_TRN, 0 = tiles 00-7F, 1 = 80-FF
+    BorderMap,        // PCT_TRN
+    AttrFiles,        // ATTR_TRN
+}
+
+pub struct Sgb {
+    // --- packet receiver ---
+    last_joyp: u8,
+    receiving: bool,
+    bits: usize,
+    packet: [u8; 16],
+    command: Vec<u8>,
+
+    // --- multiplayer (MLT_REQ) ---
+    pub players: u8,
+    pub player: u8,
+
+    // --- colour state ---
+    pub palettes: [[u16; 4]; 4],
+    sys_palettes: Vec<[u16; 4]>, // 512 entries, filled by PAL_TRN
+    pub attrs: [u8; ATTR_W * ATTR_H],
+    attr_files: Vec<[u8; 90]>, // 45 entries, filled by ATTR_TRN
+    pub mask: Mask,
+    frozen: Option<Vec<u8>>,
+
+    // --- border ---
+    border_tiles: Vec<u8>,      // 256 SNES 4bpp tiles, 32 bytes each
+    border_map: [u16; 32 * 28], // tile | palette << 10 | xflip << 14 | yflip << 15
+    border_palettes: [[u16; 16]; 4], // SNES palettes 4-7
+
+    pub pending: Option<Transfer>,
+    pub frame: Vec<u8>, // RGBA8, SGB_WIDTH x SGB_HEIGHT
+}
+
+impl Default for Sgb {
+    fn default() -> Self { Self::new() }
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        // Until the game uploads its own, use the DMG greys
+        let grey = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+        Self {
+            last_joyp: 0x30,
+            receiving: false,
+            bits: 0,
+            packet: [0; 16],
+            command: Vec::new(),
+            players: 1,
+            player: 0,
+            palettes: [grey; 4],
+            sys_palettes: vec![grey; 512],
+            attrs: [0; ATTR_W * ATTR_H],
+            attr_files: vec![[0; 90]; 45],
+            mask: Mask::Off,
+            frozen: None,
+            border_tiles: vec![0; 256 * 32],
+            border_map: [0; 32 * 28],
+            border_palettes: [[0; 16]; 4],
+            pending: None,
+            frame: vec![0xFF; SGB_WIDTH * SGB_HEIGHT * 4],
+        }
+    }
+
+    // --- JOYP side ---
+
+    /// Low nibble seen on JOYP when neither button group is selected
+    pub fn joyp_id(&self) -> u8 {
+        0x0F - self.player
+    }
+
+    pub fn write_joyp(&mut self, val: u8) {
+        let p = val & 0x30;
+        let prev = self.last_joyp;
+        self.last_joyp = p;
+
+        // MLT_REQ: each rising edge of P15 moves on to the next controller
+        if self.players > 1 && prev & 0x20 == 0 && p & 0x20 != 0 {
+            self.player = (self.player + 1) % self.players;
+        }
+
+        match p {
+            0x00 => {
+                self.receiving = true;
+                self.bits = 0;
+                self.packet = [0; 16];
+            }
+            // A bit is only latched on the first write of each pulse
+            0x10 | 0x20 if self.receiving && prev == 0x30 => {
+                let bit = (p == 0x10) as u8;
+                if self.bits == 128 {
+                    // Stop bit: must be 0 for the packet to count
+                    self.receiving = false;
+                    if bit == 0 {
+                        self.packet_done();
+                    }
+                } else {
+                    self.packet[self.bits / 8] |= bit << (self.bits % 8);
+                    self.bits += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn packet_done(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let len = (self.command[0] & 0x07).max(1) as usize;
+        if self.command.len() >= len * 16 {
+            let cmd = std::mem::take(&mut self.command);
+            self.execute(&cmd);
+        }
+    }
+
+    // --- Commands ---
+
+    fn execute(&mut self, d: &[u8]) {
+        match d[0] >> 3 {
+            0x00 => self.set_pair(0, 1, d),
+            0x01 => self.set_pair(2, 3, d),
+            0x02 => self.set_pair(0, 3, d),
+            0x03 => self.set_pair(1, 2, d),
+            0x04 => self.attr_blk(d),
+            0x05 => self.attr_lin(d),
+            0x06 => self.attr_div(d),
+            0x07 => self.attr_chr(d),
+            0x0A => self.pal_set(d),
+            0x0B => self.pending = Some(Transfer::Palettes),
+            0x11 => {
+                self.players = match d[1] & 0x03 { 1 => 2, 3 => 4, _ => 1 };
+                self.player = 0;
+            }
+            0x13 => self.pending = Some(Transfer::BorderTiles(d[1] & 0x01)),
+            0x14 => self.pending = Some(Transfer::BorderMap),
+            0x15 => self.pending = Some(Transfer::AttrFiles),
+            0x16 => {
+                self.apply_attr_file(d[1] & 0x3F);
+                if d[1] & 0x40 != 0 { self.set_mask(Mask::Off); }
+            }
+            0x17 => self.set_mask(match d[1] & 0x03 { 1 => Mask::Freeze, 2 => Mask::Black, 3 => Mask::Color0, _ => Mask::Off }),
+            _ => {} // sound, SNES-side code upload and the like are ignored
+        }
+    }
+
+    fn set_mask(&mut self, mask: Mask) {
+        self.mask = mask;
+        if mask != Mask::Freeze { self.frozen = None; }
+    }
+
+    // PAL01/PAL23/PAL03/PAL12: colour 0 is shared by all four palettes
+    fn set_pair(&mut self, a: usize, b: usize, d: &[u8]) {
+        let c = |i: usize| u16::from_le_bytes([d[1 + i * 2], d[2 + i * 2]]) & 0x7FFF;
+        for p in self.palettes.iter_mut() { p[0] = c(0); }
+        self.palettes[a][1..].copy_from_slice(&[c(1), c(2), c(3)]);
+        self.palettes[b][1..].copy_from_slice(&[c(4), c(5), c(6)]);
+    }
+
+    fn attr_blk(&mut self, d: &[u8]) {
+        let sets = (d[1] as usize).min(18);
+        for set in d[2..].chunks_exact(6).take(sets) {
+            let (ctrl, pals) = (set[0] & 0x07, set[1]);
+            let (x1, y1, x2, y2) = (set[2] as usize, set[3] as usize, set[4] as usize, set[5] as usize);
+            let (inside, mut border, outside) = (pals & 0x03, (pals >> 2) & 0x03, (pals >> 4) & 0x03);
+            // With only one of inside/outside given, the border takes its palette
+            if ctrl == 0x01 { border = inside; }
+            if ctrl == 0x04 { border = outside; }
+
+            for y in 0..ATTR_H {
+                for x in 0..ATTR_W {
+                    let in_x = x > x1 && x < x2;
+                    let in_y = y > y1 && y < y2;
+                    let on_edge = (x >= x1 && x <= x2 && y >= y1 && y <= y2) && !(in_x && in_y);
+                    let pal = if in_x && in_y {
+                        (ctrl & 0x01 != 0).then_some(inside)
+                    } else if on_edge {
+                        (ctrl & 0x02 != 0 || ctrl == 0x01 || ctrl == 0x04).then_some(border)
+                    } else {
+                        (ctrl & 0x04 != 0).then_some(outside)
+                    };
+                    if let Some(p) = pal { self.attrs[y * ATTR_W + x] = p; }
+                }
+            }
+        }
+    }
+
+    fn attr_lin(&mut self, d: &[u8]) {
+        let sets = d[1] as usize;
+        for &b in d[2..].iter().take(sets) {
+            let (line, pal) = ((b & 0x1F) as usize, (b >> 5) & 0x03);
+            if b & 0x80 != 0 {
+                if line < ATTR_H { self.attrs[line * ATTR_W..(line + 1) * ATTR_W].fill(pal); }
+            } else if line < ATTR_W {
+                (0..ATTR_H).for_each(|y| self.attrs[y * ATTR_W + line] = pal);
+            }
+        }
+    }
+
+    fn attr_div(&mut self, d: &[u8]) {
+        let (after, before, on) = (d[1] & 0x03, (d[1] >> 2) & 0x03, (d[1] >> 4) & 0x03);
+        let horizontal = d[1] & 0x40 != 0;
+        let at = d[2] as usize;
+        for y in 0..ATTR_H {
+            for x in 0..ATTR_W {
+                let pos = if horizontal { y } else { x };
+                self.attrs[y * ATTR_W + x] = match pos.cmp(&at) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    fn attr_chr(&mut self, d: &[u8]) {
+        let (mut x, mut y) = (d[1] as usize, d[2] as usize);
+        let count = (u16::from_le_bytes([d[3], d[4]]) as usize).min(ATTR_W * ATTR_H);
+        let vertical = d[5] & 0x01 != 0;
+        for i in 0..count {
+            let Some(&byte) = d.get(6 + i / 4) else { break };
+            if x >= ATTR_W || y >= ATTR_H { break; }
+            self.attrs[y * ATTR_W + x] = (byte >> (6 - (i % 4) * 2)) & 0x03;
+            if vertical {
+                y += 1;
+                if y == ATTR_H { y = 0; x += 1; }
+            } else {
+                x += 1;
+                if x == ATTR_W { x = 0; y += 1; }
+            }
+        }
+    }
+
+    fn pal_set(&mut self, d: &[u8]) {
+        for i in 0..4 {
+            let n = (u16::from_le_bytes([d[1 + i * 2], d[2 + i * 2]]) & 0x1FF) as usize;
+            self.palettes[i] = self.sys_palettes[n];
+        }
+        // Colour 0 of palette 0 is the one shared by everybody
+        let c0 = self.palettes[0][0];
+        self.palettes.iter_mut().for_each(|p| p[0] = c0);
+        if d[9] & 0x80 != 0 { self.apply_attr_file(d[9] & 0x3F); }
+        if d[9] & 0x40 != 0 { self.set_mask(Mask::Off); }
+    }
+
+    fn apply_attr_file(&mut self, n: u8) {
+        if let Some(file) = self.attr_files.get(n as usize) {
+            for i in 0..ATTR_W * ATTR_H {
+                self.attrs[i] = (file[i / 4] >> (6 - (i % 4) * 2)) & 0x03;
+            }
+        }
+    }
+
+    /// Finishes a pending VRAM transfer with the 4KB of tile data on screen.
+    pub fn finish_transfer(&mut self, data: &[u8]) {
+        let Some(transfer) = self.pending.take() else { return };
+        let word = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        match transfer {
+            Transfer::Palettes => {
+                for (n, pal) in self.sys_palettes.iter_mut().enumerate() {
+                    for (c, color) in pal.iter_mut().enumerate() {
+                        *color = word(n * 8 + c * 2) & 0x7FFF;
+                    }
+                }
+            }
+            Transfer::BorderTiles(half) => {
+                let start = half as usize * 0x1000;
+                self.border_tiles[start..start + 0x1000].copy_from_slice(&data[..0x1000]);
+            }
+            Transfer::BorderMap => {
+                for (i, entry) in self.border_map.iter_mut().enumerate() {
+                    *entry = word(i * 2);
+                }
+                for (p, pal) in self.border_palettes.iter_mut().enumerate() {
+                    for (c, color) in pal.iter_mut().enumerate() {
+                        *color = word(0x800 + p * 32 + c * 2) & 0x7FFF;
+                    }
+                }
+            }
+            Transfer::AttrFiles => {
+                for (n, file) in self.attr_files.iter_mut().enumerate() {
+                    file.copy_from_slice(&data[n * 90..n * 90 + 90]);
+                }
+            }
+        }
+    }
+
+    // --- Output ---
+
+    fn border_pixel(&self, x: usize, y: usize) -> Option<u16> {
+        let entry = self.border_map[(y / 8) * 32 + x / 8];
+        let tile = (entry & 0xFF) as usize;
+        let pal = ((entry >> 10) & 0x07) as usize;
+        let (mut tx, mut ty) = (x % 8, y % 8);
+        if entry & 0x4000 != 0 { tx = 7 - tx; }
+        if entry & 0x8000 != 0 { ty = 7 - ty; }
+
+        let t = &self.border_tiles[tile * 32..tile * 32 + 32];
+        let bit = 7 - tx;
+        let planes = [t[ty * 2], t[ty * 2 + 1], t[16 + ty * 2], t[16 + ty * 2 + 1]];
+        let id = planes.iter().enumerate().fold(0, |acc, (i, p)| acc | ((p >> bit) & 1) << i) as usize;
+        if id == 0 || pal < 4 { return None; }
+        Some(self.border_palettes[pal - 4][id])
+    }
+
+    /// Composites the border and the colourised game screen into `frame`.
+    /// `shades` is the PPU's per-pixel shade buffer (`Ppu::pixels`).
+    pub fn render(&mut self, shades: &[u8]) -> &[u8] {
+        if self.mask == Mask::Freeze && self.frozen.is_none() {
+            self.frozen = Some(shades.to_vec());
+        }
+        let backdrop = self.palettes[0][0];
+
+        for y in 0..SGB_HEIGHT {
+            for x in 0..SGB_WIDTH {
+                let in_screen = (SCREEN_X..SCREEN_X + 160).contains(&x) && (SCREEN_Y..SCREEN_Y + 144).contains(&y);
+                let color = match self.border_pixel(x, y) {
+                    Some(c) => c,
+                    None if in_screen => {
+                        let (gx, gy) = (x - SCREEN_X, y - SCREEN_Y);
+                        let shade = self.frozen.as_deref().unwrap_or(shades)[gy * 160 + gx] & 0x03;
+                        match self.mask {
+                            Mask::Black => 0x0000,
+                            Mask::Color0 => backdrop,
+                            _ => self.palettes[self.attrs[(gy / 8) * ATTR_W + gx / 8] as usize][shade as usize],
+                        }
+                    }
+                    None => backdrop,
+                };
+                let [r, g, b] = bgr555_to_rgb(color);
+                let i = (y * SGB_WIDTH + x) * 4;
+                self.frame[i..i + 4].copy_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+        &self.frame
+    }
+}
@@ -0,0 +1,106 @@
+// Symbol files in the RGBDS / no$gmb `.sym` format: one `BB:AAAA Label` per
+// line, `;` starts a comment. Banks are hex like addresses; a label outside a
+// banked region (ROM0, WRAM0, HRAM) has bank 00.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::debugger::{parse_hex, Location};
+use crate::gameboy::GameBoy;
+
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    by_name: HashMap<String, Location>,
+    by_addr: BTreeMap<(usize, u16), String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut syms = Self::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() { continue; }
+            let (at, name) = line.split_once(char::is_whitespace).ok_or(format!("line {}: expected 'BB:AAAA Label'", n + 1))?;
+            let (bank, addr) = at.split_once(':').ok_or(format!("line {}: expected 'BB:AAAA Label'", n + 1))?;
+            let at = Location { bank: Some(parse_hex(bank)? as usize), addr: parse_hex(addr)? };
+            syms.insert(name.trim(), at);
+        }
+        Ok(syms)
+    }
+
+    /// Adds or replaces a label. One without a bank is filed under bank 00.
+    pub fn insert(&mut self, name: &str, at: Location) {
+        self.by_addr.insert((at.bank.unwrap_or(0), at.addr), name.to_string());
+        self.by_name.insert(name.to_string(), at);
+    }
+
+    /// Adds every label from `other`, replacing clashes
+    pub fn merge(&mut self, other: Symbols) {
+        for (name, at) in other.by_name { self.insert(&name, at); }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Location> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The label exactly at `addr` in `bank`
+    pub fn name_at(&self, bank: usize, addr: u16) -> Option<&str> {
+        self.by_addr.get(&(bank, addr)).map(String::as_str)
+    }
+
+    /// The closest label at or before `addr` in the same bank and memory region, with the offset from it
+    pub fn nearest(&self, bank: usize, addr: u16) -> Option<(&str, u16)> {
+        let (&(b, at), name) = self.by_addr.range(..=(bank, addr)).next_back()?;
+        (b == bank && region(at) == region(addr)).then(|| (name.as_str(), addr - at))
+    }
+
+    /// `Bank:Label+offset` for an address as `gb` currently maps it, or `Bank:AAAA`
+    /// without a label. The bank is left off outside banked regions.
+    pub fn describe(&self, gb: &GameBoy, addr: u16) -> String {
+        self.describe_at(gb.mmu.bank_at(addr), addr)
+    }
+
+    /// Like `describe`, for an address in a given bank
+    pub fn describe_at(&self, bank: usize, addr: u16) -> String {
+        let prefix = if banked(addr) { format!("{bank:02X}:") } else { String::new() };
+        match self.nearest(bank, addr) {
+            Some((name, 0)) => format!("{prefix}{name}"),
+            Some((name, off)) => format!("{prefix}{name}+{off:X}"),
+            None => format!("{prefix}{addr:04X}"),
+        }
+    }
+}
+
+// Regions a label cannot reach across: ROM0, ROMX, VRAM, SRAM, WRAM0, WRAMX, OAM/IO, HRAM
+fn region(addr: u16) -> u8 {
+    match addr {
+        0x0000..=0x3FFF => 0,
+        0x4000..=0x7FFF => 1,
+        0x8000..=0x9FFF => 2,
+        0xA000..=0xBFFF => 3,
+        0xC000..=0xCFFF => 4,
+        0xD000..=0xDFFF => 5,
+        0xE000..=0xFF7F => 6,
+        _ => 7,
+    }
+}
+
+fn banked(addr: u16) -> bool {
+    matches!(region(addr), 1 | 2 | 3 | 5)
+}
@@ -0,0 +1,261 @@
+// Link cable over a local TCP socket, one emulator process per end.
+//
+// Wire format: every frame is a tag byte followed by a fixed payload.
+//   HELLO    0x00  "GBLK" version:u8 flags:u8   (flags bit 0: CGB fast clock capable)
+//   TRANSFER 0x01  cycle:u64 byte:u8 flags:u8   master clocked a byte out (flags bit 0: fast clock)
+//   REPLY    0x02  byte:u8                      what the other side shifted back
+//   SYNC     0x03  cycle:u64                    how far this side has emulated
+//
+// A background thread answers TRANSFERs straight from the last SB this side
+// armed on the external clock, so a master only waits one round trip, and
+// holds the byte back until this side has emulated up to the cycle the master
+// stamped on it. The fast clock only goes over the wire when the peer said it
+// can shift at that rate; otherwise the peer shifts at the normal 8192 Hz.
+// Waiting on replies is capped per `sync`, so a silent peer costs a frame at
+// most a bounded stall rather than a second per byte.
+//
+// SYNC keeps the two cores within `window` cycles of each other: a side that
+// gets further ahead blocks until its peer catches up, which hides latency up
+// to that window without letting the two drift apart.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::serial::{SerialEndpoint, BIT_PERIOD, FAST_BIT_PERIOD};
+use crate::MAX_FRAME_CYCLES;
+
+const MAGIC: &[u8; 4] = b"GBLK";
+const VERSION: u8 = 1;
+
+const TAG_HELLO: u8 = 0x00;
+const TAG_TRANSFER: u8 = 0x01;
+const TAG_REPLY: u8 = 0x02;
+const TAG_SYNC: u8 = 0x03;
+
+pub const EXCHANGE_BUDGET: Duration = Duration::from_millis(16); // reply waits allowed between two syncs
+pub const SYNC_TIMEOUT: Duration = Duration::from_millis(2000);
+
+struct Delivery {
+    byte: u8,
+    cycle: u64, // peer's clock when it started the transfer
+    bit_period: u32,
+}
+
+#[derive(Default)]
+struct State {
+    armed: Option<u8>,           // SB while waiting on the external clock
+    delivered: Option<Delivery>, // byte clocked in by the peer, not yet picked up
+    reply: Option<u8>,           // answer to our last TRANSFER
+    wait_left: Duration,         // what is left of EXCHANGE_BUDGET until the next sync
+    local_cycle: u64,
+    peer_cycle: u64,
+    peer_fast: bool,             // peer can run the CGB fast serial clock
+    closed: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+    writer: Mutex<TcpStream>,
+}
+
+impl Shared {
+    fn send(&self, frame: &[u8]) {
+        let mut w = self.writer.lock().unwrap();
+        if w.write_all(frame).is_err() {
+            drop(w);
+            self.close();
+        }
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.cond.notify_all();
+    }
+}
+
+pub struct TcpLink {
+    shared: Arc<Shared>,
+    pub window: u64, // cycles this side may run ahead of the peer
+}
+
+impl TcpLink {
+    /// Binds `addr` and waits for one peer to connect
+    pub fn host<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::accept(TcpListener::bind(addr)?)
+    }
+
+    /// Waits for one peer on an already bound listener
+    pub fn accept(listener: TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        Self::start(stream)
+    }
+
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::start(TcpStream::connect(addr)?)
+    }
+
+    fn start(mut stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+
+        // --- Handshake: both sides send HELLO, then check the peer's ---
+        let mut hello = vec![TAG_HELLO];
+        hello.extend_from_slice(MAGIC);
+        hello.extend_from_slice(&[VERSION, 0x01]);
+        stream.write_all(&hello)?;
+
+        stream.set_read_timeout(Some(SYNC_TIMEOUT))?;
+        let mut peer = [0u8; 7];
+        stream.read_exact(&mut peer)?;
+        if peer[0] != TAG_HELLO || &peer[1..5] != MAGIC || peer[5] != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "peer is not a compatible link cable"));
+        }
+        stream.set_read_timeout(None)?;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { peer_fast: peer[6] & 0x01 != 0, wait_left: EXCHANGE_BUDGET, ..State::default() }),
+            cond: Condvar::new(),
+            writer: Mutex::new(stream.try_clone()?),
+        });
+
+        let reader = shared.clone();
+        thread::spawn(move || {
+            let _ = read_loop(stream, &reader);
+            reader.close();
+        });
+
+        Ok(Self { shared, window: MAX_FRAME_CYCLES as u64 })
+    }
+
+    /// Serial endpoint to plug into `Mmu::serial`
+    pub fn endpoint(&self) -> TcpPort {
+        TcpPort { shared: self.shared.clone() }
+    }
+
+    pub fn peer_supports_fast_clock(&self) -> bool {
+        self.shared.state.lock().unwrap().peer_fast
+    }
+
+    pub fn is_connected(&self) -> bool {
+        !self.shared.state.lock().unwrap().closed
+    }
+
+    /// Reports that this side has emulated `cycles` in total and blocks while
+    /// it is more than `window` cycles ahead of the peer. Also refills the
+    /// time masters may spend waiting on replies.
+    /// Returns false if the peer went away or stopped answering.
+    pub fn sync(&self, cycles: u64) -> bool {
+        let mut frame = vec![TAG_SYNC];
+        frame.extend_from_slice(&cycles.to_le_bytes());
+        self.shared.send(&frame);
+
+        let mut state = self.shared.state.lock().unwrap();
+        state.local_cycle = cycles;
+        state.wait_left = EXCHANGE_BUDGET;
+        let window = self.window;
+        let (state, timeout) = self.shared.cond
+            .wait_timeout_while(state, SYNC_TIMEOUT, |s| !s.closed && cycles > s.peer_cycle + window)
+            .unwrap();
+        !state.closed && !timeout.timed_out()
+    }
+}
+
+fn read_loop(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    loop {
+        let mut tag = [0u8; 1];
+        stream.read_exact(&mut tag)?;
+        match tag[0] {
+            TAG_TRANSFER => {
+                let mut p = [0u8; 10];
+                stream.read_exact(&mut p)?;
+                let delivery = Delivery {
+                    byte: p[8],
+                    cycle: u64::from_le_bytes(p[..8].try_into().unwrap()),
+                    bit_period: if p[9] & 0x01 != 0 { FAST_BIT_PERIOD } else { BIT_PERIOD },
+                };
+                // Answer with our SB if we are listening, else the line floats high
+                let out = {
+                    let mut s = shared.state.lock().unwrap();
+                    match s.armed.take() {
+                        Some(sb) => {
+                            s.delivered = Some(delivery);
+                            sb
+                        }
+                        None => 0xFF,
+                    }
+                };
+                shared.send(&[TAG_REPLY, out]);
+            }
+            TAG_REPLY => {
+                let mut p = [0u8; 1];
+                stream.read_exact(&mut p)?;
+                shared.state.lock().unwrap().reply = Some(p[0]);
+                shared.cond.notify_all();
+            }
+            TAG_SYNC => {
+                let mut p = [0u8; 8];
+                stream.read_exact(&mut p)?;
+                shared.state.lock().unwrap().peer_cycle = u64::from_le_bytes(p);
+                shared.cond.notify_all();
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown link frame")),
+        }
+    }
+}
+
+pub struct TcpPort {
+    shared: Arc<Shared>,
+}
+
+impl SerialEndpoint for TcpPort {
+    fn exchange(&mut self, out: u8) -> u8 {
+        let now = self.shared.state.lock().unwrap().local_cycle;
+        self.exchange_at(out, BIT_PERIOD, now)
+    }
+
+    fn exchange_at(&mut self, out: u8, bit_period: u32, now: u64) -> u8 {
+        let fast = {
+            let mut s = self.shared.state.lock().unwrap();
+            if s.closed { return 0xFF; }
+            s.reply = None;
+            // A peer without the fast clock shifts at the normal rate instead
+            bit_period == FAST_BIT_PERIOD && s.peer_fast
+        };
+        let mut frame = vec![TAG_TRANSFER];
+        frame.extend_from_slice(&now.to_le_bytes());
+        frame.extend_from_slice(&[out, fast as u8]);
+        self.shared.send(&frame);
+
+        let started = Instant::now();
+        let state = self.shared.state.lock().unwrap();
+        let budget = state.wait_left;
+        let (mut state, _) = self.shared.cond
+            .wait_timeout_while(state, budget, |s| !s.closed && s.reply.is_none())
+            .unwrap();
+        state.wait_left = budget.saturating_sub(started.elapsed());
+        state.reply.take().unwrap_or(0xFF)
+    }
+
+    fn poll_external(&mut self, out: u8) -> Option<(u8, u32)> {
+        let mut s = self.shared.state.lock().unwrap();
+        match s.delivered.take() {
+            // Not before this side has caught up with the master's clock
+            Some(d) if d.cycle > s.local_cycle => {
+                s.delivered = Some(d);
+                None
+            }
+            Some(d) => Some((d.byte, d.bit_period)),
+            None => {
+                s.armed = Some(out);
+                None
+            }
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.shared.state.lock().unwrap().armed = None;
+    }
+}
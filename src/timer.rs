@@ -0,0 +1,154 @@
+// DIV/TIMA/TMA/TAC (FF04-FF07) driven by the 16-bit system counter.
+//
+// DIV is the top byte of the counter. TIMA ticks on a falling edge of one
+// counter bit (picked by TAC) ANDed with the enable bit, which is why
+// resetting DIV or rewriting TAC can bump TIMA. On overflow TIMA reads 0x00
+// for one M-cycle before TMA is loaded and the interrupt fires; writing TIMA
+// in that window cancels both.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+enum Reload {
+    #[default]
+    Idle,
+    Pending, // TIMA overflowed this M-cycle and reads 0x00
+    Done,    // TMA was loaded this M-cycle: TIMA writes are ignored, TMA writes go through
+}
+
+#[derive(Clone, Debug)]
+pub struct Timer {
+    counter: u16,
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+    reload: Reload,
+}
+
+impl Default for Timer {
+    fn default() -> Self { Self::new() }
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self { counter: 0, tima: 0, tma: 0, tac: 0xF8, reload: Reload::Idle }
+    }
+
+    pub fn div(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    // The counter bit TIMA watches, gated by the enable bit
+    fn signal(&self) -> bool {
+        self.tac & 0x04 != 0 && (self.counter >> self.bit()) & 1 != 0
+    }
+
+    fn bit(&self) -> u32 {
+        match self.tac & 0x03 {
+            0x00 => 9, // 4096 Hz
+            0x01 => 3, // 262144 Hz
+            0x02 => 5, // 65536 Hz
+            _    => 7, // 16384 Hz
+        }
+    }
+
+    // T-cycles until the next falling edge of the watched bit (it falls when the
+    // counter crosses a multiple of twice its weight)
+    fn until_edge(&self) -> u32 {
+        let period = 2u32 << self.bit();
+        period - (self.counter as u32 & (period - 1))
+    }
+
+    /// T-cycles from now until the timer interrupt fires if nothing is written, if enabled
+    pub fn cycles_until_irq(&self) -> Option<u32> {
+        if self.reload == Reload::Pending { return Some(4); }
+        if self.tac & 0x04 == 0 { return None; }
+        let period = 2u32 << self.bit();
+        // The increment that overflows, then one M-cycle until TMA is loaded
+        Some(self.until_edge() + (0xFF - self.tima as u32) * period + 4)
+    }
+
+    fn increment_tima(&mut self) {
+        let (v, overflow) = self.tima.overflowing_add(1);
+        self.tima = v;
+        if overflow { self.reload = Reload::Pending; }
+    }
+
+    /// Advances by `cycles` T-cycles. Returns true if the timer interrupt fired.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        let mut irq = false;
+        let mut left = cycles / 4;
+        while left > 0 {
+            // Nothing but the counter moves until the next edge: skip straight to it
+            if self.reload == Reload::Idle {
+                let quiet = if self.tac & 0x04 == 0 { left } else { (self.until_edge() / 4 - 1).min(left) };
+                self.counter = self.counter.wrapping_add(quiet.wrapping_mul(4) as u16);
+                left -= quiet;
+                if left == 0 { break; }
+            }
+
+            match self.reload {
+                Reload::Pending => {
+                    self.tima = self.tma;
+                    self.reload = Reload::Done;
+                    irq = true;
+                }
+                Reload::Done => self.reload = Reload::Idle,
+                Reload::Idle => {}
+            }
+
+            let before = self.signal();
+            self.counter = self.counter.wrapping_add(4);
+            if before && !self.signal() { self.increment_tima(); }
+            left -= 1;
+        }
+        irq
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF04 => self.div(),
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            _      => self.tac | 0xF8,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // Any write clears the whole counter, which can be a falling edge
+            0xFF04 => {
+                let before = self.signal();
+                self.counter = 0;
+                if before { self.increment_tima(); }
+            }
+            0xFF05 => match self.reload {
+                Reload::Pending => { self.tima = val; self.reload = Reload::Idle; }
+                Reload::Done => {}
+                Reload::Idle => self.tima = val,
+            },
+            0xFF06 => {
+                self.tma = val;
+                if self.reload == Reload::Done { self.tima = val; }
+            }
+            _ => {
+                let before = self.signal();
+                self.tac = val | 0xF8;
+                if before && !self.signal() { self.increment_tima(); }
+            }
+        }
+    }
+
+    /// Sets a register without any of the write side effects (post-boot state, debuggers).
+    /// Writing DIV sets the top byte of the counter.
+    pub fn set_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xFF04 => self.counter = (val as u16) << 8,
+            0xFF05 => self.tima = val,
+            0xFF06 => self.tma = val,
+            _      => self.tac = val | 0xF8,
+        }
+    }
+}
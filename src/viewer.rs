@@ -0,0 +1,224 @@
+// Debug renderers for VRAM, OAM and the palettes. They read memory without
+// side effects and colour things the way the PPU would right now: DMG shades
+// through BGP/OBP0/OBP1 and the frontend palette, CGB through palette RAM.
+//
+// Images are RGBA8. Sizes are fixed:
+//   tiles     128x192  384 tiles of one VRAM bank, 16 per row, from 0x8000
+//   tilemaps  512x256  the 9800 and 9C00 maps side by side, drawn with the current
+//                      LCDC tile addressing; red outlines the SCX/SCY viewport on
+//                      the BG map (wrapping), blue the visible window on its map
+//   oam       128x120  the 40 sprites, 8 per row in 16x24 cells
+//   palettes  64x48    BGP, OBP0, OBP1 (DMG), or 64x256 for 8 BG + 8 OBJ palettes (CGB)
+//
+// The raster table lists the lines of the last frame whose registers differ
+// from the line above, then every LCD register write with its line and dot.
+
+use std::fmt::Write;
+
+use crate::cgb::bgr555_to_rgb;
+use crate::mmu::Mmu;
+use crate::palette::{argb32, Layer};
+use crate::ppu::{LineRegs, Ppu};
+
+const EMPTY: [u8; 3] = [0x30, 0x30, 0x38];   // background and sprite transparency
+const VIEWPORT: [u8; 3] = [0xFF, 0x30, 0x30];
+const WINDOW: [u8; 3] = [0x30, 0x80, 0xFF];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        let rgba = [EMPTY[0], EMPTY[1], EMPTY[2], 0xFF].repeat(width * height);
+        Self { width, height, rgba }
+    }
+
+    fn set(&mut self, x: usize, y: usize, [r, g, b]: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let i = (y * self.width + x) * 4;
+            self.rgba[i..i + 3].copy_from_slice(&[r, g, b]);
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * self.width + x) * 4;
+        [self.rgba[i], self.rgba[i + 1], self.rgba[i + 2]]
+    }
+
+    // A w x h rectangle outline at (x, y) within the `size` x `size` square at
+    // (ox, 0), wrapping around its edges like the BG map does
+    fn outline(&mut self, ox: usize, size: usize, (x, y, w, h): (usize, usize, usize, usize), rgb: [u8; 3]) {
+        for i in 0..w {
+            self.set(ox + (x + i) % size, y % size, rgb);
+            self.set(ox + (x + i) % size, (y + h - 1) % size, rgb);
+        }
+        for j in 0..h {
+            self.set(ox + x % size, (y + j) % size, rgb);
+            self.set(ox + (x + w - 1) % size, (y + j) % size, rgb);
+        }
+    }
+
+    /// ARGB32 words for minifb
+    pub fn argb32(&self) -> Vec<u32> {
+        self.rgba.chunks_exact(4).map(|p| argb32([p[0], p[1], p[2]])).collect()
+    }
+}
+
+// Colour ids (0-3) of one 8-pixel row of the tile at `addr`
+fn tile_row(mmu: &Mmu, bank: usize, addr: u16, row: u16) -> [u8; 8] {
+    let (lo, hi) = (mmu.vram_at(bank, addr + row * 2), mmu.vram_at(bank, addr + row * 2 + 1));
+    std::array::from_fn(|x| ((hi >> (7 - x)) & 1) << 1 | ((lo >> (7 - x)) & 1))
+}
+
+fn bg_color(mmu: &Mmu, ppu: &Ppu, palette: u8, id: u8) -> [u8; 3] {
+    if mmu.is_cgb() { return bgr555_to_rgb(mmu.bg_palettes.color(palette, id)); }
+    ppu.palettes[Layer::Bg as usize].rgb(mmu.io[0x47] >> (id * 2))
+}
+
+// `palette` is OBP0/1 on DMG and 0-7 on CGB
+fn obj_color(mmu: &Mmu, ppu: &Ppu, palette: u8, id: u8) -> [u8; 3] {
+    if mmu.is_cgb() { return bgr555_to_rgb(mmu.obj_palettes.color(palette, id)); }
+    let layer = if palette & 1 == 0 { Layer::Obj0 } else { Layer::Obj1 };
+    ppu.palettes[layer as usize].rgb(mmu.io[0x48 + (palette & 1) as usize] >> (id * 2))
+}
+
+/// Every tile in VRAM bank `bank` (0, or 1 on CGB), coloured with BG palette 0
+pub fn tiles(mmu: &Mmu, ppu: &Ppu, bank: usize) -> Image {
+    let mut img = Image::new(128, 192);
+    for tile in 0..384u16 {
+        let (tx, ty) = ((tile % 16) as usize * 8, (tile / 16) as usize * 8);
+        for row in 0..8 {
+            for (x, id) in tile_row(mmu, bank, 0x8000 + tile * 16, row).into_iter().enumerate() {
+                img.set(tx + x, ty + row as usize, bg_color(mmu, ppu, 0, id));
+            }
+        }
+    }
+    img
+}
+
+/// Both BG maps with the viewport and window outlined
+pub fn tilemaps(mmu: &Mmu, ppu: &Ppu) -> Image {
+    let lcdc = mmu.io[0x40];
+    let mut img = Image::new(512, 256);
+    for (m, base) in [0x9800u16, 0x9C00].into_iter().enumerate() {
+        for i in 0..1024u16 {
+            let tile = mmu.vram_at(0, base + i);
+            let attr = if mmu.is_cgb() { mmu.vram_at(1, base + i) } else { 0 };
+            let addr = if lcdc & 0x10 != 0 { 0x8000 + tile as u16 * 16 } else { (0x9000 + tile as i8 as i32 * 16) as u16 };
+            let bank = ((attr >> 3) & 1) as usize;
+            let (tx, ty) = (m * 256 + (i % 32) as usize * 8, (i / 32) as usize * 8);
+            for row in 0..8u16 {
+                let src_row = if attr & 0x40 != 0 { 7 - row } else { row };
+                let ids = tile_row(mmu, bank, addr, src_row);
+                for x in 0..8 {
+                    let id = ids[if attr & 0x20 != 0 { 7 - x } else { x }];
+                    img.set(tx + x, ty + row as usize, bg_color(mmu, ppu, attr & 7, id));
+                }
+            }
+        }
+    }
+    let bg_map = if lcdc & 0x08 != 0 { 256 } else { 0 };
+    img.outline(bg_map, 256, (mmu.io[0x43] as usize, mmu.io[0x42] as usize, 160, 144), VIEWPORT);
+    let (wx, wy) = (mmu.io[0x4B] as usize, mmu.io[0x4A] as usize);
+    if lcdc & 0x20 != 0 && wx <= 166 && wy <= 143 {
+        let win_map = if lcdc & 0x40 != 0 { 256 } else { 0 };
+        img.outline(win_map, 256, (0, 0, 167 - wx.max(7), 144 - wy), WINDOW);
+    }
+    img
+}
+
+/// The 40 OAM entries as sprites, in 16x24 cells
+pub fn oam(mmu: &Mmu, ppu: &Ppu) -> Image {
+    let tall = mmu.io[0x40] & 0x04 != 0;
+    let mut img = Image::new(128, 120);
+    for (n, s) in mmu.oam.chunks_exact(4).enumerate() {
+        let (cx, cy) = ((n % 8) * 16 + 4, (n / 8) * 24 + 4);
+        let (tile, attr) = (if tall { s[2] & 0xFE } else { s[2] }, s[3]);
+        let height = if tall { 16 } else { 8 };
+        let (bank, palette) = if mmu.is_cgb() { (((attr >> 3) & 1) as usize, attr & 7) } else { (0, (attr >> 4) & 1) };
+        for row in 0..height {
+            let src_row = if attr & 0x40 != 0 { height - 1 - row } else { row };
+            let ids = tile_row(mmu, bank, 0x8000 + tile as u16 * 16, src_row);
+            for x in 0..8 {
+                let id = ids[if attr & 0x20 != 0 { 7 - x } else { x }];
+                if id != 0 { img.set(cx + x, cy + row as usize, obj_color(mmu, ppu, palette, id)); }
+            }
+        }
+    }
+    img
+}
+
+/// Swatches of every palette, one row of four 16x16 colours each
+pub fn palettes(mmu: &Mmu, ppu: &Ppu) -> Image {
+    let rows: Vec<[[u8; 3]; 4]> = if mmu.is_cgb() {
+        (0..8).map(|p| std::array::from_fn(|id| bg_color(mmu, ppu, p, id as u8)))
+            .chain((0..8).map(|p| std::array::from_fn(|id| obj_color(mmu, ppu, p, id as u8))))
+            .collect()
+    } else {
+        vec![
+            std::array::from_fn(|id| bg_color(mmu, ppu, 0, id as u8)),
+            std::array::from_fn(|id| obj_color(mmu, ppu, 0, id as u8)),
+            std::array::from_fn(|id| obj_color(mmu, ppu, 1, id as u8)),
+        ]
+    };
+    let mut img = Image::new(64, rows.len() * 16);
+    for (r, colors) in rows.iter().enumerate() {
+        for (id, &rgb) in colors.iter().enumerate() {
+            for y in 0..16 { for x in 0..16 { img.set(id * 16 + x, r * 16 + y, rgb); } }
+        }
+    }
+    img
+}
+
+/// One line per OAM entry: position, tile and decoded attributes
+pub fn oam_table(mmu: &Mmu) -> String {
+    let mut out = String::from(" #   Y   X tile attr  pal  flip  prio\n");
+    for (n, s) in mmu.oam.chunks_exact(4).enumerate() {
+        let attr = s[3];
+        let pal = if mmu.is_cgb() { format!("{}/{}", attr & 7, (attr >> 3) & 1) } else { format!("OBP{}", (attr >> 4) & 1) };
+        let flip = match attr & 0x60 { 0x20 => "x", 0x40 => "y", 0x60 => "xy", _ => "-" };
+        let prio = if attr & 0x80 != 0 { "behind" } else { "front" };
+        let hidden = if s[0] == 0 || s[0] >= 160 || s[1] == 0 || s[1] >= 168 { "  offscreen" } else { "" };
+        writeln!(out, "{n:>2} {:>3} {:>3}  ${:02X}  ${attr:02X} {pal:>4} {flip:>5} {prio:>6}{hidden}", s[0], s[1], s[2]).unwrap();
+    }
+    out
+}
+
+/// BGP, OBP0 and OBP1 with the shade each colour id maps to
+pub fn palette_table(mmu: &Mmu) -> String {
+    let mut out = String::new();
+    for (name, reg) in [("BGP", 0x47), ("OBP0", 0x48), ("OBP1", 0x49)] {
+        let v = mmu.io[reg];
+        let shades: Vec<String> = (0..4).map(|id| ((v >> (id * 2)) & 3).to_string()).collect();
+        writeln!(out, "{name:<4} ${v:02X}  ids 0-3 -> shades {}", shades.join(" ")).unwrap();
+    }
+    out
+}
+
+fn register_name(addr: u16) -> &'static str {
+    match addr {
+        0xFF40 => "LCDC", 0xFF42 => "SCY", 0xFF43 => "SCX", 0xFF45 => "LYC",
+        0xFF47 => "BGP", 0xFF48 => "OBP0", 0xFF49 => "OBP1", 0xFF4A => "WY", 0xFF4B => "WX",
+        _ => "?",
+    }
+}
+
+/// Per-line register changes and the writes behind them, from the last frame
+pub fn raster_table(ppu: &Ppu) -> String {
+    let mut out = String::from(" LY  LCDC SCY SCX  WY  WX BGP\n");
+    let mut above: Option<LineRegs> = None;
+    for (ly, &r) in ppu.line_regs.iter().enumerate() {
+        if above == Some(r) { continue; }
+        writeln!(out, "{ly:>3}   ${:02X} ${:02X} ${:02X} ${:02X} ${:02X} ${:02X}", r.lcdc, r.scy, r.scx, r.wy, r.wx, r.bgp).unwrap();
+        above = Some(r);
+    }
+    writeln!(out, "\n{} writes since the VBlank before\n LY dot  reg   old -> new", ppu.raster_events.len()).unwrap();
+    for e in &ppu.raster_events {
+        writeln!(out, "{:>3} {:>3}  {:<4}  ${:02X} -> ${:02X}", e.ly, e.dot, register_name(e.addr), e.old, e.new).unwrap();
+    }
+    out
+}
@@ -0,0 +1,1371 @@
+use pokegameboy::cdl::{Cdl, Format, CODE, DATA, INDIRECT_CODE, INDIRECT_DATA, JUMP_TARGET, OPCODE};
+use pokegameboy::cgb::bgr555_to_rgb;
+use pokegameboy::cheats::{self, Cheats, Code};
+use pokegameboy::debugger::{Debugger, Expr, Location, Stop, WatchKind};
+use pokegameboy::gameboy::GameBoy;
+use pokegameboy::gdb_stub::GdbStub;
+use pokegameboy::link::LinkCable;
+use pokegameboy::mmu::Mmu;
+use pokegameboy::model::Model;
+use pokegameboy::profiler::{Mode, Profiler};
+use pokegameboy::repl::Repl;
+use pokegameboy::search::{Compare, RamSearch, ValueType};
+use pokegameboy::symbols::Symbols;
+use pokegameboy::tcp_link::TcpLink;
+use pokegameboy::timer::Timer;
+use pokegameboy::viewer;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+// 32KB ROM-only cartridge with `code` at the 0x0100 entry point
+fn rom_with(code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x100 + code.len()].copy_from_slice(code);
+    rom
+}
+
+// Master: idle long enough for the slave to arm, then send 0x42 on the internal clock
+fn link_master() -> Vec<u8> {
+    rom_with(&[
+        0x06, 0x00,       // LD B,0
+        0x05,             // DEC B
+        0x20, 0xFD,       // JR NZ,-3
+        0x3E, 0x42,       // LD A,0x42
+        0xE0, 0x01,       // LDH (SB),A
+        0x3E, 0x81,       // LD A,0x81
+        0xE0, 0x02,       // LDH (SC),A
+        0x18, 0xFE,       // JR -2
+    ])
+}
+
+// Slave: load 0x99 and wait on the external clock
+fn link_slave() -> Vec<u8> {
+    rom_with(&[
+        0x3E, 0x99,       // LD A,0x99
+        0xE0, 0x01,       // LDH (SB),A
+        0x3E, 0x80,       // LD A,0x80
+        0xE0, 0x02,       // LDH (SC),A
+        0x18, 0xFE,       // JR -2
+    ])
+}
+
+fn assert_transfer_done(gb: &GameBoy, received: u8) {
+    assert_eq!(gb.mmu.read(0xFF01), received);
+    assert_eq!(gb.mmu.read(0xFF02) & 0x80, 0, "transfer still running");
+    assert_ne!(gb.mmu.read(0xFF0F) & 0x08, 0, "serial interrupt not raised");
+}
+
+#[test]
+fn link_cable_exchanges_one_byte_each_way() {
+    let mut link = LinkCable::new(GameBoy::new(link_master()), GameBoy::new(link_slave()));
+    link.run_frame();
+
+    assert_transfer_done(&link.a, 0x99);
+    assert_transfer_done(&link.b, 0x42);
+}
+
+#[test]
+fn link_cable_slave_shifts_at_the_master_clock() {
+    let mut link = LinkCable::new(GameBoy::new(link_master()), GameBoy::new(link_slave()));
+    let busy = |gb: &GameBoy| gb.mmu.read(0xFF02) & 0x80 != 0;
+    let (mut started, mut done) = (None, [None; 2]);
+    while done.contains(&None) {
+        link.run_for(4);
+        let now = [link.a.mmu.scheduler.now(), link.b.mmu.scheduler.now()];
+        started = started.or(busy(&link.a).then_some(now[0]));
+        for (side, gb) in [&link.a, &link.b].into_iter().enumerate() {
+            if started.is_some() && done[side].is_none() && !busy(gb) { done[side] = Some(now[side]); }
+        }
+        assert!(now[0] < 70224, "transfer never finished");
+    }
+    let (started, [master, slave]) = (started.unwrap(), done.map(Option::unwrap));
+    assert!((4096..4096 + 16).contains(&(master - started)), "master took {}", master - started);
+    assert!(slave.abs_diff(master) <= 128 + 16, "slave finished at {slave}, master at {master}");
+}
+
+// Swaps the 44 bytes at C000 (one Gen 1 party struct) with the partner, a byte
+// per transfer, storing what comes back at C100. The master idles before each
+// byte so the slave has re-armed.
+fn link_block(sc: u8, idle: u8) -> Vec<u8> {
+    rom_with(&[
+        0x21, 0x00, 0xC0, // LD HL,C000
+        0x06, 44,         // LD B,44
+        0x0E, idle,       // LD C,idle
+        0x0D, 0x20, 0xFD, // DEC C; JR NZ,-3
+        0x7E,             // LD A,(HL)
+        0xE0, 0x01,       // LDH (SB),A
+        0x3E, sc,         // LD A,sc
+        0xE0, 0x02,       // LDH (SC),A
+        0xF0, 0x02,       // LDH A,(SC)
+        0xE6, 0x80,       // AND 0x80
+        0x20, 0xFA,       // JR NZ,-6
+        0xF0, 0x01,       // LDH A,(SB)
+        0x24, 0x77, 0x25, // INC H; LD (HL),A; DEC H
+        0x2C,             // INC L
+        0x05, 0x20, 0xE5, // DEC B; JR NZ,-27
+        0x18, 0xFE,       // JR -2
+    ])
+}
+
+#[test]
+fn link_cable_swaps_a_block() {
+    let mut link = LinkCable::new(GameBoy::new(link_block(0x81, 0x40)), GameBoy::new(link_block(0x80, 1)));
+    let blocks: [Vec<u8>; 2] = [(0..44).map(|i| i * 3).collect(), (0..44).map(|i| 0xFF - i).collect()];
+    for (gb, block) in [&mut link.a, &mut link.b].into_iter().zip(&blocks) {
+        for (i, &b) in block.iter().enumerate() { gb.mmu.write(0xC000 + i as u16, b); }
+    }
+    for _ in 0..6 { link.run_frame(); }
+    let received = |gb: &GameBoy| (0..44).map(|i| gb.mmu.peek(0xC100 + i)).collect::<Vec<u8>>();
+    assert_eq!(received(&link.a), blocks[1]);
+    assert_eq!(received(&link.b), blocks[0]);
+    assert_eq!(link.a.cpu.regs.b, 0, "master still sending");
+}
+
+// Two headless cores in lockstep over loopback, one scanline of slack
+fn run_linked(rom: Vec<u8>, mut link: TcpLink) -> GameBoy {
+    link.window = 456;
+    let mut gb = GameBoy::new(rom);
+    gb.mmu.serial.connect(Box::new(link.endpoint()));
+    let mut cycles = 0u64;
+    while cycles < 70224 {
+        cycles += gb.run_for(456) as u64;
+        assert!(link.sync(cycles), "link partner stalled");
+    }
+    gb
+}
+
+#[test]
+fn tcp_link_exchanges_one_byte_each_way() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let host = thread::spawn(move || {
+        let gb = run_linked(link_master(), TcpLink::accept(listener).unwrap());
+        (gb.mmu.read(0xFF01), gb.mmu.read(0xFF02), gb.mmu.read(0xFF0F))
+    });
+    let slave = run_linked(link_slave(), TcpLink::connect(addr).unwrap());
+    let (sb, sc, iflag) = host.join().unwrap();
+
+    assert_eq!((sb, sc & 0x80, iflag & 0x08), (0x99, 0, 0x08));
+    assert_transfer_done(&slave, 0x42);
+}
+
+// Frames a printer packet: magic, header, data, checksum, then the two reply bytes
+fn printer_packet(command: u8, compressed: bool, data: &[u8]) -> Vec<u8> {
+    let mut body = vec![command, compressed as u8, data.len() as u8, (data.len() >> 8) as u8];
+    body.extend_from_slice(data);
+    let sum = body.iter().fold(0u16, |s, &b| s.wrapping_add(b as u16));
+    let mut packet = vec![0x88, 0x33];
+    packet.extend(body);
+    packet.extend([sum as u8, (sum >> 8) as u8, 0x00, 0x00]);
+    packet
+}
+
+#[test]
+fn tcp_link_stamps_transfers_and_caps_reply_waits() {
+    use pokegameboy::serial::{SerialEndpoint, FAST_BIT_PERIOD};
+    use std::time::{Duration, Instant};
+
+    // A hand-driven peer without the fast clock that never answers a TRANSFER
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let peer = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"\x00GBLK\x01\x00").unwrap();
+        stream.read_exact(&mut [0u8; 7]).unwrap();
+        stream
+    });
+    let link = TcpLink::connect(addr).unwrap();
+    let mut peer = peer.join().unwrap();
+    let mut port = link.endpoint();
+    assert!(!link.peer_supports_fast_clock());
+
+    // The fast clock goes out at the normal rate, stamped with the cycle the
+    // transfer started at rather than the last sync, and the second wait in the
+    // same frame finds the budget spent
+    assert!(link.sync(1000));
+    let start = Instant::now();
+    assert_eq!((port.exchange_at(0x42, FAST_BIT_PERIOD, 1200), port.exchange_at(0x43, FAST_BIT_PERIOD, 1300)), (0xFF, 0xFF));
+    assert!(start.elapsed() < Duration::from_millis(200), "waited {:?}", start.elapsed());
+    let mut sent = [0u8; 9 + 11 + 11];
+    peer.read_exact(&mut sent).unwrap();
+    assert_eq!(sent[9..20], [0x01, 0xB0, 0x04, 0, 0, 0, 0, 0, 0, 0x42, 0x00]);
+
+    // A byte the peer clocked at cycle 5000 waits until this side gets there
+    assert_eq!(port.poll_external(0x99), None);
+    peer.write_all(&[0x01, 0x88, 0x13, 0, 0, 0, 0, 0, 0, 0x55, 0x01]).unwrap();
+    let mut reply = [0u8; 2];
+    peer.read_exact(&mut reply).unwrap();
+    assert_eq!(reply, [0x02, 0x99]);
+    assert_eq!(port.poll_external(0x99), None);
+    assert!(link.sync(5000));
+    assert_eq!(port.poll_external(0x99), Some((0x55, FAST_BIT_PERIOD)));
+}
+
+#[test]
+fn printer_prints_one_rle_band() {
+    use pokegameboy::printer::Printer;
+    use pokegameboy::serial::SerialEndpoint;
+
+    let mut printer = Printer::new();
+    let strips = printer.strips();
+    let mut send = |packet: Vec<u8>| -> (u8, u8) {
+        let replies: Vec<u8> = packet.iter().map(|&b| printer.exchange(b)).collect();
+        (replies[replies.len() - 2], replies[replies.len() - 1])
+    };
+
+    assert_eq!(send(printer_packet(0x01, false, &[])), (0x81, 0x00));
+    // One all-black band (640 bytes of 0xFF): four 129-byte runs, a 122-byte run, two literals
+    let mut rle = [0xFF, 0xFF].repeat(4);
+    rle.extend([0xFA, 0xFF, 0x01, 0xFF, 0xFF]);
+    assert_eq!(send(printer_packet(0x04, true, &rle)).1, 0x08);
+    assert_eq!(send(printer_packet(0x04, false, &[])).1, 0x08);
+    assert_eq!(send(printer_packet(0x02, false, &[1, 0x10, 0xE4, 0x40])).1 & 0x02, 0x02);
+
+    // A corrupted checksum is reported and ignored
+    let mut bad = printer_packet(0x0F, false, &[]);
+    bad[6] ^= 1;
+    assert_eq!(send(bad).1 & 0x01, 0x01);
+
+    let strips = strips.borrow();
+    assert_eq!(strips.len(), 1);
+    let strip = &strips[0];
+    assert_eq!((strip.width, strip.height), (160, 32));
+    assert!(strip.pixels[..160 * 16].iter().all(|&p| p == 0xFF), "top margin not blank");
+    assert!(strip.pixels[160 * 16..].iter().all(|&p| p == 0x00));
+}
+
+#[test]
+fn printer_reports_each_file_it_writes() {
+    use pokegameboy::printer::Printer;
+    use pokegameboy::serial::SerialEndpoint;
+
+    let dir = std::env::temp_dir().join(format!("pokegb-printer-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (out, ok) in [(dir.clone(), true), (dir.join("missing"), false)] {
+        let mut printer = Printer::saving_to(&out);
+        let saved = printer.saved();
+        for packet in [printer_packet(0x04, false, &[0; 640]), printer_packet(0x02, false, &[0, 0, 0xE4, 0x40])] {
+            packet.iter().for_each(|&b| { printer.exchange(b); });
+        }
+        let saved = saved.borrow();
+        assert_eq!(saved.len(), 1);
+        assert_eq!((saved[0].0.clone(), saved[0].1.is_ok()), (out.join("print-0001.pgm"), ok));
+    }
+    assert!(std::fs::read(dir.join("print-0001.pgm")).unwrap().starts_with(b"P5\n160 16\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bindings_override_defaults_and_reject_typos() {
+    use minifb::Key;
+    use pokegameboy::bindings::{Action, Bindings, Hotkey};
+    use pokegameboy::joypad::Buttons;
+
+    let b = Bindings::parse("[keys]\nA = \"a\"\nF5 = \"screenshot\"\n").unwrap();
+    assert!(b.keys.contains(&(Key::A, Action::Button(Buttons::A))));
+    assert!(b.keys.contains(&(Key::X, Action::Button(Buttons::A))), "defaults dropped");
+    assert_eq!(b.hotkey_keys(Hotkey::Screenshot).collect::<Vec<_>>(), [Key::F12, Key::F5]);
+    assert_eq!(b.hotkey_keys(Hotkey::Save).count(), 0);
+
+    let err = Bindings::parse("[keys]\nEntr = \"start\"\n").unwrap_err();
+    assert!(err.contains("unknown key \"Entr\""), "{}", err);
+    let err = Bindings::parse("[keys]\nEnter = \"strat\"\n").unwrap_err();
+    assert!(err.contains("unknown action") && err.contains("start"), "{}", err);
+    assert!(Bindings::parse("[kyes]\n").is_err());
+}
+
+#[test]
+fn turbo_and_macros_expand_per_frame() {
+    use pokegameboy::joypad::{Buttons, InputMacro, InputStage};
+
+    let mut input = InputStage::new();
+    input.set_turbo_period(Buttons::A, 2);
+    input.set_held(Buttons::LEFT);
+    input.set_turbo(Buttons::A);
+    let frames: Vec<Buttons> = (0..6).map(|_| input.next_frame()).collect();
+    let a = Buttons::A | Buttons::LEFT;
+    assert_eq!(frames, [a, a, Buttons::LEFT, Buttons::LEFT, a, a]);
+
+    input.set_held(Buttons::empty());
+    input.set_turbo(Buttons::empty());
+    input.play(InputMacro::parse("up*2, wait, a+b").unwrap());
+    let frames: Vec<Buttons> = (0..5).map(|_| input.next_frame()).collect();
+    assert_eq!(frames, [Buttons::UP, Buttons::UP, Buttons::empty(), Buttons::A | Buttons::B, Buttons::empty()]);
+    assert!(!input.is_playing());
+
+    assert!(InputMacro::parse("up*x").is_err());
+    assert!(InputMacro::parse("jump").unwrap_err().contains("unknown button"));
+}
+
+#[test]
+fn joypad_wires_both_groups_and_interrupts_on_falling_lines() {
+    use pokegameboy::joypad::Buttons;
+    use pokegameboy::mmu::Mmu;
+
+    let mut gb = GameBoy::new(rom_with(&[0x18, 0xFE]));
+    let mmu = &mut gb.mmu;
+    // Right and B share no line; Right and A share P10
+    mmu.set_buttons(Buttons::RIGHT | Buttons::B);
+    let reads = [0x00, 0x20, 0x10, 0x30].map(|select| { mmu.write(0xFF00, select); mmu.read(0xFF00) });
+    assert_eq!(reads, [0xCC, 0xEE, 0xDD, 0xFF]);
+    mmu.set_buttons(Buttons::RIGHT | Buttons::A);
+    mmu.write(0xFF00, 0x00);
+    assert_eq!(mmu.read(0xFF00), 0xCE);
+
+    // Whether a JOYP select write, or a new set of held buttons, raises the interrupt
+    fn irq(mmu: &mut Mmu, change: impl FnOnce(&mut Mmu)) -> bool {
+        mmu.write(0xFF0F, 0x00);
+        change(mmu);
+        mmu.read(0xFF0F) & 0x10 != 0
+    }
+    let select = |val: u8| move |mmu: &mut Mmu| mmu.write(0xFF00, val);
+    let hold = |held: Buttons| move |mmu: &mut Mmu| mmu.set_buttons(held);
+    mmu.write(0xFF00, 0x30);
+    mmu.set_buttons(Buttons::RIGHT);
+    assert!(irq(mmu, select(0x20)), "selecting the d-pad pulls P10 low");
+    assert!(!irq(mmu, select(0x20)), "same selection, no edge");
+    assert!(!irq(mmu, select(0x00)), "A is not held, P10 already low");
+    assert!(!irq(mmu, hold(Buttons::RIGHT | Buttons::A)), "P10 already low from Right");
+    assert!(irq(mmu, hold(Buttons::RIGHT | Buttons::A | Buttons::DOWN)));
+    assert!(!irq(mmu, hold(Buttons::empty())), "releasing is a rising edge");
+    mmu.write(0xFF00, 0x20);
+    assert!(!irq(mmu, hold(Buttons::START)), "buttons not selected");
+    assert!(irq(mmu, select(0x10)), "selecting the buttons with Start held");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gamepad_reads_native_input_events_and_reports_disconnects() {
+    use pokegameboy::bindings::{Action, GamepadConfig, Hotkey};
+    use pokegameboy::gamepad::Gamepad;
+    use pokegameboy::joypad::Buttons;
+
+    // struct input_event { struct timeval time; u16 type; u16 code; i32 value; }
+    let event = |kind: u16, code: u16, value: i32| {
+        let mut ev = vec![0xEE; 2 * size_of::<std::os::raw::c_long>()];
+        ev.extend(kind.to_ne_bytes());
+        ev.extend(code.to_ne_bytes());
+        ev.extend(value.to_ne_bytes());
+        ev
+    };
+    // BTN_SOUTH = A, BTN_START = pause, hat left; a plain file reads as a device that then goes away
+    let path = std::env::temp_dir().join(format!("pokegb-gamepad-{}", std::process::id()));
+    std::fs::write(&path, [event(0x01, 0x130, 1), event(0x01, 0x13B, 1), event(0x03, 0x10, -1)].concat()).unwrap();
+    let config = GamepadConfig {
+        device: path.to_string_lossy().into_owned(),
+        buttons: vec![(0x130, Action::Button(Buttons::A)), (0x13B, Action::Hotkey(Hotkey::Pause))],
+    };
+    let mut pad = Gamepad::open(&config).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(pad.is_connected());
+    assert_eq!(pad.poll(), [Action::Hotkey(Hotkey::Pause)]);
+    assert!(!pad.is_connected());
+    assert_eq!(pad.buttons(), Buttons::empty(), "released on disconnect");
+    assert!(pad.poll().is_empty());
+}
+
+#[test]
+fn frames_end_at_vblank_without_losing_cycles() {
+    // Halted with no interrupts enabled: the CPU only ever skips to the next event
+    let mut gb = GameBoy::new(rom_with(&[0xF3, 0x76, 0x18, 0xFD])); // DI; HALT; JR -3
+    gb.run_frame();
+    let start = gb.mmu.scheduler.now();
+    for _ in 0..3 {
+        assert_eq!(gb.run_frame(), 70224);
+        assert_eq!(gb.mmu.read(0xFF44), 144);
+    }
+    assert_eq!(gb.mmu.scheduler.now() - start, 3 * 70224);
+}
+
+// Calls a subroutine at 0x0200 that increments B, then stores A to C001 forever
+fn debug_rom() -> Vec<u8> {
+    let mut rom = rom_with(&[
+        0x3E, 0x3F,       // 0100 LD A,0x3F
+        0x21, 0x00, 0xC0, // 0102 LD HL,C000
+        0x36, 0x06,       // 0105 LD (HL),6
+        0xCD, 0x00, 0x02, // 0107 CALL 0200
+        0xEA, 0x01, 0xC0, // 010A LD (C001),A
+        0x18, 0xFE,       // 010D JR -2
+    ]);
+    rom[0x200..0x202].copy_from_slice(&[0x04, 0xC9]); // INC B; RET
+    rom
+}
+
+#[test]
+fn debugger_breaks_steps_and_watches() {
+    let mut gb = GameBoy::new(debug_rom());
+    let mut dbg = Debugger::new();
+    dbg.break_at(Location::new(0x0105), Some(Expr::parse("A == 0").unwrap()));
+    let bp = dbg.break_at(Location::parse("00:0107").unwrap(), Some(Expr::parse("A == 0x3F && [HL] > 5").unwrap()));
+    assert_eq!(dbg.run(&mut gb, 1), Some(Stop::Breakpoint(bp)));
+    assert_eq!(gb.cpu.regs.pc, 0x0107);
+
+    let b = gb.cpu.regs.b;
+    dbg.step_over(&gb);
+    assert_eq!(dbg.run(&mut gb, 1), Some(Stop::Step));
+    assert_eq!((gb.cpu.regs.pc, gb.cpu.regs.b), (0x010A, b.wrapping_add(1)));
+
+    let wp = dbg.watch(0xC001, WatchKind::Write, None);
+    assert_eq!(dbg.run(&mut gb, 1), Some(Stop::Watchpoint { id: wp, addr: 0xC001, old: 0x00, new: 0x3F }));
+    assert_eq!(gb.cpu.regs.pc, 0x010D);
+
+    // Step into the subroutine from a fresh start, then back out of it
+    let mut gb = GameBoy::new(debug_rom());
+    let mut dbg = Debugger::new();
+    dbg.run_to(Location::new(0x0200));
+    assert_eq!(dbg.run(&mut gb, 1), Some(Stop::Step));
+    dbg.step_out(&gb);
+    assert_eq!(dbg.run(&mut gb, 1), Some(Stop::Step));
+    assert_eq!(gb.cpu.regs.pc, 0x010A);
+    assert!(Expr::parse("A ==").is_err());
+}
+
+#[test]
+fn rendering_does_not_trip_read_watchpoints() {
+    // Background and sprite both draw tile 0 for ~7 frames, then the CPU reads it
+    let mut gb = GameBoy::new(rom_with(&[
+        0x0E, 0x80,             // LD C,$80
+        0x05, 0x20, 0xFD,       // DEC B; JR NZ,-3
+        0x0D, 0x20, 0xFA,       // DEC C; JR NZ,-6
+        0xFA, 0x00, 0x80,       // LD A,($8000)
+        0x18, 0xFE,
+    ]));
+    gb.mmu.write(0xFF40, 0x93);
+    gb.mmu.write(0xFE00, 16);
+    gb.mmu.write(0xFE01, 8);
+    let mut dbg = Debugger::new();
+    let wp = dbg.watch(0x8000, WatchKind::Read, None);
+    assert_eq!(dbg.run(&mut gb, 2), None);
+    assert!(matches!(dbg.run(&mut gb, 10), Some(Stop::Watchpoint { id, addr: 0x8000, .. }) if id == wp));
+    assert_eq!(gb.cpu.regs.pc, 0x010B);
+}
+
+#[test]
+fn reads_while_stopped_are_not_watchpoint_hits() {
+    let mut gb = GameBoy::new(debug_rom());
+    let mut dbg = Debugger::new();
+    let bp = dbg.break_at(Location::new(0x0107), None);
+    dbg.watch(0xC000, WatchKind::Read, None);
+    assert_eq!(dbg.run(&mut gb, 1), Some(Stop::Breakpoint(bp)));
+    // A frontend reading through the bus while stopped must not stop the next run
+    gb.mmu.read(0xC000);
+    assert_eq!(dbg.run(&mut gb, 1), None);
+}
+
+#[test]
+fn repl_drives_the_debugger() {
+    let mut gb = GameBoy::new(debug_rom());
+    let mut repl = Repl::new();
+    let run = |repl: &mut Repl, gb: &mut GameBoy, line: &str| {
+        let mut out = String::new();
+        repl.command(gb, line, &mut out).unwrap();
+        out
+    };
+    assert_eq!(run(&mut repl, &mut gb, "break 0107 if A == 0x3F"), "Breakpoint 1 at 0107\n");
+    run(&mut repl, &mut gb, "c");
+    assert!(repl.running());
+    assert_eq!(repl.run_frame(&mut gb).unwrap(), "Breakpoint 1\n0107: CALL $0200");
+    assert!(run(&mut repl, &mut gb, "disasm 0107 2").ends_with("CD 00 02  CALL $0200\n   010A: EA 01 C0  LD ($C001),A\n"));
+
+    run(&mut repl, &mut gb, "next");
+    assert_eq!(repl.run_frame(&mut gb).unwrap(), "010A: LD ($C001),A");
+    run(&mut repl, &mut gb, "set A=0x40");
+    assert_eq!(run(&mut repl, &mut gb, "p A + 1"), "65 ($41)\n");
+    assert_eq!(run(&mut repl, &mut gb, "x/4 C000"), "C000: 06 00 00 00\n");
+    run(&mut repl, &mut gb, "step");
+    repl.run_frame(&mut gb).unwrap();
+    assert_eq!(run(&mut repl, &mut gb, "x/2 (HL)"), "C000: 06 40\n");
+    assert_eq!(repl.history().len(), 9);
+    assert!(repl.command(&mut gb, "frobnicate", &mut String::new()).is_err());
+}
+
+// Minimal RSP client: sends a packet and returns the next packet that comes back
+fn rsp(stream: &mut TcpStream, packet: &str) -> String {
+    let sum = packet.bytes().fold(0u8, |a, b| a.wrapping_add(b));
+    write!(stream, "${packet}#{sum:02x}").unwrap();
+    rsp_reply(stream)
+}
+
+fn rsp_reply(stream: &mut TcpStream) -> String {
+    let mut reply = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        match byte[0] {
+            b'+' if reply.is_empty() => {}
+            b'#' => break,
+            b'$' => reply.clear(),
+            b => reply.push(b),
+        }
+    }
+    let mut sum = [0u8; 2];
+    stream.read_exact(&mut sum).unwrap();
+    stream.write_all(b"+").unwrap();
+    String::from_utf8(reply).unwrap()
+}
+
+#[test]
+fn gdb_stub_answers_a_scripted_session() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let mut s = TcpStream::connect(addr).unwrap();
+        assert!(rsp(&mut s, "qSupported:swbreak+").contains("qXfer:features:read+"));
+        assert_eq!(rsp(&mut s, "?"), "S05");
+        assert!(rsp(&mut s, "qXfer:features:read:target.xml:0,fff").starts_with("l<?xml"));
+        assert!(rsp(&mut s, "g").ends_with("feff0001")); // SP=FFFE PC=0100
+        assert_eq!(rsp(&mut s, "Z0,107,1"), "OK");
+        assert_eq!(rsp(&mut s, "c"), "T05swbreak:;");
+        assert_eq!(rsp(&mut s, "p5"), "0701");
+        assert_eq!(rsp(&mut s, "Mc000,2:aabb"), "OK");
+        assert_eq!(rsp(&mut s, "mc000,2"), "aabb");
+        assert_eq!(rsp(&mut s, "s"), "S05");
+        assert_eq!(rsp(&mut s, "p5"), "0002");
+        assert_eq!(rsp(&mut s, "z0,107,1"), "OK");
+        assert_eq!(rsp(&mut s, "Z2,c001,1"), "OK");
+        assert_eq!(rsp(&mut s, "c"), "T05watch:c001;");
+        let bank: String = "bank".bytes().map(|b| format!("{b:02x}")).collect();
+        assert!(rsp(&mut s, &format!("qRcmd,{bank}")).starts_with('O'));
+        assert_eq!(rsp_reply(&mut s), "OK");
+        assert_eq!(rsp(&mut s, "D"), "OK");
+    });
+
+    let mut gb = GameBoy::new(debug_rom());
+    let mut stub = GdbStub::accept(&listener).unwrap();
+    while stub.is_connected() && !client.is_finished() {
+        stub.poll(&mut gb);
+        stub.run_frame(&mut gb);
+    }
+    client.join().unwrap();
+}
+
+#[test]
+fn symbols_name_addresses_everywhere() {
+    let syms = Symbols::parse("; debug_rom\n00:0100 Start\n00:0107 Start.call\n00:0200 Sub\n00:C001 wResult\n01:4000 Banked\n").unwrap();
+    assert_eq!(syms.nearest(1, 0x4010), Some(("Banked", 0x10)));
+    assert_eq!(syms.nearest(2, 0x4010), None);
+    assert!(Symbols::parse("0100 Start").is_err());
+
+    let mut gb = GameBoy::new(debug_rom());
+    let mut repl = Repl::new();
+    repl.symbols = syms;
+    let run = |repl: &mut Repl, gb: &mut GameBoy, line: &str| {
+        let mut out = String::new();
+        repl.command(gb, line, &mut out).unwrap();
+        out
+    };
+    assert_eq!(run(&mut repl, &mut gb, "break Sub if [wResult] == 0"), "Breakpoint 1 at 00:0200\n");
+    run(&mut repl, &mut gb, "c");
+    assert_eq!(repl.run_frame(&mut gb).unwrap(), "Breakpoint 1\nSub: INC B");
+    assert_eq!(run(&mut repl, &mut gb, "bt"), "#0 Sub\n#1 Start.call+3 (SP=FFFC)\n");
+    assert!(run(&mut repl, &mut gb, "disasm Start.call 1").starts_with("Start.call:\n   0107: CD 00 02  CALL Sub\n"));
+    assert_eq!(run(&mut repl, &mut gb, "trace 2"), "Sub: INC B\nSub+1: RET\n");
+}
+
+// 0100 calls 0200, which calls 0300, which "jumps" to 0310 with PUSH + RET
+fn nested_call_rom() -> Vec<u8> {
+    let mut rom = rom_with(&[0xCD, 0x00, 0x02, 0x18, 0xFB]);       // CALL 0200; JR 0100
+    rom[0x200..0x204].copy_from_slice(&[0xCD, 0x00, 0x03, 0xC9]);  // CALL 0300; RET
+    rom[0x300..0x305].copy_from_slice(&[0x21, 0x10, 0x03, 0xE5, 0xC9]); // LD HL,0310; PUSH HL; RET
+    rom[0x310] = 0xC9;                                               // RET
+    rom
+}
+
+#[test]
+fn shadow_stack_and_profiler_follow_calls() {
+    let mut gb = GameBoy::new(nested_call_rom());
+    let mut dbg = Debugger::new();
+    dbg.run_to(Location::new(0x0310));
+    assert_eq!(dbg.run(&mut gb, 1), Some(Stop::Step));
+    let rets: Vec<(u16, u16)> = dbg.stack.frames().iter().map(|f| (f.entry, f.ret)).collect();
+    assert_eq!(rets, [(0x0200, 0x0103), (0x0300, 0x0203)]);
+    dbg.step_into();
+    dbg.run(&mut gb, 1);
+    assert_eq!(dbg.stack.frames().len(), 1);
+
+    // Per pass of the loop: CALL 0200 and JR in the root (24 + 12 cycles),
+    // CALL 0300 and RET in 0200 (24 + 16), LD, PUSH and both RETs in 0300 (12 + 16 * 3)
+    let profiler = profile_nested_calls(&mut gb, &mut dbg, Mode::Instrument);
+    assert_eq!(profiler.folded(&Symbols::new()), "(root) 108\n(root);0200 120\n(root);0200;0300 180\n");
+    let funcs = profiler.functions();
+    let (outer, inner) = (funcs[&Some((0, 0x0200))], funcs[&Some((0, 0x0300))]);
+    assert_eq!((outer.calls, outer.inclusive, outer.exclusive), (3, 300, 120));
+    assert_eq!((inner.calls, inner.inclusive, inner.exclusive), (3, 180, 180));
+    assert_eq!(profiler.total(), 408);
+}
+
+// Runs three passes of `nested_call_rom`'s loop from 0100 with a fresh profiler
+fn profile_nested_calls(gb: &mut GameBoy, dbg: &mut Debugger, mode: Mode) -> Profiler {
+    dbg.run_to(Location::new(0x0100));
+    assert_eq!(dbg.run(gb, 2), Some(Stop::Step));
+    dbg.profiler = Some(Profiler::new(mode));
+    for _ in 0..3 {
+        dbg.run_to(Location::new(0x0100));
+        assert_eq!(dbg.run(gb, 2), Some(Stop::Step));
+    }
+    dbg.profiler.take().unwrap()
+}
+
+#[test]
+fn sampling_profiler_counts_the_stack_at_each_sample() {
+    let mut gb = GameBoy::new(nested_call_rom());
+    let mut dbg = Debugger::new();
+    // 408 cycles hold 20 samples 20 cycles apart: 5 land in the root, 6 in 0200, 9 in 0300
+    let profiler = profile_nested_calls(&mut gb, &mut dbg, Mode::Sample { interval: 20 });
+    assert_eq!(profiler.folded(&Symbols::new()), "(root) 100\n(root);0200 120\n(root);0200;0300 180\n");
+    assert_eq!(profiler.total(), 408);
+    assert!(profiler.report(&Symbols::new(), 3).starts_with("408 cycles, sampled every 20\n"));
+}
+
+#[test]
+fn cdl_tags_code_data_and_jumps() {
+    let mut rom = rom_with(&[
+        0x21, 0x50, 0x01, // LD HL,0150
+        0x7E,             // LD A,(HL)
+        0xFA, 0x51, 0x01, // LD A,(0151)
+        0x21, 0x10, 0x02, // LD HL,0210
+        0xE9,             // JP HL
+    ]);
+    rom[0x210..0x212].copy_from_slice(&[0x18, 0xFE]); // JR 0210
+    let mut gb = GameBoy::new(rom);
+    gb.mmu.cdl = Some(Box::new(Cdl::new(gb.mmu.rom_len(), gb.mmu.extram.len())));
+    gb.run_frame();
+    let cdl = gb.mmu.cdl.take().unwrap();
+    assert_eq!(cdl.flags(0x100), CODE | OPCODE | JUMP_TARGET);
+    assert_eq!(cdl.flags(0x101), CODE);
+    assert_eq!(cdl.flags(0x103), CODE | OPCODE);
+    assert_eq!(cdl.flags(0x150), DATA | INDIRECT_DATA);
+    assert_eq!(cdl.flags(0x151), DATA);
+    assert_eq!(cdl.flags(0x210), CODE | OPCODE | JUMP_TARGET | INDIRECT_CODE);
+    assert_eq!(cdl.flags(0x211), CODE);
+    let banks = cdl.coverage();
+    assert_eq!((banks[0].code, banks[0].data, banks[1].unused), (13, 2, 0x4000));
+
+    // Sessions merge; BizHawk files keep opcode/operand/data
+    let bizhawk = Cdl::decode(&cdl.encode(Format::BizHawk)).unwrap();
+    assert_eq!((bizhawk.format, bizhawk.flags(0x100), bizhawk.flags(0x101)), (Format::BizHawk, CODE | OPCODE, CODE));
+    let mut merged = Cdl::decode(&cdl.encode(Format::Fceux)).unwrap();
+    assert!(merged.merge(&bizhawk).is_ok() && merged.flags(0x210) == cdl.flags(0x210));
+    assert!(merged.merge(&Cdl::new(0x4000, 0)).is_err());
+}
+
+#[test]
+fn bizhawk_cdl_has_every_gambatte_block() {
+    // The layout BizHawk's CodeDataLog.Save writes for a GB game with 8KB of
+    // cartridge RAM: .NET length-prefixed strings, then little-endian i32
+    // block count and sizes
+    let mut expected = b"\x0DBIZHAWK-CDL-2\x0FGB             \x04\x00\x00\x00".to_vec();
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x102].copy_from_slice(&[0x01, 0x02]); // ExecFirst, ExecOperand
+    for (name, block) in [("ROM", rom), ("HRAM", vec![0; 0x80]), ("WRAM", vec![0; 0x8000]), ("CartRAM", vec![0; 0x2000])] {
+        expected.push(name.len() as u8);
+        expected.extend(name.as_bytes());
+        expected.extend((block.len() as i32).to_le_bytes());
+        expected.extend(block);
+    }
+
+    let cdl = Cdl::new(0x8000, 0x2000);
+    cdl.note_fetch(Some(0x100), 0x100, 0x18);
+    cdl.note_fetch(Some(0x101), 0x101, 0xFE);
+    assert_eq!(cdl.encode(Format::BizHawk), expected);
+
+    // RAM flags BizHawk logged (code run from WRAM C010) come back out unchanged
+    let wram = 34 + 5 + 4 + 0x8000 + 5 + 4 + 0x80 + 5 + 4;
+    expected[wram + 0x10] = 0x01;
+    let loaded = Cdl::decode(&expected).unwrap();
+    assert_eq!((loaded.format, loaded.flags(0x100)), (Format::BizHawk, CODE | OPCODE));
+    assert_eq!(loaded.encode(Format::BizHawk), expected);
+    let mut merged = Cdl::new(0x8000, 0x2000);
+    merged.merge(&loaded).unwrap();
+    assert_eq!(merged.encode(Format::BizHawk), expected);
+    assert!(Cdl::new(0x8000, 0x4000).merge(&loaded).unwrap_err().contains("CartRAM"));
+}
+
+#[test]
+fn ram_search_narrows_and_freezes() {
+    let mut gb = GameBoy::new(rom_with(&[0x21, 0x00, 0xC1, 0x34, 0x18, 0xFD])); // LD HL,C100; INC (HL); JR
+    for (i, b) in [0x12, 0x34, 0x56].into_iter().enumerate() { gb.mmu.write(0xC200 + i as u16, b); }
+
+    let mut search = RamSearch::new(&gb.mmu, ValueType::parse("bcd24be").unwrap());
+    assert!(search.candidates().len() > 1000);
+    assert_eq!(search.filter(&gb.mmu, Compare::Equal, Some(123456)), 1);
+    gb.mmu.write(0xC202, 0x57);
+    assert_eq!(search.filter(&gb.mmu, Compare::Greater, None), 1);
+    let c = search.candidates()[0];
+    assert_eq!((c.addr, c.value, c.previous, c.changes), (0xC200, 123457, 123456, 1));
+    assert_eq!(ValueType::parse("u16").unwrap().read(&gb.mmu, 0xC201), Some(0x5734));
+    assert_eq!(ValueType::parse("bcd16").unwrap().encode(1234), Ok(vec![0x34, 0x12]));
+    assert!(ValueType::U8.encode(256).is_err());
+
+    // The counter the CPU keeps incrementing stays put while frozen
+    let mut search = RamSearch::new(&gb.mmu, ValueType::U8);
+    gb.run_frame();
+    search.filter(&gb.mmu, Compare::Changed, None);
+    assert!(search.candidates().iter().any(|c| c.addr == 0xC100));
+    ValueType::U8.freeze(&mut gb.mmu, 0xC100, 0x42).unwrap();
+    gb.run_frame();
+    assert_eq!(gb.mmu.peek(0xC100), 0x42);
+    assert_eq!(search.filter(&gb.mmu, Compare::Unchanged, None), 0);
+    ValueType::U8.unfreeze(&mut gb.mmu, 0xC100);
+    gb.run_frame();
+    assert_ne!(gb.mmu.peek(0xC100), 0x42);
+
+    let mut repl = Repl::new();
+    let mut out = String::new();
+    repl.command(&mut gb, "search start bcd24be", &mut out).unwrap();
+    repl.command(&mut gb, "search eq 123457", &mut out).unwrap();
+    assert!(out.contains("1 candidates") && out.contains("C200"), "{out}");
+}
+
+#[test]
+fn frozen_bytes_keep_their_bank() {
+    let mut mmu = Mmu::with_model(rom_with(&[]), vec![0; 0x8000], Model::Cgb);
+    mmu.write(0xFF70, 2);
+    mmu.freeze(0xD000, 0x42);
+    mmu.write(0x4000, 1);
+    mmu.freeze(0xA000, 0x24);
+
+    // Other banks stay writable, and switching back finds the frozen bytes intact
+    mmu.write(0xFF70, 3);
+    mmu.write(0x4000, 2);
+    mmu.write(0xD000, 0x11);
+    mmu.write(0xA000, 0x22);
+    assert!(!mmu.is_frozen(0xD000) && !mmu.is_frozen(0xA000));
+    mmu.apply_frozen();
+    assert_eq!((mmu.peek(0xD000), mmu.peek(0xA000)), (0x11, 0x22));
+    mmu.write(0xFF70, 2);
+    mmu.write(0x4000, 1);
+    assert_eq!((mmu.peek(0xD000), mmu.peek(0xA000)), (0x42, 0x24));
+
+    // Echo RAM is the same byte
+    mmu.write(0xF000, 0x99);
+    assert_eq!((mmu.peek(0xD000), mmu.peek(0xF000)), (0x42, 0x42));
+    mmu.freeze(0xE100, 0x55);
+    mmu.write(0xC100, 0x66);
+    assert_eq!(mmu.peek(0xC100), 0x55);
+    mmu.unfreeze(0xC100);
+    mmu.write(0xE100, 0x66);
+    assert_eq!(mmu.peek(0xC100), 0x66);
+}
+
+#[test]
+fn cheats_patch_rom_and_poke_ram() {
+    let mut rom = rom_with(&[0xFA, 0x50, 0x01, 0xEA, 0x00, 0xC0, 0x18, 0xF8]); // LD A,(0150); LD (C000),A; JR
+    rom[0x150] = 0x11;
+    let mut gb = GameBoy::new(rom);
+    assert_eq!(Code::parse("010138CD"), Ok(Code::GameShark { bank: None, addr: 0xCD38, value: 0x01 }));
+    assert_eq!(Code::parse("991-50F-AAE"), Ok(Code::GameGenie { addr: 0x0150, value: 0x99, compare: Some(0x11) }));
+    assert!(Code::parse("991-50F-AAF").is_ok() && Code::parse("12345").is_err() && Code::parse("02000000").is_err());
+
+    // A compare byte that doesn't match leaves the ROM alone
+    let wrong = gb.mmu.cheats.add("881-50F-AAF", "wrong compare").unwrap();
+    gb.mmu.cheats.add("991-50F-AAE", "patch").unwrap();
+    gb.mmu.cheats.add("0142D0C1", "poke").unwrap();
+    gb.run_frame();
+    assert_eq!((gb.mmu.peek(0xC000), gb.mmu.peek(0xC1D0)), (0x99, 0x42));
+
+    gb.mmu.cheats.set_active(false);
+    gb.mmu.write(0xC1D0, 0);
+    gb.run_frame();
+    assert_eq!((gb.mmu.peek(0xC000), gb.mmu.peek(0xC1D0)), (0x11, 0));
+    gb.mmu.cheats.set_active(true);
+    gb.mmu.cheats.set_enabled(wrong, false).unwrap();
+
+    // The database keeps other games' sections and this one's switches
+    let key = cheats::rom_key(gb.mmu.rom());
+    let db = gb.mmu.cheats.to_database("[ABCDEF] OTHER GAME\n+ 01FF00C0 Other\n", &key, "TEST");
+    assert!(db.starts_with("[ABCDEF] OTHER GAME\n+ 01FF00C0 Other\n\n"));
+    assert!(db.contains(&format!("[{key}] TEST\n- 881-50F-AAF wrong compare\n+ 991-50F-AAE patch\n")));
+    let loaded = Cheats::from_database(&db, &key).unwrap();
+    assert_eq!(loaded.list(), gb.mmu.cheats.list());
+    assert_eq!(Cheats::from_database(&db, "ABCDEF").unwrap().list().len(), 1);
+}
+
+#[test]
+fn viewers_render_vram_oam_and_palettes() {
+    let mut gb = GameBoy::new(rom_with(&[0x18, 0xFE]));
+    gb.mmu.write(0x8000, 0xFF); // tile 0, row 0: colour 1 throughout
+    for (reg, val) in [(0xFF47, 0xE4), (0xFF48, 0xE4), (0xFF42, 0), (0xFF43, 0)] { gb.mmu.write(reg, val); }
+    for (i, b) in [16, 8, 0, 0x20].into_iter().enumerate() { gb.mmu.write(0xFE00 + i as u16, b); }
+    let (white, light) = ([0xFF; 3], [0xAA; 3]);
+
+    let tiles = viewer::tiles(&gb.mmu, &gb.ppu, 0);
+    assert_eq!((tiles.width, tiles.height, tiles.rgba.len()), (128, 192, 128 * 192 * 4));
+    assert_eq!((tiles.get(0, 0), tiles.get(7, 0), tiles.get(0, 1)), (light, light, white));
+
+    let maps = viewer::tilemaps(&gb.mmu, &gb.ppu);
+    assert_eq!((maps.width, maps.height), (512, 256));
+    assert_eq!((maps.get(0, 0), maps.get(159, 143), maps.get(1, 1), maps.get(257, 0)), ([0xFF, 0x30, 0x30], [0xFF, 0x30, 0x30], white, light));
+
+    let oam = viewer::oam(&gb.mmu, &gb.ppu);
+    assert_eq!((oam.get(4, 4), oam.get(4, 5), oam.get(12, 4)), (light, [0x30, 0x30, 0x38], [0x30, 0x30, 0x38]));
+    assert!(viewer::oam_table(&gb.mmu).contains(" 0  16   8  $00  $20 OBP0     x  front\n"));
+
+    let pals = viewer::palettes(&gb.mmu, &gb.ppu);
+    assert_eq!((pals.height, pals.get(0, 0), pals.get(16, 0), pals.get(63, 47)), (48, white, light, [0; 3]));
+    assert!(viewer::palette_table(&gb.mmu).starts_with("BGP  $E4  ids 0-3 -> shades 0 1 2 3\n"));
+}
+
+#[test]
+fn raster_log_and_layer_switches() {
+    // SCX = 4 from line 64, back to 0 from line 100, every frame
+    let mut gb = GameBoy::new(rom_with(&[
+        0xF0, 0x44, 0xFE, 0x40, 0x20, 0xFA, 0x3E, 0x04, 0xE0, 0x43,
+        0xF0, 0x44, 0xFE, 0x64, 0x20, 0xFA, 0xAF, 0xE0, 0x43, 0x18, 0xEB,
+    ]));
+    gb.run_frame();
+    gb.run_frame();
+    let regs = &gb.ppu.line_regs;
+    assert_eq!((regs[63].scx, regs[64].scx, regs[99].scx, regs[100].scx), (0, 4, 4, 0));
+    let events: Vec<_> = gb.ppu.raster_events.iter().map(|e| (e.ly, e.addr, e.old, e.new)).collect();
+    assert_eq!(events, [(64, 0xFF43, 0, 4), (100, 0xFF43, 4, 0)]);
+    assert!(gb.ppu.raster_events.iter().all(|e| e.dot < 80));
+    let table = viewer::raster_table(&gb.ppu);
+    assert_eq!(table.lines().filter(|l| l.contains('$')).count(), 5);
+    assert!(table.contains(" 64   $91 $00 $04") && table.contains("SCX   $04 -> $00"));
+
+    // Sprite tile 1 (colour 1) at the top left over an empty background
+    gb.mmu.write(0x8010, 0xFF);
+    for (reg, val) in [(0xFF40, 0x93), (0xFF47, 0xE4), (0xFF48, 0xE4)] { gb.mmu.write(reg, val); }
+    for (i, b) in [16, 8, 1, 0].into_iter().enumerate() { gb.mmu.write(0xFE00 + i as u16, b); }
+    let top_left = |gb: &mut GameBoy| { gb.run_frame(); gb.ppu.framebuffer[..3].to_vec() };
+    assert_eq!(top_left(&mut gb), [0xAA; 3]);
+    gb.ppu.highlight_sprites = true;
+    assert_eq!(top_left(&mut gb), [0xD5, 0x55, 0xD5]);
+    *gb.ppu.layer("sprites").unwrap() = false;
+    assert_eq!(top_left(&mut gb), [0xFF; 3]);
+    gb.mmu.write(0x8000, 0xFF); // BG tile 0 now colour 1 too
+    assert_eq!(top_left(&mut gb), [0xAA; 3]);
+    gb.ppu.show_bg = false;
+    assert_eq!(top_left(&mut gb), [0xFF; 3]);
+    assert!(gb.ppu.layer("sky").is_err());
+}
+
+// --- OAM DMA ---
+
+// An Mmu with 0x00.. in VRAM and 0x80.. in WRAM to copy from. DMA runs lazily,
+// so `sync_dma` brings OAM up to date before it is inspected directly.
+fn dma_mmu() -> Mmu {
+    let mut mmu = Mmu::new(rom_with(&[]), vec![0u8; 0x8000]);
+    for i in 0..0xA0u16 {
+        mmu.write(0x8000 + i, i as u8);
+        mmu.write(0xC000 + i, 0x80 | i as u8);
+    }
+    mmu
+}
+
+#[test]
+fn oam_dma_starts_two_m_cycles_after_the_write() {
+    let mut mmu = dma_mmu();
+    mmu.oam[0] = 0x55;
+    mmu.write(0xFF46, 0xC0);
+
+    mmu.tick();
+    assert_eq!(mmu.read(0xFE00), 0x55, "OAM locked before the first byte");
+    mmu.tick();
+    mmu.sync_dma();
+    assert_eq!(mmu.oam[0], 0x80);
+    assert_eq!(mmu.read(0xFE00), 0xFF);
+}
+
+#[test]
+fn oam_reads_ff_until_the_last_byte_is_copied() {
+    let mut mmu = dma_mmu();
+    mmu.write(0xFF46, 0x80);
+    for _ in 0..1 + 0x9F { mmu.tick(); }
+    assert_eq!(mmu.read(0xFE9F), 0xFF);
+    mmu.write(0xFE00, 0x12);
+    mmu.sync_dma();
+    assert_eq!(mmu.oam[0], 0x00, "OAM write went through during DMA");
+
+    mmu.tick();
+    assert_eq!(mmu.read(0xFE9F), 0x9F);
+    assert_eq!(mmu.read(0xFE10), 0x10);
+}
+
+#[test]
+fn oam_dma_owns_the_bus_it_reads_from() {
+    let mut mmu = dma_mmu();
+    mmu.write(0xFF46, 0xC0);
+    for _ in 0..5 { mmu.tick(); }
+
+    // ROM and WRAM share the external bus with the WRAM source, VRAM and HRAM do not
+    assert_eq!(mmu.read(0x0150), 0x83);
+    assert_eq!(mmu.read(0xC050), 0x83);
+    assert_eq!(mmu.read(0x8010), 0x10);
+    mmu.write(0xFF80, 0x77);
+    assert_eq!(mmu.read(0xFF80), 0x77);
+    mmu.write(0xC050, 0x00);
+    for _ in 0..0xA0 { mmu.tick(); }
+    assert_eq!(mmu.read(0xC050), 0xD0, "WRAM write went through during DMA");
+}
+
+#[test]
+fn oam_dma_restart_keeps_oam_locked_until_the_new_copy() {
+    let mut mmu = dma_mmu();
+    mmu.write(0xFF46, 0xC0);
+    for _ in 0..12 { mmu.tick(); }
+    mmu.write(0xFF46, 0x80);
+
+    // The old transfer keeps running through the new one's startup delay
+    mmu.tick();
+    mmu.sync_dma();
+    assert_eq!(mmu.oam[11], 0x8B);
+    assert_eq!(mmu.read(0xFE00), 0xFF);
+    mmu.tick();
+    mmu.sync_dma();
+    assert_eq!(mmu.oam[0], 0x00);
+    assert_eq!(mmu.oam[12], 0x00, "old transfer ran past the restart");
+    for _ in 0..0x9F { mmu.tick(); }
+    mmu.sync_dma();
+    assert!((0..0xA0).all(|i| mmu.oam[i] == i as u8));
+}
+
+#[test]
+fn cpu_sees_oam_dma_at_each_bus_access() {
+    // LDH (DMA),A then LD A,(HL) from HRAM: the read lands on the first copied byte
+    let mut gb = GameBoy { mmu: dma_mmu(), ..GameBoy::with_model(rom_with(&[]), Model::Dmg) };
+    for (i, &b) in [0xE0, 0x46, 0x7E].iter().enumerate() { gb.mmu.write(0xFF80 + i as u16, b); }
+    gb.cpu.regs.pc = 0xFF80;
+    gb.cpu.regs.a = 0x80;
+    gb.cpu.regs.set_hl(0xFE00);
+
+    assert_eq!(gb.step(), 12);
+    assert_eq!(gb.step(), 8);
+    assert_eq!(gb.cpu.regs.a, 0xFF);
+    gb.mmu.sync_dma();
+    assert_eq!(gb.mmu.oam[0], 0x00);
+}
+
+// --- Mooneye acceptance ROMs (cargo test -- --ignored with MOONEYE_DIR set) ---
+
+// Runs one ROM until it executes LD B,B, the suite's end marker, and checks for
+// the pass signature B-L = 3, 5, 8, 13, 21, 34
+fn mooneye(dir: &str, name: &str) -> Result<(), String> {
+    let rom = std::fs::read(format!("{dir}/{name}")).map_err(|e| format!("{name}: {e}"))?;
+    let mut gb = GameBoy::with_model(rom, Model::Dmg);
+    let mut cycles = 0u64;
+    while cycles < 30 * 4_194_304 {
+        if !gb.cpu.halted && gb.mmu.read(gb.cpu.regs.pc) == 0x40 {
+            let r = &gb.cpu.regs;
+            return match [r.b, r.c, r.d, r.e, r.h, r.l] {
+                [3, 5, 8, 13, 21, 34] => Ok(()),
+                regs => Err(format!("{name}: failed with B-L = {regs:?}")),
+            };
+        }
+        cycles += gb.step() as u64;
+    }
+    Err(format!("{name}: timed out"))
+}
+
+fn mooneye_suite(names: &[&str]) {
+    let dir = std::env::var("MOONEYE_DIR").expect("MOONEYE_DIR must point at a built mooneye-test-suite");
+    let failures: Vec<String> = names.iter().filter_map(|n| mooneye(&dir, n).err()).collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "needs MOONEYE_DIR"]
+fn mooneye_oam_dma() {
+    mooneye_suite(&[
+        "acceptance/oam_dma_restart.gb", "acceptance/oam_dma_start.gb", "acceptance/oam_dma_timing.gb",
+        "acceptance/oam_dma/basic.gb", "acceptance/oam_dma/reg_read.gb",
+    ]);
+}
+
+// --- Rendering regressions ---
+
+// A DMG scene with scrolling, the window, every sprite attribute and a line over the sprite limit
+fn dmg_scene(mut write: impl FnMut(u16, u8)) {
+    for i in 0..32 * 16u16 { write(0x8000 + i, (i.wrapping_mul(37) ^ (i >> 4).wrapping_mul(11)) as u8); }
+    for i in 0..1024u16 {
+        write(0x9800 + i, (i * 7 % 32) as u8);
+        write(0x9C00 + i, ((i * 3 + 5) % 32) as u8);
+    }
+    for i in 0..12u16 {
+        let s = [16 + (i * 9 % 140) as u8, 8 + (i * 23 % 160) as u8, i as u8, (i as u8 % 16) << 4];
+        for (j, b) in s.into_iter().enumerate() { write(0xFE00 + i * 4 + j as u16, b); }
+    }
+    for i in 12..24u16 { // twelve on line 40: only ten are drawn
+        let s = [56, 8 + ((i - 12) * 12) as u8, (i % 32) as u8, 0];
+        for (j, b) in s.into_iter().enumerate() { write(0xFE00 + i * 4 + j as u16, b); }
+    }
+    for (reg, val) in [(0xFF42, 27), (0xFF43, 13), (0xFF4A, 100), (0xFF4B, 87), (0xFF47, 0xE4), (0xFF48, 0xD2), (0xFF49, 0x1B), (0xFF40, 0xF3)] {
+        write(reg, val);
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |h, &b| (h ^ b as u64).wrapping_mul(0x100_0000_01B3))
+}
+
+#[test]
+fn dmg_frame_matches_the_pre_cgb_renderer() {
+    let mut gb = GameBoy::with_model(rom_with(&[0x18, 0xFE]), Model::Dmg);
+    dmg_scene(|addr, val| gb.mmu.write(addr, val));
+    gb.run_frame();
+    gb.run_frame();
+    // Hash of the same scene rendered by the DMG-only PPU this core started from
+    assert_eq!(fnv1a(&gb.ppu.framebuffer), 0xE472_AFFC_765D_C3CA);
+}
+
+#[test]
+fn cgb_renders_banks_attributes_and_palette_ram() {
+    let mut gb = GameBoy::with_model(rom_with(&[0x18, 0xFE]), Model::Cgb);
+    let mmu = &mut gb.mmu;
+    // Bank 0 tile 1: colour 1 at the top left, colour 3 at the bottom right; tile 2 row 0 all colour 3
+    for (addr, val) in [(0x8010, 0x80), (0x801E, 0x01), (0x801F, 0x01), (0x8020, 0xFF), (0x8021, 0xFF)] { mmu.write(addr, val); }
+    for i in 0..4 { mmu.write(0x9800 + i, 1); }
+    mmu.write(0xFF4F, 1);
+    assert_eq!(mmu.read(0xFF4F), 0xFF);
+    mmu.write(0x8010, 0xFF); // bank 1 tile 1 row 0: colour 1 throughout
+    // Attributes: x-flip with palette 2, y-flip, bank 1 with palette 1, BG-over-OBJ priority
+    for (i, attr) in [0x22, 0x40, 0x09, 0x80].into_iter().enumerate() { mmu.write(0x9800 + i as u16, attr); }
+    mmu.write(0xFF4F, 0);
+    assert_eq!(mmu.read(0x8010), 0x80);
+
+    // Palette RAM through the auto-incrementing index
+    let bg: [u16; 12] = [0x7FFF, 0x001F, 0x03E0, 0x7C00, 0x0000, 0x5294, 0x0000, 0x0000, 0x0000, 0x7C1F, 0x0000, 0x0000];
+    mmu.write(0xFF68, 0x80);
+    for c in bg { mmu.write(0xFF69, c as u8); mmu.write(0xFF69, (c >> 8) as u8); }
+    assert_eq!(mmu.read(0xFF68) & 0x3F, 24);
+    mmu.write(0xFF6A, 0x80 | 6);
+    mmu.write(0xFF6B, 0xFF);
+    mmu.write(0xFF6B, 0x03);
+    for (i, b) in [16, 32, 2, 0].into_iter().enumerate() { mmu.write(0xFE00 + i as u16, b); }
+    for (reg, val) in [(0xFF42, 0), (0xFF43, 0), (0xFF40, 0x93)] { mmu.write(reg, val); }
+    gb.run_frame();
+    gb.run_frame();
+
+    let px = |x: usize| { let i = x * 4; [gb.ppu.framebuffer[i], gb.ppu.framebuffer[i + 1], gb.ppu.framebuffer[i + 2]] };
+    assert_eq!((px(0), px(7)), (bgr555_to_rgb(0x0000), bgr555_to_rgb(0x7C1F))); // x-flipped, palette 2
+    assert_eq!((px(8), px(15)), (bgr555_to_rgb(0x7FFF), bgr555_to_rgb(0x7C00))); // y-flipped: row 7
+    assert_eq!(px(16), bgr555_to_rgb(0x5294));                                   // bank 1, palette 1 colour 1
+    assert_eq!((px(24), px(25)), (bgr555_to_rgb(0x001F), bgr555_to_rgb(0x03FF))); // BG colour 1 beats the sprite, colour 0 doesn't
+}
+
+#[test]
+fn general_purpose_hdma_stalls_the_cpu() {
+    // LD A,$03; LDH ($55),A: four blocks from C000 to 8000
+    let mut gb = GameBoy::with_model(rom_with(&[0x3E, 0x03, 0xE0, 0x55, 0x18, 0xFE]), Model::Cgb);
+    for (reg, val) in [(0xFF51, 0xC0), (0xFF52, 0x00), (0xFF53, 0x00), (0xFF54, 0x00)] { gb.mmu.write(reg, val); }
+    gb.mmu.write(0xC03F, 0x5A);
+    gb.step();
+    assert_eq!(gb.step(), 12 + 4 * 32);
+    assert_eq!((gb.mmu.read(0x803F), gb.mmu.read(0xFF55)), (0x5A, 0xFF));
+}
+
+// --- Super Game Boy ---
+
+// Bit-bangs a command through JOYP: reset pulse, 128 bits LSB first (P15 low = 1,
+// P14 low = 0) and a 0 stop bit per 16-byte packet
+fn sgb_send(gb: &mut GameBoy, command: &[u8]) {
+    for packet in command.chunks(16) {
+        let mut bytes = [0u8; 16];
+        bytes[..packet.len()].copy_from_slice(packet);
+        gb.mmu.write(0xFF00, 0x00);
+        gb.mmu.write(0xFF00, 0x30);
+        let bits = (0..128).map(|i| bytes[i / 8] >> (i % 8) & 1).chain([0]);
+        for bit in bits {
+            gb.mmu.write(0xFF00, if bit == 1 { 0x10 } else { 0x20 });
+            gb.mmu.write(0xFF00, 0x30);
+        }
+    }
+}
+
+// Puts `data` where a *_TRN transfer picks it up (tiles 00-FF laid out on the BG
+// map) and lets the next VBlank latch it
+fn sgb_transfer(gb: &mut GameBoy, command: &[u8], data: &[u8]) {
+    sgb_send(gb, command);
+    for i in 0..256u16 { gb.mmu.write(0x9800 + i / 20 * 32 + i % 20, i as u8); }
+    for i in 0..0x1000u16 { gb.mmu.write(0x8000 + i, data.get(i as usize).copied().unwrap_or(0)); }
+    gb.run_frame();
+    assert!(gb.mmu.sgb.as_ref().unwrap().pending.is_none());
+}
+
+fn sgb() -> GameBoy {
+    GameBoy::with_model(rom_with(&[0x18, 0xFE]), Model::Sgb)
+}
+
+#[test]
+fn sgb_palette_and_attribute_packets() {
+    let mut gb = sgb();
+    // PAL01: shared colour 0, then three colours each for palettes 0 and 1
+    let colors: [u16; 7] = [0x0001, 0x0002, 0x0003, 0x0004, 0x0005, 0x0006, 0x7FFF];
+    let mut pal01 = vec![0x01];
+    colors.iter().for_each(|c| pal01.extend(c.to_le_bytes()));
+    sgb_send(&mut gb, &pal01);
+    let s = gb.mmu.sgb.as_ref().unwrap();
+    assert_eq!(s.palettes[0], [0x0001, 0x0002, 0x0003, 0x0004]);
+    assert_eq!(s.palettes[1], [0x0001, 0x0005, 0x0006, 0x7FFF]);
+    assert_eq!(s.palettes[3][0], 0x0001);
+
+    // ATTR_BLK over two packets: palette 1 inside and 2 on the border of (2,3)-(6,8),
+    // then an outside-only set and an inside-only set
+    sgb_send(&mut gb, &[
+        0x22, 3,
+        0x03, 0x09, 2, 3, 6, 8,
+        0x04, 0x30, 0, 0, 10, 17,
+        0x01, 0x01, 12, 1, 14, 2,
+    ]);
+    let attr = |gb: &GameBoy, x: usize, y: usize| gb.mmu.sgb.as_ref().unwrap().attrs[y * 20 + x];
+    assert_eq!((attr(&gb, 4, 5), attr(&gb, 2, 3), attr(&gb, 6, 5)), (1, 2, 2));
+    assert_eq!((attr(&gb, 15, 0), attr(&gb, 5, 0), attr(&gb, 8, 9), attr(&gb, 13, 1)), (3, 3, 0, 1)); // the border takes the outside palette
+
+    // ATTR_LIN: row 10 palette 3; ATTR_DIV: left of column 4 palette 1, right palette 2
+    sgb_send(&mut gb, &[0x29, 1, 0x80 | 3 << 5 | 10]);
+    assert!((0..20).all(|x| attr(&gb, x, 10) == 3));
+    sgb_send(&mut gb, &[0x31, 0x06, 4]);
+    assert_eq!((attr(&gb, 3, 0), attr(&gb, 4, 0), attr(&gb, 5, 17)), (1, 0, 2));
+}
+
+#[test]
+fn sgb_mlt_req_cycles_players() {
+    let mut gb = sgb();
+    assert_eq!(gb.mmu.read(0xFF00) & 0x0F, 0x0F);
+    sgb_send(&mut gb, &[0x89, 0x03]); // four players
+    let mut ids = Vec::new();
+    for _ in 0..5 {
+        gb.mmu.write(0xFF00, 0x10); // P15 low, then high: next controller
+        gb.mmu.write(0xFF00, 0x30);
+        ids.push(gb.mmu.read(0xFF00) & 0x0F);
+    }
+    assert_eq!(ids, [0x0E, 0x0D, 0x0C, 0x0F, 0x0E]);
+    sgb_send(&mut gb, &[0x89, 0x00]);
+    assert_eq!(gb.mmu.read(0xFF00) & 0x0F, 0x0F);
+}
+
+#[test]
+fn sgb_vram_transfers_fill_palettes_and_border() {
+    let mut gb = sgb();
+    // PAL_TRN: system palette 5 = 1111..4444, then PAL_SET picks 5, 1, 2, 3
+    let mut pals = vec![0u8; 0x1000];
+    for (c, v) in [0x1111u16, 0x2222, 0x3333, 0x4444].into_iter().enumerate() {
+        pals[5 * 8 + c * 2..5 * 8 + c * 2 + 2].copy_from_slice(&v.to_le_bytes());
+    }
+    pals[8 + 2..8 + 4].copy_from_slice(&0x0AAAu16.to_le_bytes());
+    sgb_transfer(&mut gb, &[0x59], &pals);
+    sgb_send(&mut gb, &[0x51, 5, 0, 1, 0, 2, 0, 3, 0, 0]);
+    let s = gb.mmu.sgb.as_ref().unwrap();
+    assert_eq!((s.palettes[0], s.palettes[1][..2].to_vec()), ([0x1111, 0x2222, 0x3333, 0x4444], vec![0x1111, 0x0AAA]));
+
+    // CHR_TRN: border tile 1 is colour 1 throughout; PCT_TRN: map entry 0 is tile 1
+    // in palette 4, whose colour 1 is blue
+    let mut tiles = vec![0u8; 0x1000];
+    (0..8).for_each(|row| tiles[32 + row * 2] = 0xFF);
+    sgb_transfer(&mut gb, &[0x99, 0x00], &tiles);
+    let mut map = vec![0u8; 0x1000];
+    map[..2].copy_from_slice(&(1u16 | 4 << 10).to_le_bytes());
+    map[0x802..0x804].copy_from_slice(&0x7C00u16.to_le_bytes());
+    sgb_transfer(&mut gb, &[0xA1], &map);
+
+    let pixels = gb.ppu.pixels;
+    let frame = gb.mmu.sgb.as_mut().unwrap().render(&pixels);
+    assert_eq!((frame[..3].to_vec(), frame[8 * 4..8 * 4 + 3].to_vec()), (bgr555_to_rgb(0x7C00).to_vec(), bgr555_to_rgb(0x1111).to_vec()));
+}
+
+#[test]
+fn post_boot_io_matches_pan_docs() {
+    // Pan Docs "Power Up Sequence", DMG/MGB, SGB and CGB columns; "??" entries left out
+    let common: [(u16, u8); 39] = [
+        (0xFF00, 0xCF), (0xFF01, 0x00), (0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8), (0xFF0F, 0xE1),
+        (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0xBF), (0xFF16, 0x3F),
+        (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF), (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F),
+        (0xFF1D, 0xFF), (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0xBF),
+        (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF40, 0x91), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF44, 0x00),
+        (0xFF45, 0x00), (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00),
+        (0xFF51, 0xFF), (0xFF52, 0xFF), (0xFF53, 0xFF), (0xFF54, 0xFF), (0xFF55, 0xFF),
+    ];
+    //                       SC    DIV         STAT        NR52  DMA   KEY1  VBK   RP    BCPS  OCPS  SVBK
+    let per_model = [
+        (Model::Dmg, 0x7E, Some(0xAB), Some(0x85), [0xF1, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]),
+        (Model::Sgb, 0x7E, None,       None,       [0xF0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]),
+        (Model::Cgb, 0x7F, None,       None,       [0xF1, 0x00, 0x7E, 0xFE, 0x3E, 0xC0, 0xC1, 0xF8]),
+    ];
+    for (model, sc, div, stat, [nr52, dma, key1, vbk, rp, bcps, ocps, svbk]) in per_model {
+        let mmu = Mmu::with_model(rom_with(&[0x18, 0xFE]), vec![0u8; 0x8000], model);
+        let mut expected = common.to_vec();
+        expected.extend([(0xFF02, sc), (0xFF26, nr52), (0xFF46, dma), (0xFF4D, key1), (0xFF4F, vbk), (0xFF56, rp)]);
+        expected.extend([(0xFF68, bcps), (0xFF6A, ocps), (0xFF70, svbk)]);
+        expected.extend(div.map(|v| (0xFF04, v)));
+        expected.extend(stat.map(|v| (0xFF41, v)));
+        for (addr, val) in expected {
+            assert_eq!(mmu.read(addr), val, "{model:?} {addr:04X}");
+        }
+    }
+}
+
+// --- Timer ---
+
+#[test]
+fn timer_sees_each_access_at_its_own_m_cycle() {
+    // TAC = 262144 Hz: TIMA ticks when the counter crosses a multiple of 16.
+    // Reset DIV, zero TIMA 12 cycles later, then read TIMA 28 cycles after the
+    // reset with LDH (before the second tick) and 32 after with LD A,(nn) (after it).
+    let mut gb = GameBoy::with_model(rom_with(&[
+        0x3E, 0x05, 0xE0, 0x07,             // LD A,$05; LDH ($07),A
+        0xAF, 0xE0, 0x04, 0xE0, 0x05, 0x00, // XOR A; LDH ($04),A; LDH ($05),A; NOP
+        0xF0, 0x05, 0x47,                   // LDH A,($05); LD B,A
+        0xAF, 0xE0, 0x04, 0xE0, 0x05, 0x00, // XOR A; LDH ($04),A; LDH ($05),A; NOP
+        0xFA, 0x05, 0xFF, 0x4F,             // LD A,($FF05); LD C,A
+        0x18, 0xFE,
+    ]), Model::Dmg);
+    for _ in 0..14 { gb.step(); }
+    assert_eq!((gb.cpu.regs.b, gb.cpu.regs.c), (1, 2));
+}
+
+// TIMA = FF with TAC = 262144 Hz from a zeroed counter: it overflows on the fourth M-cycle
+fn overflowing_timer() -> Timer {
+    let mut timer = Timer::new();
+    timer.write(0xFF07, 0x05);
+    timer.write(0xFF06, 0x80);
+    timer.write(0xFF05, 0xFF);
+    for _ in 0..4 { assert!(!timer.tick(4)); }
+    timer
+}
+
+#[test]
+fn tima_reads_zero_for_one_m_cycle_before_the_reload() {
+    let mut timer = overflowing_timer();
+    assert_eq!(timer.read(0xFF05), 0x00);
+    assert!(timer.tick(4), "no interrupt on the reload");
+    assert_eq!(timer.read(0xFF05), 0x80);
+
+    // Writing TIMA while it reads zero cancels both the reload and the interrupt
+    let mut timer = overflowing_timer();
+    timer.write(0xFF05, 0x33);
+    assert!(!timer.tick(4));
+    assert_eq!(timer.read(0xFF05), 0x33);
+}
+
+#[test]
+fn tima_ignores_writes_on_the_reload_m_cycle() {
+    let mut timer = overflowing_timer();
+    timer.tick(4);
+    timer.write(0xFF05, 0x44);
+    assert_eq!(timer.read(0xFF05), 0x80);
+    timer.write(0xFF06, 0x55); // TMA still goes through, and into TIMA
+    assert_eq!(timer.read(0xFF05), 0x55);
+
+    timer.tick(4);
+    timer.write(0xFF05, 0x66);
+    assert_eq!(timer.read(0xFF05), 0x66);
+}
+
+#[test]
+fn div_write_ticks_tima_on_a_falling_edge() {
+    let mut timer = Timer::new();
+    timer.write(0xFF07, 0x05); // TIMA watches counter bit 3
+    timer.tick(4);
+    timer.write(0xFF04, 0x00); // bit 3 low: no edge
+    assert_eq!((timer.read(0xFF05), timer.read(0xFF04)), (0, 0));
+
+    timer.tick(4);
+    timer.tick(4);
+    timer.write(0xFF04, 0x12); // bit 3 high: resetting the counter drops it
+    assert_eq!((timer.read(0xFF05), timer.counter()), (1, 0));
+
+    // With the timer off the same reset does nothing
+    timer.write(0xFF07, 0x01);
+    timer.tick(4);
+    timer.tick(4);
+    timer.write(0xFF04, 0x00);
+    assert_eq!(timer.read(0xFF05), 1);
+}
+
+#[test]
+#[ignore = "needs MOONEYE_DIR"]
+fn mooneye_timer() {
+    mooneye_suite(&[
+        "acceptance/timer/div_write.gb", "acceptance/timer/rapid_toggle.gb",
+        "acceptance/timer/tim00.gb", "acceptance/timer/tim00_div_trigger.gb",
+        "acceptance/timer/tim01.gb", "acceptance/timer/tim01_div_trigger.gb",
+        "acceptance/timer/tim10.gb", "acceptance/timer/tim10_div_trigger.gb",
+        "acceptance/timer/tim11.gb", "acceptance/timer/tim11_div_trigger.gb",
+        "acceptance/timer/tima_reload.gb", "acceptance/timer/tima_write_reloading.gb",
+        "acceptance/timer/tma_write_reloading.gb",
+    ]);
+}
+
+// --- Serial ---
+
+#[test]
+fn serial_master_shifts_in_ones_with_nothing_plugged_in() {
+    // LD A,$3C; LDH (SB),A; LD A,sc; LDH (SC),A; JR -2. With no partner every
+    // bit shifted in is 1; SB, SC and IF are checked against the clock after each loop.
+    for (model, sc, period) in [(Model::Dmg, 0x81, 512), (Model::Cgb, 0x81, 512), (Model::Cgb, 0x83, 16)] {
+        let mut gb = GameBoy::with_model(rom_with(&[0x3E, 0x3C, 0xE0, 0x01, 0x3E, sc, 0xE0, 0x02, 0x18, 0xFE]), model);
+        gb.mmu.write(0xFF0F, 0x00);
+        for _ in 0..4 { gb.step(); }
+        let mut elapsed = 0;
+        while elapsed < 9 * period {
+            let bits = (elapsed / period).min(8);
+            let sb = (0x3Cu16 << bits | ((1 << bits) - 1)) as u8;
+            let done = bits == 8;
+            assert_eq!(gb.mmu.read(0xFF01), sb, "{model:?} SC={sc:02X} after {bits} bits");
+            assert_eq!(gb.mmu.read(0xFF02) & 0x80 == 0, done, "{model:?} SC={sc:02X} after {bits} bits");
+            assert_eq!(gb.mmu.read(0xFF0F) & 0x08 != 0, done, "{model:?} SC={sc:02X} after {bits} bits");
+            elapsed += gb.step();
+        }
+    }
+}
+
+// --- Pokémon Cable Club trade, run with --ignored when POKEMON_ROM, POKEMON_SAV_A and POKEMON_SAV_B are set ---
+
+// Gen 1 party in the battery save (SRAM bank 1): count, species list, then 44-byte
+// party structs with the original trainer's ID at offset 0x0C
+const PARTY: usize = 0x2F2C;
+
+fn party_mon(sav: &[u8], slot: usize) -> (u8, u16) {
+    let mon = PARTY + 8 + slot * 44;
+    (sav[mon], u16::from_be_bytes([sav[mon + 0x0C], sav[mon + 0x0D]]))
+}
+
+// Species and OT ID of the last party slot, where a traded-in Pokémon lands
+fn newest_party_mon(gb: &GameBoy) -> (u8, u16) {
+    let sav = gb.mmu.get_save_data();
+    party_mon(&sav, sav[PARTY] as usize - 1)
+}
+
+#[test]
+#[ignore = "needs POKEMON_ROM, POKEMON_SAV_A and POKEMON_SAV_B"]
+fn pokemon_trade_swaps_the_first_party_slot() {
+    use pokegameboy::joypad::{InputMacro, InputStage};
+
+    // Both saves were made standing in front of the Cable Club attendant
+    // (Pokémon Center 2F), facing her. POKEMON_ROM_B runs side B on another
+    // version, e.g. Red against Blue.
+    let var = |name: &str| std::env::var(name).unwrap_or_else(|_| panic!("{name} must be set for the trade"));
+    let (rom, sav_a, sav_b) = (var("POKEMON_ROM"), var("POKEMON_SAV_A"), var("POKEMON_SAV_B"));
+    let rom_b = std::env::var("POKEMON_ROM_B").unwrap_or_else(|_| rom.clone());
+    let load = |rom: &str, sav: &str| {
+        let mut gb = GameBoy::new(std::fs::read(rom).unwrap_or_else(|e| panic!("{rom}: {e}")));
+        gb.mmu.load_save_data(std::fs::read(sav).unwrap_or_else(|e| panic!("{sav}: {e}")));
+        gb
+    };
+    let mut link = LinkCable::new(load(&rom, &sav_a), load(&rom_b, &sav_b));
+    let offered = [&link.a, &link.b].map(|gb| party_mon(&gb.mmu.get_save_data(), 0));
+    assert_ne!(offered[0], offered[1], "both saves lead with the same Pokémon, a trade would not show");
+
+    // Tapping A walks through everything on the default choice: the intro, the
+    // title screen, CONTINUE, the attendant's save prompt, the link handshake,
+    // TRADE CENTER, the table, the first party slot, TRADE and the confirmation.
+    // The game saves once the trade animation ends, so stop as soon as both
+    // saves hold the partner's Pokémon rather than picking another one to trade.
+    let tap = InputMacro::parse("a*2, wait*18").unwrap();
+    let mut input = InputStage::new();
+    for _ in 0..3 * 60 * 60 {
+        if !input.is_playing() { input.play(tap.clone()); }
+        let buttons = input.next_frame();
+        link.a.mmu.set_buttons(buttons);
+        link.b.mmu.set_buttons(buttons);
+        link.run_frame();
+        if newest_party_mon(&link.a) == offered[1] && newest_party_mon(&link.b) == offered[0] {
+            return;
+        }
+    }
+    panic!("no trade after three minutes: A ends with {:?}, B with {:?}, offered {:?}",
+        newest_party_mon(&link.a), newest_party_mon(&link.b), offered);
+}